@@ -1,10 +1,17 @@
+use std::fmt;
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum RespectExpr {
     Canstow,
     Maistow,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+/// The comment following declarations Quill adds itself (like the ancillas
+/// decomposition declares), so they can be told apart from the programmer's
+/// own, even after the program is written out and parsed again
+pub const GENERATED: &str = " generated by quill";
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum GateExpr {
     Q1Gate,
     Q1ParamGate,
@@ -14,7 +21,7 @@ pub enum GateExpr {
     QMultiGate,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum ValueExpr {
     QReg,
     Qubit,
@@ -22,6 +29,123 @@ pub enum ValueExpr {
     CReg,
 }
 
+/// A parsed Quill program, which is the list of its statements in source order
+#[derive(PartialEq, Debug, Clone)]
+pub struct Program {
+    pub stmts: Vec<Statement>,
+}
+
+/// A statement along with the (1-indexed) source line it starts on
+#[derive(PartialEq, Debug, Clone)]
+pub struct Statement {
+    pub line_no: i32,
+    pub kind: Stmt,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Stmt {
+    /// (Canstow / Maistow) create oo `ty` `name` with value `value`
+    Assign {
+        respect: RespectExpr,
+        ty: ValueExpr,
+        name: String,
+        value: Value,
+    },
+    /// Thy `gate` shalt target `targets` and control on `controls` with `params`,
+    /// where the gate name is kept as written (so aliases like cx are preserved)
+    /// and `kind` is the category the gate registry files it under
+    Gate {
+        gate: String,
+        kind: GateExpr,
+        targets: Vec<Target>,
+        controls: Vec<Target>,
+        params: Vec<Param>,
+    },
+    /// Rede `measured` and quyken `recipient`
+    Measure { measured: Target, recipient: Target },
+    /// Return `shots`
+    Return { shots: i32 },
+    /// A comment, holding its text after the "//"
+    Comment(String),
+    /// Fecche "`path`", which makes another file's gates and constants
+    /// available under its name
+    Import { path: String },
+    /// Devyse `name` on `qubits`, followed by its body up to Ende. The body
+    /// holds only gate applications (of either kind) and comments.
+    GateDef {
+        name: String,
+        qubits: Vec<String>,
+        body: Vec<Statement>,
+    },
+    /// Certes `name` with value `value`
+    Constant { name: String, value: Param },
+    /// Thy `gate` shalt target `targets` with `params`, for a gate defined with
+    /// Devyse (here, or in an imported file if the name is like "bell.pair"),
+    /// or a built-in composite like qft. Only composites take parameters.
+    /// Programs only hold these until their modules are linked.
+    Call {
+        gate: String,
+        targets: Vec<Target>,
+        params: Vec<Param>,
+    },
+}
+
+/// The value a variable is created with. Registers are made of one or more
+/// segments of (initial value, length), joined with " + " in the source, as in
+/// `0[4] + +[3]`
+#[derive(PartialEq, Debug, Clone)]
+pub enum Value {
+    Qubit(String),
+    CBit(i32),
+    QReg(Vec<(String, usize)>),
+    CReg(Vec<(i32, usize)>),
+}
+
+/// Something a gate or measurement acts on
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Target {
+    /// A whole variable, like `q1`
+    Name(String),
+    /// One element (where `start == end`) or an inclusive range of elements of a
+    /// register, like `qr[2]` or `qr[0..3]`
+    Slice {
+        name: String,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl Target {
+    pub fn name(&self) -> &str {
+        match self {
+            Target::Name(name) | Target::Slice { name, .. } => name,
+        }
+    }
+}
+
+/// A parameter to a parameterized gate
+#[derive(PartialEq, Debug, Clone)]
+pub enum Param {
+    Int(i32),
+    Float(f64),
+    /// PI * numerator / denominator, written as PI, PI[i], or PI[i, j]
+    PI(i32, i32),
+    /// A constant declared with Certes, like `quarter` or `angles.quarter`,
+    /// which is replaced by its value when the program's modules are linked
+    Constant(String),
+}
+
+impl Param {
+    pub fn value(&self) -> f64 {
+        match *self {
+            Param::Int(val) => val as f64,
+            Param::Float(val) => val,
+            Param::PI(num, den) => std::f64::consts::PI * (num as f64) / (den as f64),
+            Param::Constant(ref name) => unreachable!("{} should have been linked", name),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum NodeKind {
     // Question: Do we want to add data / fields to the NodeKind enum variants?
@@ -49,10 +173,16 @@ pub enum NodeKind {
     ValueType(ValueExpr),
     GateType(GateExpr),
     RespectType(RespectExpr),
-    COMMENT,
+    COMMENT(String),
+    Import(String),
+    GateDefinition(String),
+    Constant(String),
+    Call(String),
     EOI,
 }
 
+/// The generic node tree, which is kept as a debugging view of a `Program`
+/// (see `Program::to_tree`) rather than something passes should work with
 #[derive(PartialEq, Debug, Clone)]
 pub struct ASTNode {
     pub children: Option<Vec<ASTNode>>,
@@ -91,17 +221,353 @@ pub struct ASTNode {
 impl ASTNode {
     pub fn new(children: Option<Vec<ASTNode>>, node_kind: NodeKind) -> ASTNode {
         ASTNode {
-            children: children,
-            node_kind: node_kind,
+            children,
+            node_kind,
         }
     }
 
+    fn leaf(node_kind: NodeKind) -> ASTNode {
+        ASTNode::new(None, node_kind)
+    }
+
     pub fn print_nodes(node: &ASTNode, depth: usize) {
         println!("{}{:?}", String::from("    ").repeat(depth), node.node_kind);
-        for child in &node.children {
-            for c in child {
-                ASTNode::print_nodes(&c, depth + 1)
+        if let Some(children) = &node.children {
+            for c in children {
+                ASTNode::print_nodes(c, depth + 1)
             }
         }
     }
 }
+
+impl Program {
+    /// Builds the generic node tree for this program, which is only meant as a
+    /// debugging view (see `ASTNode::print_nodes`)
+    pub fn to_tree(&self) -> ASTNode {
+        let mut children: Vec<ASTNode> =
+            self.stmts.iter().map(|stmt| stmt.kind.to_tree()).collect();
+        children.push(ASTNode::leaf(NodeKind::EOI));
+        ASTNode::new(Some(children), NodeKind::Program)
+    }
+
+    /// Whether the statement at `index` is a declaration Quill generated,
+    /// rather than one the programmer wrote
+    pub fn is_generated(&self, index: usize) -> bool {
+        matches!(self.stmts[index].kind, Stmt::Assign { .. })
+            && matches!(
+                self.stmts.get(index + 1).map(|stmt| &stmt.kind),
+                Some(Stmt::Comment(text)) if text.trim_end() == GENERATED
+            )
+    }
+}
+
+impl Stmt {
+    fn to_tree(&self) -> ASTNode {
+        match self {
+            Stmt::Assign {
+                respect,
+                ty,
+                name,
+                value,
+            } => ASTNode::new(
+                Some(vec![
+                    ASTNode::leaf(NodeKind::RespectType(*respect)),
+                    ASTNode::leaf(NodeKind::ValueType(*ty)),
+                    ASTNode::leaf(NodeKind::Name(name.clone())),
+                    value.to_tree(),
+                ]),
+                NodeKind::Assignment,
+            ),
+            Stmt::Gate {
+                gate,
+                kind,
+                targets,
+                controls,
+                params,
+            } => {
+                let mut children = vec![
+                    ASTNode::leaf(NodeKind::Name(gate.clone())),
+                    ASTNode::leaf(NodeKind::GateType(*kind)),
+                ];
+                children.extend(
+                    targets
+                        .iter()
+                        .map(|target| target.to_tree(NodeKind::QRegSlice)),
+                );
+                if !controls.is_empty() {
+                    let controls = controls
+                        .iter()
+                        .map(|control| control.to_tree(NodeKind::QRegSlice))
+                        .collect();
+                    children.push(ASTNode::new(Some(controls), NodeKind::ControlList));
+                }
+                if !params.is_empty() {
+                    let params = params.iter().map(|param| param.to_tree()).collect();
+                    children.push(ASTNode::new(Some(params), NodeKind::ValList));
+                }
+                ASTNode::new(Some(children), NodeKind::GateApplication)
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => ASTNode::new(
+                Some(vec![
+                    measured.to_tree(NodeKind::QRegSlice),
+                    recipient.to_tree(NodeKind::CRegSlice),
+                ]),
+                NodeKind::Measurement,
+            ),
+            Stmt::Return { shots } => ASTNode::new(
+                Some(vec![ASTNode::leaf(NodeKind::Int(*shots))]),
+                NodeKind::Return,
+            ),
+            Stmt::Comment(text) => ASTNode::leaf(NodeKind::COMMENT(text.clone())),
+            Stmt::Import { path } => ASTNode::leaf(NodeKind::Import(path.clone())),
+            Stmt::GateDef { name, qubits, body } => {
+                let mut children: Vec<ASTNode> = qubits
+                    .iter()
+                    .map(|qubit| ASTNode::leaf(NodeKind::Name(qubit.clone())))
+                    .collect();
+                children.extend(body.iter().map(|stmt| stmt.kind.to_tree()));
+                ASTNode::new(Some(children), NodeKind::GateDefinition(name.clone()))
+            }
+            Stmt::Constant { name, value } => ASTNode::new(
+                Some(vec![value.to_tree()]),
+                NodeKind::Constant(name.clone()),
+            ),
+            Stmt::Call {
+                gate,
+                targets,
+                params,
+            } => {
+                let mut children: Vec<ASTNode> = targets
+                    .iter()
+                    .map(|target| target.to_tree(NodeKind::QRegSlice))
+                    .collect();
+                if !params.is_empty() {
+                    let params = params.iter().map(|param| param.to_tree()).collect();
+                    children.push(ASTNode::new(Some(params), NodeKind::ValList));
+                }
+                ASTNode::new(Some(children), NodeKind::Call(gate.clone()))
+            }
+        }
+    }
+}
+
+impl Value {
+    fn to_tree(&self) -> ASTNode {
+        match self {
+            Value::Qubit(qubit) => ASTNode::leaf(NodeKind::Qubit(qubit.clone())),
+            Value::CBit(cbit) => ASTNode::leaf(NodeKind::CBit(*cbit)),
+            Value::QReg(segments) => {
+                let mut qregs: Vec<ASTNode> = segments
+                    .iter()
+                    .map(|(qubit, length)| {
+                        ASTNode::new(
+                            Some(vec![
+                                ASTNode::leaf(NodeKind::Qubit(qubit.clone())),
+                                ASTNode::leaf(NodeKind::Index(*length as i32)),
+                            ]),
+                            NodeKind::QReg,
+                        )
+                    })
+                    .collect();
+                if qregs.len() == 1 {
+                    qregs.remove(0)
+                } else {
+                    ASTNode::new(Some(qregs), NodeKind::QRegTensor)
+                }
+            }
+            Value::CReg(segments) => {
+                let mut cregs: Vec<ASTNode> = segments
+                    .iter()
+                    .map(|(cbit, length)| {
+                        ASTNode::new(
+                            Some(vec![
+                                ASTNode::leaf(NodeKind::CBit(*cbit)),
+                                ASTNode::leaf(NodeKind::Int(*length as i32)),
+                            ]),
+                            NodeKind::CReg,
+                        )
+                    })
+                    .collect();
+                if cregs.len() == 1 {
+                    cregs.remove(0)
+                } else {
+                    ASTNode::new(Some(cregs), NodeKind::CRegTensor)
+                }
+            }
+        }
+    }
+}
+
+impl Target {
+    /// Slices become a node of the given kind (QRegSlice or CRegSlice), since the
+    /// typed AST leaves it to the type checker to know which one a slice is
+    fn to_tree(&self, slice_kind: NodeKind) -> ASTNode {
+        match self {
+            Target::Name(name) => ASTNode::leaf(NodeKind::Name(name.clone())),
+            Target::Slice { name, start, end } => {
+                let mut indices = vec![ASTNode::leaf(NodeKind::Index(*start as i32))];
+                if start != end {
+                    indices.push(ASTNode::leaf(NodeKind::Index(*end as i32)));
+                }
+                ASTNode::new(
+                    Some(vec![
+                        ASTNode::leaf(NodeKind::Name(name.clone())),
+                        ASTNode::new(Some(indices), NodeKind::Indices),
+                    ]),
+                    slice_kind,
+                )
+            }
+        }
+    }
+}
+
+impl Param {
+    fn to_tree(&self) -> ASTNode {
+        ASTNode::leaf(match self {
+            Param::Int(val) => NodeKind::Int(*val),
+            Param::Float(val) => NodeKind::Float(*val),
+            Param::PI(..) => NodeKind::PI(self.value()),
+            Param::Constant(name) => NodeKind::Name(name.clone()),
+        })
+    }
+}
+
+// Printing the typed AST back out as Quill source. This keeps the statements
+// as they are; `formatter` is what lays a program out canonically.
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for stmt in &self.stmts {
+            writeln!(f, "{}", stmt.kind)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stmt::Assign {
+                respect,
+                ty,
+                name,
+                value,
+            } => {
+                let ty = match ty {
+                    ValueExpr::Qubit => "qubit",
+                    ValueExpr::QReg => "qreg",
+                    ValueExpr::CBit => "cbit",
+                    ValueExpr::CReg => "creg",
+                };
+                write!(
+                    f,
+                    "{:?} create oo {} {} with value {}",
+                    respect, ty, name, value
+                )
+            }
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                params,
+                ..
+            } => {
+                write!(f, "Thy {} shalt target {}", gate, join(targets, " and "))?;
+                if !controls.is_empty() {
+                    write!(f, " and control on {}", join(controls, ", "))?;
+                }
+                if !params.is_empty() {
+                    write!(f, " with [{}]", join(params, ", "))?;
+                }
+                Ok(())
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => write!(f, "Rede {} and quyken {}", measured, recipient),
+            Stmt::Return { shots } => write!(f, "Return {}", shots),
+            Stmt::Comment(text) => write!(f, "//{}", text),
+            Stmt::Import { path } => write!(f, "Fecche \"{}\"", path),
+            // The whole definition, over several lines
+            Stmt::GateDef { name, qubits, body } => {
+                writeln!(f, "Devyse {} on {}", name, qubits.join(", "))?;
+                for stmt in body {
+                    writeln!(f, "    {}", stmt.kind)?;
+                }
+                write!(f, "Ende")
+            }
+            Stmt::Constant { name, value } => write!(f, "Certes {} with value {}", name, value),
+            Stmt::Call {
+                gate,
+                targets,
+                params,
+            } => {
+                write!(f, "Thy {} shalt target {}", gate, join(targets, " and "))?;
+                if !params.is_empty() {
+                    write!(f, " with [{}]", join(params, ", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Qubit(qubit) => write!(f, "{}", qubit),
+            Value::CBit(cbit) => write!(f, "b{}", cbit),
+            Value::QReg(segments) => {
+                let segments: Vec<String> = segments
+                    .iter()
+                    .map(|(qubit, length)| format!("{}[{}]", qubit, length))
+                    .collect();
+                write!(f, "{}", segments.join(" + "))
+            }
+            Value::CReg(segments) => {
+                let segments: Vec<String> = segments
+                    .iter()
+                    .map(|(cbit, length)| format!("b{}[{}]", cbit, length))
+                    .collect();
+                write!(f, "{}", segments.join(" + "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Name(name) => write!(f, "{}", name),
+            Target::Slice { name, start, end } if start == end => {
+                write!(f, "{}[{}]", name, start)
+            }
+            Target::Slice { name, start, end } => write!(f, "{}[{}..{}]", name, start, end),
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Param::Int(val) => write!(f, "{}", val),
+            // The grammar needs a decimal point to tell a float from an int
+            Param::Float(val) if val.fract() == 0.0 => write!(f, "{:.1}", val),
+            Param::Float(val) => write!(f, "{}", val),
+            Param::PI(1, 1) => write!(f, "PI"),
+            Param::PI(num, 1) if num >= 0 => write!(f, "PI[{}]", num),
+            Param::PI(num, den) if num >= 0 && den > 0 => write!(f, "PI[{}, {}]", num, den),
+            // PI only takes non-negative indices, so anything else is written out as a float
+            Param::PI(..) => Param::Float(self.value()).fmt(f),
+            Param::Constant(ref name) => write!(f, "{}", name),
+        }
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T], separator: &str) -> String {
+    let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+    items.join(separator)
+}
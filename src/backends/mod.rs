@@ -0,0 +1,87 @@
+//! Code generation for the formats Quill compiles to. Every backend only knows
+//! a fixed set of gates (its `GATES`), so programs using anything else have to
+//! be brought down to those first, which `BasisTranslation` can do:
+//!
+//! ```ignore
+//! BasisTranslation::new(qasm::GATES)?.run(&mut program, &symbols);
+//! let source = qasm::emit(&program, &symbols)?;
+//! ```
+use crate::ast::{Program, Stmt, Target, Value};
+use crate::gates;
+use crate::type_checker::checked_range;
+use std::collections::HashMap;
+
+pub mod qasm;
+pub mod qasm3;
+pub mod qir;
+pub mod qiskit;
+
+/// Every (variable, index) a target covers, given the width of every variable
+/// at the statement the target is in
+pub(crate) fn elements<'a>(
+    target: &'a Target,
+    widths: &HashMap<String, usize>,
+    line_no: i32,
+) -> Result<Vec<(&'a str, usize)>, String> {
+    let (name, start, end) = checked_range(target, widths, line_no)?;
+    Ok((start..=end).map(|i| (name, i)).collect())
+}
+
+/// Every (variable, index) the given targets cover, one target after another
+pub(crate) fn all_elements<'a>(
+    targets: impl IntoIterator<Item = &'a Target>,
+    widths: &HashMap<String, usize>,
+    line_no: i32,
+) -> Result<Vec<(&'a str, usize)>, String> {
+    let mut all = vec![];
+    for target in targets {
+        all.extend(elements(target, widths, line_no)?);
+    }
+    Ok(all)
+}
+
+/// The gates that take a qubit from 0 to the given initial value
+pub(crate) fn preparation(qubit: &str) -> &'static [&'static str] {
+    match qubit {
+        "1" => &["x"],
+        "+" => &["h"],
+        "-" => &["x", "h"],
+        _ => &[],
+    }
+}
+
+/// The initial value of every element of an assignment, in order, as the
+/// qubit value ("0", "1", "+", "-") or the cbit value ("0", "1")
+pub(crate) fn initial_values(value: &Value) -> Vec<String> {
+    match value {
+        Value::Qubit(qubit) => vec![qubit.clone()],
+        Value::CBit(cbit) => vec![cbit.to_string()],
+        Value::QReg(segments) => segments
+            .iter()
+            .flat_map(|(qubit, length)| vec![qubit.clone(); *length])
+            .collect(),
+        Value::CReg(segments) => segments
+            .iter()
+            .flat_map(|(cbit, length)| vec![cbit.to_string(); *length])
+            .collect(),
+    }
+}
+
+/// Makes sure every gate the program applies is one the backend knows
+pub(crate) fn check_gates(
+    program: &Program,
+    supported: &[&str],
+    backend: &str,
+) -> Result<(), String> {
+    for stmt in &program.stmts {
+        if let Stmt::Gate { gate, .. } = &stmt.kind {
+            if !supported.contains(&gates::signature(gate).name) {
+                return Err(format!(
+                    "{}: {} isn't supported by {}, translate the program into a basis it supports first!",
+                    stmt.line_no, gate, backend
+                ));
+            }
+        }
+    }
+    Ok(())
+}
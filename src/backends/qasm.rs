@@ -0,0 +1,126 @@
+//! The OpenQASM 2.0 backend
+use super::{all_elements, check_gates, elements, initial_values, preparation};
+use crate::ast::{Param, Program, Stmt, ValueExpr};
+use crate::gates;
+use crate::type_checker::SymbolTable;
+use std::collections::{HashMap, HashSet};
+
+/// The gates of qelib1.inc that Quill has
+pub const GATES: &[&str] = &[
+    "h", "x", "y", "z", "s", "sadj", "t", "tadj", "sx", "sxadj", "rx", "ry", "rz", "u3", "cnot",
+    "cz", "swap", "rxx", "rzz", "toffoli",
+];
+
+/// The name qelib1.inc gives a gate from the registry
+pub(crate) fn qasm_name(gate: &str) -> &str {
+    match gate {
+        "sadj" => "sdg",
+        "tadj" => "tdg",
+        "sxadj" => "sxdg",
+        "cnot" => "cx",
+        "toffoli" => "ccx",
+        _ => gate,
+    }
+}
+
+/// Writes a parameter the way OpenQASM does, in terms of pi when it can
+pub(crate) fn qasm_param(param: &Param) -> String {
+    match *param {
+        Param::PI(0, _) => "0".to_string(),
+        Param::PI(1, 1) => "pi".to_string(),
+        Param::PI(num, 1) => format!("{}*pi", num),
+        Param::PI(1, den) => format!("pi/{}", den),
+        Param::PI(num, den) => format!("{}*pi/{}", num, den),
+        _ => param.to_string(),
+    }
+}
+
+/// Emits the program as OpenQASM 2.0. Every variable becomes a register (plain
+/// qubits and cbits of width 1) as wide as its widest declaration, single qubit
+/// gates over a range are written out once per qubit, and reassigning a qubit
+/// resets it. OpenQASM 2 has no way
+/// to start a cbit as 1 or to report the shot count, so those become comments.
+pub fn emit(program: &Program, symbols: &SymbolTable) -> Result<String, String> {
+    check_gates(program, GATES, "OpenQASM 2")?;
+    let mut lines = vec![
+        "OPENQASM 2.0;".to_string(),
+        "include \"qelib1.inc\";".to_string(),
+    ];
+    let mut declared: HashSet<&str> = HashSet::new();
+    // Widths as of the current statement, since a variable may be redeclared
+    let mut widths: HashMap<String, usize> = HashMap::new();
+    for stmt in &program.stmts {
+        let line_no = stmt.line_no;
+        match &stmt.kind {
+            Stmt::Assign {
+                ty, name, value, ..
+            } => {
+                let quantum = matches!(ty, ValueExpr::Qubit | ValueExpr::QReg);
+                let values = initial_values(value);
+                widths.insert(name.clone(), values.len());
+                if declared.insert(name) {
+                    let kind = if quantum { "qreg" } else { "creg" };
+                    lines.push(format!("{} {}[{}];", kind, name, symbols.sizes[name]));
+                } else if quantum {
+                    lines.extend((0..values.len()).map(|i| format!("reset {}[{}];", name, i)));
+                }
+                for (i, value) in values.iter().enumerate() {
+                    if quantum {
+                        lines.extend(
+                            preparation(value)
+                                .iter()
+                                .map(|gate| format!("{} {}[{}];", gate, name, i)),
+                        );
+                    } else if value == "1" {
+                        lines.push(format!(
+                            "// {}[{}] starts as 1, which OpenQASM 2 can't express",
+                            name, i
+                        ));
+                    }
+                }
+            }
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                params,
+                ..
+            } => {
+                let signature = gates::signature(gate);
+                let mut call = qasm_name(signature.name).to_string();
+                if !params.is_empty() {
+                    let params: Vec<String> = params.iter().map(qasm_param).collect();
+                    call = format!("{}({})", call, params.join(","));
+                }
+                if signature.qubits == 1 && controls.is_empty() {
+                    for (name, i) in elements(&targets[0], &widths, line_no)? {
+                        lines.push(format!("{} {}[{}];", call, name, i));
+                    }
+                    continue;
+                }
+                // Controls come first in qelib1.inc
+                let qubits: Vec<String> =
+                    all_elements(controls.iter().chain(targets), &widths, line_no)?
+                        .into_iter()
+                        .map(|(name, i)| format!("{}[{}]", name, i))
+                        .collect();
+                lines.push(format!("{} {};", call, qubits.join(",")));
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let pairs = elements(measured, &widths, line_no)?
+                    .into_iter()
+                    .zip(elements(recipient, &widths, line_no)?);
+                for ((qname, qi), (cname, ci)) in pairs {
+                    lines.push(format!("measure {}[{}] -> {}[{}];", qname, qi, cname, ci));
+                }
+            }
+            Stmt::Return { shots } => lines.push(format!("// shots: {}", shots)),
+            Stmt::Comment(_) => {}
+            _ => unreachable!("modules are linked before compiling"),
+        }
+    }
+    Ok(lines.join("\n") + "\n")
+}
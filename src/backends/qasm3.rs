@@ -0,0 +1,120 @@
+//! The OpenQASM 3 backend
+use super::qasm::{qasm_name, qasm_param};
+use super::{all_elements, check_gates, elements, initial_values, preparation};
+use crate::ast::{Program, Stmt, ValueExpr};
+use crate::gates;
+use crate::type_checker::SymbolTable;
+use std::collections::{HashMap, HashSet};
+
+/// The gates of stdgates.inc that Quill has
+pub const GATES: &[&str] = &[
+    "h", "x", "y", "z", "s", "sadj", "t", "tadj", "sx", "rx", "ry", "rz", "u3", "cnot", "cz",
+    "swap", "toffoli",
+];
+
+/// A bit string literal for the given cbit values, which OpenQASM 3 writes
+/// with index 0 last
+fn bit_string(values: &[String]) -> String {
+    let bits: String = values.iter().rev().map(String::as_str).collect();
+    format!("\"{}\"", bits)
+}
+
+/// Emits the program as OpenQASM 3. Every variable becomes a qubit or bit
+/// register (plain qubits and cbits of width 1) as wide as its widest
+/// declaration, single qubit gates over a range are written out once per qubit,
+/// and reassigning a qubit resets it.
+/// OpenQASM 3 has no way to give the shot count, so that becomes a comment.
+pub fn emit(program: &Program, symbols: &SymbolTable) -> Result<String, String> {
+    check_gates(program, GATES, "OpenQASM 3")?;
+    let mut lines = vec![
+        "OPENQASM 3.0;".to_string(),
+        "include \"stdgates.inc\";".to_string(),
+    ];
+    let mut declared: HashSet<&str> = HashSet::new();
+    // Widths as of the current statement, since a variable may be redeclared
+    let mut widths: HashMap<String, usize> = HashMap::new();
+    for stmt in &program.stmts {
+        let line_no = stmt.line_no;
+        match &stmt.kind {
+            Stmt::Assign {
+                ty, name, value, ..
+            } => {
+                let values = initial_values(value);
+                widths.insert(name.clone(), values.len());
+                let size = symbols.sizes[name];
+                if !matches!(ty, ValueExpr::Qubit | ValueExpr::QReg) {
+                    if declared.insert(name) {
+                        // Bits past this declaration's width start as 0
+                        let mut bits = values.clone();
+                        bits.resize(size, "0".to_string());
+                        lines.push(format!("bit[{}] {} = {};", size, name, bit_string(&bits)));
+                    } else if values.len() == size {
+                        lines.push(format!("{} = {};", name, bit_string(&values)));
+                    } else {
+                        lines.extend(
+                            values
+                                .iter()
+                                .enumerate()
+                                .map(|(i, value)| format!("{}[{}] = {};", name, i, value)),
+                        );
+                    }
+                    continue;
+                }
+                if declared.insert(name) {
+                    lines.push(format!("qubit[{}] {};", size, name));
+                } else {
+                    lines.push(format!("reset {};", name));
+                }
+                for (i, value) in values.iter().enumerate() {
+                    lines.extend(
+                        preparation(value)
+                            .iter()
+                            .map(|gate| format!("{} {}[{}];", gate, name, i)),
+                    );
+                }
+            }
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                params,
+                ..
+            } => {
+                let signature = gates::signature(gate);
+                let mut call = qasm_name(signature.name).to_string();
+                if !params.is_empty() {
+                    let params: Vec<String> = params.iter().map(qasm_param).collect();
+                    call = format!("{}({})", call, params.join(", "));
+                }
+                if signature.qubits == 1 && controls.is_empty() {
+                    for (name, i) in elements(&targets[0], &widths, line_no)? {
+                        lines.push(format!("{} {}[{}];", call, name, i));
+                    }
+                    continue;
+                }
+                // Controls come first in stdgates.inc
+                let qubits: Vec<String> =
+                    all_elements(controls.iter().chain(targets), &widths, line_no)?
+                        .into_iter()
+                        .map(|(name, i)| format!("{}[{}]", name, i))
+                        .collect();
+                lines.push(format!("{} {};", call, qubits.join(", ")));
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let pairs = elements(measured, &widths, line_no)?
+                    .into_iter()
+                    .zip(elements(recipient, &widths, line_no)?);
+                for ((qname, qi), (cname, ci)) in pairs {
+                    lines.push(format!("{}[{}] = measure {}[{}];", cname, ci, qname, qi));
+                }
+            }
+            Stmt::Return { shots } => lines.push(format!("// shots: {}", shots)),
+            Stmt::Comment(_) => {}
+            _ => unreachable!("modules are linked before compiling"),
+        }
+    }
+    Ok(lines.join("\n") + "\n")
+}
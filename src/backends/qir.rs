@@ -0,0 +1,262 @@
+//! The QIR backend, which emits LLVM IR following the QIR base profile
+use super::{all_elements, check_gates, elements, initial_values, preparation};
+use crate::ast::{Program, Stmt, ValueExpr};
+use crate::gates;
+use crate::type_checker::SymbolTable;
+use std::collections::{HashMap, HashSet};
+
+/// The gates the QIR quantum instruction set has that Quill does too
+pub const GATES: &[&str] = &[
+    "h", "x", "y", "z", "s", "sadj", "t", "tadj", "rx", "ry", "rz", "cnot", "cz", "swap", "rxx",
+    "ryy", "rzz", "toffoli",
+];
+
+/// The name of the QIS function for a gate from the registry
+fn qis_name(gate: &str) -> &str {
+    match gate {
+        "sadj" => "s__adj",
+        "tadj" => "t__adj",
+        "toffoli" => "ccx",
+        _ => gate,
+    }
+}
+
+fn qubit(index: usize) -> String {
+    match index {
+        0 => "%Qubit* null".to_string(),
+        _ => format!("%Qubit* inttoptr (i64 {} to %Qubit*)", index),
+    }
+}
+
+fn result(index: usize) -> String {
+    match index {
+        0 => "%Result* null".to_string(),
+        _ => format!("%Result* inttoptr (i64 {} to %Result*)", index),
+    }
+}
+
+/// LLVM needs a decimal point in every double
+fn double(value: f64) -> String {
+    let value = value.to_string();
+    if value.contains('.') {
+        format!("double {}", value)
+    } else {
+        format!("double {}.0", value)
+    }
+}
+
+/// Numbers every qubit and every cbit of the program, in the order they were
+/// declared, since QIR addresses qubits and results by index alone. Each
+/// variable gets a block as big as its widest declaration.
+#[derive(Default)]
+struct Layout {
+    // Stores entries of <Variable Name, Index of its first element>
+    qubits: HashMap<String, usize>,
+    results: HashMap<String, usize>,
+    num_qubits: usize,
+    num_results: usize,
+}
+
+/// The body of the entry point, along with the declarations of the functions
+/// it calls
+#[derive(Default)]
+struct Main {
+    body: Vec<String>,
+    // Stores entries of <Function, Its declaration>, in the order they were first called
+    declarations: Vec<(String, String)>,
+}
+
+impl Main {
+    fn call(&mut self, function: String, signature: &str, args: Vec<String>) {
+        if !self.declarations.iter().any(|(name, _)| *name == function) {
+            // Measurements are the only calls that can't be undone
+            let attributes = if function.contains("__mz__") {
+                " #1"
+            } else {
+                ""
+            };
+            let declaration = format!("declare void @{}({}){}", function, signature, attributes);
+            self.declarations.push((function.clone(), declaration));
+        }
+        self.body
+            .push(format!("  call void @{}({})", function, args.join(", ")));
+    }
+}
+
+/// Emits the program as QIR. Reassigning a qubit resets it, and after the
+/// program runs, every cbit is recorded as an output in declaration order.
+/// QIR has no way to start a result as 1 or to give the shot count, so those
+/// become comments.
+pub fn emit(program: &Program, symbols: &SymbolTable) -> Result<String, String> {
+    check_gates(program, GATES, "QIR")?;
+    let mut layout = Layout::default();
+    for stmt in &program.stmts {
+        if let Stmt::Assign { ty, name, .. } = &stmt.kind {
+            let width = symbols.sizes[name];
+            if matches!(ty, ValueExpr::Qubit | ValueExpr::QReg) {
+                if !layout.qubits.contains_key(name) {
+                    layout.qubits.insert(name.clone(), layout.num_qubits);
+                    layout.num_qubits += width;
+                }
+            } else if !layout.results.contains_key(name) {
+                layout.results.insert(name.clone(), layout.num_results);
+                layout.num_results += width;
+            }
+        }
+    }
+
+    let mut main = Main::default();
+    let mut assigned: HashSet<&str> = HashSet::new();
+    // Widths as of the current statement, since a variable may be redeclared
+    let mut widths: HashMap<String, usize> = HashMap::new();
+    for stmt in &program.stmts {
+        let line_no = stmt.line_no;
+        match &stmt.kind {
+            Stmt::Assign {
+                ty, name, value, ..
+            } => {
+                let values = initial_values(value);
+                widths.insert(name.clone(), values.len());
+                if !matches!(ty, ValueExpr::Qubit | ValueExpr::QReg) {
+                    for (i, value) in values.iter().enumerate() {
+                        if value == "1" {
+                            main.body.push(format!(
+                                "  ; {}[{}] starts as 1, which QIR can't express",
+                                name, i
+                            ));
+                        }
+                    }
+                    continue;
+                }
+                let first = layout.qubits[name];
+                let reassigned = !assigned.insert(name);
+                for (i, value) in values.iter().enumerate() {
+                    if reassigned {
+                        main.call(
+                            "__quantum__qis__reset__body".to_string(),
+                            "%Qubit*",
+                            vec![qubit(first + i)],
+                        );
+                    }
+                    for gate in preparation(value) {
+                        main.call(
+                            format!("__quantum__qis__{}__body", gate),
+                            "%Qubit*",
+                            vec![qubit(first + i)],
+                        );
+                    }
+                }
+            }
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                params,
+                ..
+            } => {
+                let signature = gates::signature(gate);
+                let function = format!("__quantum__qis__{}__body", qis_name(signature.name));
+                let doubles: Vec<String> =
+                    params.iter().map(|param| double(param.value())).collect();
+                let index = |(name, i): (&str, usize)| layout.qubits[name] + i;
+                if signature.qubits == 1 && controls.is_empty() {
+                    let types: Vec<&str> = doubles
+                        .iter()
+                        .map(|_| "double")
+                        .chain(["%Qubit*"])
+                        .collect();
+                    for element in elements(&targets[0], &widths, line_no)? {
+                        let mut args = doubles.clone();
+                        args.push(qubit(index(element)));
+                        main.call(function.clone(), &types.join(", "), args);
+                    }
+                    continue;
+                }
+                // Controls come first in the QIS
+                let qubits: Vec<String> =
+                    all_elements(controls.iter().chain(targets), &widths, line_no)?
+                        .into_iter()
+                        .map(|element| qubit(index(element)))
+                        .collect();
+                let types: Vec<&str> = doubles
+                    .iter()
+                    .map(|_| "double")
+                    .chain(qubits.iter().map(|_| "%Qubit*"))
+                    .collect();
+                let mut args = doubles;
+                args.extend(qubits);
+                main.call(function, &types.join(", "), args);
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let pairs = elements(measured, &widths, line_no)?
+                    .into_iter()
+                    .zip(elements(recipient, &widths, line_no)?);
+                for ((qname, qi), (cname, ci)) in pairs {
+                    main.call(
+                        "__quantum__qis__mz__body".to_string(),
+                        "%Qubit*, %Result* writeonly",
+                        vec![
+                            qubit(layout.qubits[qname] + qi),
+                            result(layout.results[cname] + ci).replacen(
+                                "%Result*",
+                                "%Result* writeonly",
+                                1,
+                            ),
+                        ],
+                    );
+                }
+            }
+            Stmt::Return { shots } => main.body.push(format!("  ; shots: {}", shots)),
+            Stmt::Comment(_) => {}
+            _ => unreachable!("modules are linked before compiling"),
+        }
+    }
+    for i in 0..layout.num_results {
+        main.call(
+            "__quantum__rt__result_record_output".to_string(),
+            "%Result*, i8*",
+            vec![result(i), "i8* null".to_string()],
+        );
+    }
+
+    let mut lines = vec![
+        "; ModuleID = 'quill'".to_string(),
+        "source_filename = \"quill\"".to_string(),
+        String::new(),
+        "%Qubit = type opaque".to_string(),
+        "%Result = type opaque".to_string(),
+        String::new(),
+        "define void @main() #0 {".to_string(),
+        "entry:".to_string(),
+    ];
+    lines.extend(main.body);
+    lines.push("  ret void".to_string());
+    lines.push("}".to_string());
+    lines.push(String::new());
+    lines.extend(
+        main.declarations
+            .into_iter()
+            .map(|(_, declaration)| declaration),
+    );
+    lines.push(String::new());
+    lines.push(format!(
+        "attributes #0 = {{ \"entry_point\" \"output_labeling_schema\" \"qir_profiles\"=\"base_profile\" \"required_num_qubits\"=\"{}\" \"required_num_results\"=\"{}\" }}",
+        layout.num_qubits, layout.num_results
+    ));
+    lines.push("attributes #1 = { \"irreversible\" }".to_string());
+    lines.push(String::new());
+    lines.extend(
+        [
+            "!llvm.module.flags = !{!0, !1, !2, !3}",
+            "!0 = !{i32 1, !\"qir_major_version\", i32 1}",
+            "!1 = !{i32 7, !\"qir_minor_version\", i32 0}",
+            "!2 = !{i32 1, !\"dynamic_qubit_management\", i1 false}",
+            "!3 = !{i32 1, !\"dynamic_result_management\", i1 false}",
+        ]
+        .map(String::from),
+    );
+    Ok(lines.join("\n") + "\n")
+}
@@ -0,0 +1,202 @@
+//! The Qiskit backend, which writes a Python script building the program as a
+//! `QuantumCircuit`
+use super::qasm::qasm_param;
+use super::{all_elements, check_gates, elements, initial_values, preparation};
+use crate::ast::{Program, Stmt, ValueExpr};
+use crate::gates;
+use crate::type_checker::SymbolTable;
+use std::collections::{HashMap, HashSet};
+
+/// The gates with a `QuantumCircuit` method that Quill has
+pub const GATES: &[&str] = &[
+    "h", "x", "y", "z", "s", "sadj", "t", "tadj", "sx", "sxadj", "rx", "ry", "rz", "u3", "cnot",
+    "cz", "swap", "rxx", "ryy", "rzz", "toffoli",
+];
+
+/// The name of the `QuantumCircuit` method for a gate from the registry
+fn method_name(gate: &str) -> &str {
+    match gate {
+        "sadj" => "sdg",
+        "tadj" => "tdg",
+        "sxadj" => "sxdg",
+        "u3" => "u",
+        "cnot" => "cx",
+        "toffoli" => "ccx",
+        _ => gate,
+    }
+}
+
+/// Names the script itself uses, along with Python's keywords, which Quill
+/// variables get an underscore after to steer clear of
+const RESERVED: &[&str] = &[
+    "circuit",
+    "pi",
+    "shots",
+    "QuantumCircuit",
+    "QuantumRegister",
+    "ClassicalRegister",
+    "False",
+    "None",
+    "True",
+    "and",
+    "as",
+    "assert",
+    "async",
+    "await",
+    "break",
+    "class",
+    "continue",
+    "def",
+    "del",
+    "elif",
+    "else",
+    "except",
+    "finally",
+    "for",
+    "from",
+    "global",
+    "if",
+    "import",
+    "in",
+    "is",
+    "lambda",
+    "nonlocal",
+    "not",
+    "or",
+    "pass",
+    "raise",
+    "return",
+    "try",
+    "while",
+    "with",
+    "yield",
+];
+
+fn python_name(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Emits the program as a Python script that builds it with Qiskit, leaving
+/// the circuit in `circuit` and the shot count in `shots`. Every variable
+/// becomes a register (plain qubits and cbits of width 1) as wide as its widest
+/// declaration, and reassigning a qubit resets it. Qiskit has no way to start a
+/// classical bit as 1, so that becomes a comment.
+pub fn emit(program: &Program, symbols: &SymbolTable) -> Result<String, String> {
+    check_gates(program, GATES, "Qiskit")?;
+    let mut registers = vec![];
+    let mut declarations = vec![];
+    for stmt in &program.stmts {
+        if let Stmt::Assign { ty, name, .. } = &stmt.kind {
+            if !registers.contains(&python_name(name)) {
+                let kind = match ty {
+                    ValueExpr::Qubit | ValueExpr::QReg => "QuantumRegister",
+                    ValueExpr::CBit | ValueExpr::CReg => "ClassicalRegister",
+                };
+                registers.push(python_name(name));
+                declarations.push(format!(
+                    "{} = {}({}, \"{}\")",
+                    python_name(name),
+                    kind,
+                    symbols.sizes[name],
+                    name
+                ));
+            }
+        }
+    }
+
+    let mut lines = vec![
+        "from math import pi".to_string(),
+        String::new(),
+        "from qiskit import ClassicalRegister, QuantumCircuit, QuantumRegister".to_string(),
+        String::new(),
+    ];
+    lines.extend(declarations);
+    lines.push(format!(
+        "circuit = QuantumCircuit({})",
+        registers.join(", ")
+    ));
+
+    let qubit = |name: &str, i: usize| format!("{}[{}]", python_name(name), i);
+    let mut assigned: HashSet<&str> = HashSet::new();
+    // Widths as of the current statement, since a variable may be redeclared
+    let mut widths: HashMap<String, usize> = HashMap::new();
+    for stmt in &program.stmts {
+        let line_no = stmt.line_no;
+        match &stmt.kind {
+            Stmt::Assign {
+                ty, name, value, ..
+            } => {
+                let values = initial_values(value);
+                widths.insert(name.clone(), values.len());
+                let reassigned = !assigned.insert(name);
+                for (i, value) in values.iter().enumerate() {
+                    if !matches!(ty, ValueExpr::Qubit | ValueExpr::QReg) {
+                        if value == "1" {
+                            lines.push(format!(
+                                "# {}[{}] starts as 1, which Qiskit can't express",
+                                name, i
+                            ));
+                        }
+                        continue;
+                    }
+                    if reassigned {
+                        lines.push(format!("circuit.reset({})", qubit(name, i)));
+                    }
+                    for gate in preparation(value) {
+                        lines.push(format!("circuit.{}({})", gate, qubit(name, i)));
+                    }
+                }
+            }
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                params,
+                ..
+            } => {
+                let signature = gates::signature(gate);
+                let method = method_name(signature.name);
+                let params: Vec<String> = params.iter().map(qasm_param).collect();
+                if signature.qubits == 1 && controls.is_empty() {
+                    for (name, i) in elements(&targets[0], &widths, line_no)? {
+                        let mut args = params.clone();
+                        args.push(qubit(name, i));
+                        lines.push(format!("circuit.{}({})", method, args.join(", ")));
+                    }
+                    continue;
+                }
+                // Controls come first in the QuantumCircuit methods
+                let mut args = params;
+                args.extend(
+                    all_elements(controls.iter().chain(targets), &widths, line_no)?
+                        .into_iter()
+                        .map(|(name, i)| qubit(name, i)),
+                );
+                lines.push(format!("circuit.{}({})", method, args.join(", ")));
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let pairs = elements(measured, &widths, line_no)?
+                    .into_iter()
+                    .zip(elements(recipient, &widths, line_no)?);
+                for ((qname, qi), (cname, ci)) in pairs {
+                    lines.push(format!(
+                        "circuit.measure({}, {})",
+                        qubit(qname, qi),
+                        qubit(cname, ci)
+                    ));
+                }
+            }
+            Stmt::Return { shots } => lines.push(format!("shots = {}", shots)),
+            Stmt::Comment(_) => {}
+            _ => unreachable!("modules are linked before compiling"),
+        }
+    }
+    Ok(lines.join("\n") + "\n")
+}
@@ -0,0 +1,16 @@
+//! The Quill language server, which speaks the Language Server Protocol over
+//! stdin and stdout
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let stdin = std::io::stdin();
+    match quill::lsp::serve(stdin.lock(), std::io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        // The protocol wants 1 when the client exits without shutting down first
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(1)
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! The circuit DAG, an intermediate representation of a type checked program
+//! in which operations are connected by the qubits and cbits they share. The
+//! optimizer measures depth with its layers, routing schedules gates with it,
+//! and the REPL draws it. The peephole passes still rewrite the program itself,
+//! using the commutation DAG instead.
+use crate::ast::{Program, Statement, Stmt, Target, Value};
+use crate::backends::initial_values;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// A single qubit or cbit of a variable, as (variable, index). Plain qubits
+/// and cbits have index 0.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Wire {
+    Qubit(String, usize),
+    CBit(String, usize),
+}
+
+/// An operation in the circuit, which is a gate application, a measurement, or
+/// an assignment (which initializes every wire of its variable), along with the
+/// wires it acts on
+#[derive(PartialEq, Debug, Clone)]
+pub struct Node {
+    pub stmt: Statement,
+    pub wires: Vec<Wire>,
+}
+
+/// The wire `wire` going from the operation at `from` straight to the next
+/// operation on it, at `to`
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub wire: Wire,
+}
+
+/// An operation drawn in a column, as (first row, last row, label per row)
+type Placed = (usize, usize, HashMap<usize, String>);
+
+/// Where a comment goes when the circuit is turned back into a program. A
+/// comment on the same line as an operation stays right after it, and any
+/// other comment stays right before the operation that came after it (or at
+/// the end, when no operation did).
+#[derive(PartialEq, Debug, Clone)]
+enum Placement {
+    Before(usize),
+    After(usize),
+    End,
+}
+
+/// A program as a DAG of its operations. Nodes are indexed in the order they
+/// were added, which is always a topological order, and there is one edge per
+/// wire between consecutive operations on that wire (so two operations sharing
+/// several wires are joined by several edges). Comments are not operations, so
+/// they're kept aside along with the operation they go with, and so is the
+/// Return, since it ends every wire.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Circuit {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    /// The last operation on each wire so far, which the next one connects to
+    last: HashMap<Wire, usize>,
+    /// The width of every variable declared so far
+    widths: HashMap<String, usize>,
+    comments: Vec<(Placement, Statement)>,
+    pub ret: Option<Statement>,
+}
+
+impl Circuit {
+    /// Builds the circuit for a (type checked) program
+    pub fn from_program(program: &Program) -> Circuit {
+        let mut circuit = Circuit::default();
+        for stmt in &program.stmts {
+            match stmt.kind {
+                Stmt::Comment(_) => {
+                    let last = circuit.nodes.len().checked_sub(1);
+                    let placement = match last {
+                        Some(last) if circuit.nodes[last].stmt.line_no == stmt.line_no => {
+                            Placement::After(last)
+                        }
+                        _ => Placement::Before(circuit.nodes.len()),
+                    };
+                    circuit.comments.push((placement, stmt.clone()));
+                }
+                Stmt::Return { .. } => circuit.ret = Some(stmt.clone()),
+                _ => {
+                    circuit.push(stmt.clone());
+                }
+            }
+        }
+        // Operations pushed later don't take over the comments at the end
+        for (placement, _) in &mut circuit.comments {
+            if *placement == Placement::Before(circuit.nodes.len()) {
+                *placement = Placement::End;
+            }
+        }
+        circuit
+    }
+
+    /// Appends an operation after everything already in the circuit, and
+    /// returns its index
+    pub fn push(&mut self, stmt: Statement) -> usize {
+        let index = self.nodes.len();
+        if let Stmt::Assign { name, value, .. } = &stmt.kind {
+            self.widths
+                .insert(name.clone(), initial_values(value).len());
+        }
+        let wires = self.wires_of(&stmt.kind);
+        for wire in &wires {
+            if let Some(from) = self.last.insert(wire.clone(), index) {
+                self.edges.push(Edge {
+                    from,
+                    to: index,
+                    wire: wire.clone(),
+                });
+            }
+        }
+        self.nodes.push(Node { stmt, wires });
+        index
+    }
+
+    /// Every wire an operation touches
+    fn wires_of(&self, stmt: &Stmt) -> Vec<Wire> {
+        let expand = |target: &Target, wire: fn(String, usize) -> Wire| -> Vec<Wire> {
+            let name = target.name().to_string();
+            let (start, end) = match *target {
+                Target::Name(_) => (0, self.widths.get(&name).copied().unwrap_or(1) - 1),
+                Target::Slice { start, end, .. } => (start, end),
+            };
+            (start..=end).map(|i| wire(name.clone(), i)).collect()
+        };
+        match stmt {
+            Stmt::Assign { name, value, .. } => {
+                let wire = match value {
+                    Value::Qubit(_) | Value::QReg(_) => Wire::Qubit,
+                    Value::CBit(_) | Value::CReg(_) => Wire::CBit,
+                };
+                (0..self.widths[name])
+                    .map(|i| wire(name.clone(), i))
+                    .collect()
+            }
+            Stmt::Gate {
+                targets, controls, ..
+            } => targets
+                .iter()
+                .chain(controls)
+                .flat_map(|target| expand(target, Wire::Qubit))
+                .collect(),
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let mut wires = expand(measured, Wire::Qubit);
+                wires.extend(expand(recipient, Wire::CBit));
+                wires
+            }
+            Stmt::Return { .. } | Stmt::Comment(_) => vec![],
+            _ => unreachable!("modules are linked before analysis"),
+        }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The operations the given one directly depends on, without duplicates
+    pub fn predecessors(&self, index: usize) -> Vec<usize> {
+        let mut nodes: Vec<usize> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.to == index)
+            .map(|edge| edge.from)
+            .collect();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    /// The operations that directly depend on the given one, without duplicates
+    pub fn successors(&self, index: usize) -> Vec<usize> {
+        let mut nodes: Vec<usize> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.from == index)
+            .map(|edge| edge.to)
+            .collect();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    /// The operations in an order where every operation comes after the ones it
+    /// depends on. Whenever there's a choice, the earliest added operation goes
+    /// first, so the original program order comes back out.
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut remaining: Vec<usize> = (0..self.nodes.len())
+            .map(|index| self.predecessors(index).len())
+            .collect();
+        let mut ready: BinaryHeap<Reverse<usize>> = remaining
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count == 0)
+            .map(|(index, _)| Reverse(index))
+            .collect();
+        let mut order = vec![];
+        while let Some(Reverse(index)) = ready.pop() {
+            order.push(index);
+            for successor in self.successors(index) {
+                remaining[successor] -= 1;
+                if remaining[successor] == 0 {
+                    ready.push(Reverse(successor));
+                }
+            }
+        }
+        order
+    }
+
+    /// Groups the operations into layers, where every operation goes in the
+    /// layer right after the last of the ones it depends on. Operations in the
+    /// same layer act on disjoint wires, so they can all happen at once.
+    pub fn layers(&self) -> Vec<Vec<usize>> {
+        let mut layer_of = vec![0; self.nodes.len()];
+        let mut layers: Vec<Vec<usize>> = vec![];
+        for index in self.topological_order() {
+            let layer = self
+                .predecessors(index)
+                .iter()
+                .map(|pred| layer_of[*pred] + 1)
+                .max()
+                .unwrap_or(0);
+            layer_of[index] = layer;
+            if layers.len() == layer {
+                layers.push(vec![]);
+            }
+            layers[layer].push(index);
+        }
+        layers
+    }
+
+    /// Turns the circuit back into a program, with its operations in
+    /// topological order, each comment next to its operation, and the Return
+    /// (if any) at the end, before the comments that came after it
+    pub fn to_program(&self) -> Program {
+        let comments = |placement: Placement| {
+            self.comments
+                .iter()
+                .filter(move |(placed, _)| *placed == placement)
+                .map(|(_, comment)| comment.clone())
+        };
+        let mut stmts: Vec<Statement> = vec![];
+        for index in self.topological_order() {
+            stmts.extend(comments(Placement::Before(index)));
+            stmts.push(self.nodes[index].stmt.clone());
+            stmts.extend(comments(Placement::After(index)));
+        }
+        let (before, after): (Vec<Statement>, Vec<Statement>) =
+            comments(Placement::End).partition(|comment| {
+                self.ret
+                    .as_ref()
+                    .is_none_or(|ret| comment.line_no < ret.line_no)
+            });
+        stmts.extend(before);
+        stmts.extend(self.ret.clone());
+        stmts.extend(after);
+        Program { stmts }
+    }
+
+    /// Prints the circuit back out as Quill source
+    pub fn to_source(&self) -> String {
+        self.to_program().to_string()
+    }
+
+    /// Draws the circuit as text, with a row per qubit (drawn with '-') and
+    /// then a row per cbit (drawn with '='), in the order they were declared.
+    /// Operations are placed in the leftmost column after the ones they depend
+    /// on where they don't overlap anything, controls are drawn as '*', and
+    /// '|' joins the rows an operation spans.
+    pub fn draw(&self) -> String {
+        let mut wires: Vec<&Wire> = vec![];
+        for node in &self.nodes {
+            for wire in &node.wires {
+                if !wires.contains(&wire) {
+                    wires.push(wire);
+                }
+            }
+        }
+        wires.sort_by_key(|wire| matches!(wire, Wire::CBit(..)));
+        let row = |wire: &Wire| wires.iter().position(|w| *w == wire).unwrap();
+
+        let mut columns: Vec<Vec<Placed>> = vec![];
+        let mut column_of = vec![0; self.nodes.len()];
+        for index in self.topological_order() {
+            let node = &self.nodes[index];
+            let labels = self.labels(node, &row);
+            let first = *labels.keys().min().unwrap();
+            let last = *labels.keys().max().unwrap();
+            let mut column = self
+                .predecessors(index)
+                .iter()
+                .map(|pred| column_of[*pred] + 1)
+                .max()
+                .unwrap_or(0);
+            while column < columns.len()
+                && columns[column]
+                    .iter()
+                    .any(|(lo, hi, _)| *lo <= last && first <= *hi)
+            {
+                column += 1;
+            }
+            if column == columns.len() {
+                columns.push(vec![]);
+            }
+            column_of[index] = column;
+            columns[column].push((first, last, labels));
+        }
+
+        let names: Vec<String> = wires
+            .iter()
+            .map(|wire| match wire {
+                Wire::Qubit(name, index) | Wire::CBit(name, index) if self.widths[name] > 1 => {
+                    format!("{}[{}]", name, index)
+                }
+                Wire::Qubit(name, _) | Wire::CBit(name, _) => name.clone(),
+            })
+            .collect();
+        let name_width = names.iter().map(String::len).max().unwrap_or(0);
+        let mut lines: Vec<String> = names
+            .iter()
+            .map(|name| format!("{:width$} ", name, width = name_width))
+            .collect();
+        for (r, wire) in wires.iter().enumerate() {
+            let fill = match wire {
+                Wire::Qubit(..) => '-',
+                Wire::CBit(..) => '=',
+            };
+            lines[r].push(fill);
+            for column in &columns {
+                let width = column
+                    .iter()
+                    .flat_map(|(_, _, labels)| labels.values())
+                    .map(|label| label.chars().count())
+                    .max()
+                    .unwrap_or(1);
+                let label = column
+                    .iter()
+                    .find_map(|(lo, hi, labels)| match labels.get(&r) {
+                        Some(label) => Some(label.clone()),
+                        None if *lo <= r && r <= *hi => Some("|".to_string()),
+                        None => None,
+                    });
+                let label = label.unwrap_or_else(|| fill.to_string());
+                let pad = width - label.chars().count();
+                let fills = |n: usize| fill.to_string().repeat(n);
+                lines[r].push_str(&fills(pad / 2));
+                lines[r].push_str(&label);
+                lines[r].push_str(&fills(pad - pad / 2 + 1));
+            }
+        }
+        let mut drawing = String::new();
+        for line in lines {
+            drawing.push_str(&line);
+            drawing.push('\n');
+        }
+        drawing
+    }
+
+    /// What to draw on each row an operation touches
+    fn labels(&self, node: &Node, row: &dyn Fn(&Wire) -> usize) -> HashMap<usize, String> {
+        let mut labels = HashMap::new();
+        match &node.stmt.kind {
+            Stmt::Assign { value, .. } => {
+                for (wire, initial) in node.wires.iter().zip(initial_values(value)) {
+                    let label = match wire {
+                        Wire::Qubit(..) => format!("|{}>", initial),
+                        Wire::CBit(..) => initial,
+                    };
+                    labels.insert(row(wire), label);
+                }
+            }
+            Stmt::Gate {
+                gate,
+                targets,
+                params,
+                ..
+            } => {
+                let mut label = gate.clone();
+                if !params.is_empty() {
+                    let params: Vec<String> =
+                        params.iter().map(|param| param.to_string()).collect();
+                    label = format!("{}({})", gate, params.join(", "));
+                }
+                // Targets come first among the wires, then the controls
+                let covered: usize = targets
+                    .iter()
+                    .map(|target| match target {
+                        Target::Name(name) => self.widths.get(name).copied().unwrap_or(1),
+                        Target::Slice { start, end, .. } => end - start + 1,
+                    })
+                    .sum();
+                for (i, wire) in node.wires.iter().enumerate() {
+                    let label = if i < covered {
+                        label.clone()
+                    } else {
+                        "*".to_string()
+                    };
+                    labels.insert(row(wire), label);
+                }
+            }
+            _ => {
+                for wire in &node.wires {
+                    let label = match wire {
+                        Wire::Qubit(..) => "M",
+                        Wire::CBit(..) => "v",
+                    };
+                    labels.insert(row(wire), label.to_string());
+                }
+            }
+        }
+        labels
+    }
+}
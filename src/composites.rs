@@ -0,0 +1,278 @@
+//! Built-in gates made out of the gates in the registry, for the building
+//! blocks of common algorithms. Unlike registry gates they act on any number
+//! of qubits, so a program names them like any other gate (as in
+//! `Thy qft shalt target q[0..3]`), and linking replaces them with the gate
+//! applications they expand into. Everything downstream, from the optimizer
+//! to the simulator and backends, only ever sees registry gates.
+use crate::ast::{Param, Stmt, Target};
+use crate::gates;
+
+/// Builds the gates a composite turns into from the qubits of each of its
+/// targets and its parameters, or says why it can't
+pub type Expansion = fn(&[Vec<Target>], &[Param]) -> Result<Vec<Stmt>, String>;
+
+/// A built-in composite gate. `expand` is given the qubits each target stands
+/// for, one at a time (so a whole register of three qubits is three of them),
+/// and the parameters, which have already been counted.
+pub struct Composite {
+    pub name: &'static str,
+    /// What it does, for hover and completion
+    pub description: &'static str,
+    /// How many targets it takes, or None for any number, which are then
+    /// treated as one register
+    pub targets: Option<usize>,
+    pub params: usize,
+    pub expand: Expansion,
+}
+
+/// Looks up a composite gate by its name, which is case insensitive
+pub fn lookup(name: &str) -> Option<&'static Composite> {
+    COMPOSITES
+        .iter()
+        .find(|composite| composite.name.eq_ignore_ascii_case(name))
+}
+
+pub static COMPOSITES: &[Composite] = &[
+    Composite {
+        name: "qft",
+        description: "the quantum Fourier transform, with the first qubit the most significant",
+        targets: None,
+        params: 0,
+        expand: |targets, _| Ok(qft(&at_least(1, "qft", targets)?)),
+    },
+    Composite {
+        name: "iqft",
+        description: "the inverse quantum Fourier transform",
+        targets: None,
+        params: 0,
+        expand: |targets, _| Ok(inverse(qft(&at_least(1, "iqft", targets)?))),
+    },
+    Composite {
+        name: "diffuse",
+        description: "Grover's diffusion, reflecting about the uniform superposition",
+        targets: None,
+        params: 0,
+        expand: |targets, _| {
+            let qubits = at_least(2, "diffuse", targets)?;
+            let (last, rest) = qubits.split_last().unwrap();
+            let mut stmts = each("h", &qubits);
+            stmts.extend(each("x", &qubits));
+            stmts.push(gate("mcz", &[last], rest, vec![]));
+            stmts.extend(each("x", &qubits));
+            stmts.extend(each("h", &qubits));
+            Ok(stmts)
+        },
+    },
+    Composite {
+        name: "ghz",
+        description: "prepares qubits starting in 0 in (|00...0> + |11...1>) / sqrt(2)",
+        targets: None,
+        params: 0,
+        expand: |targets, _| {
+            let qubits = at_least(2, "ghz", targets)?;
+            let mut stmts = vec![gate("h", &[&qubits[0]], &[], vec![])];
+            for pair in qubits.windows(2) {
+                stmts.push(gate("cnot", &[&pair[1]], &pair[..1], vec![]));
+            }
+            Ok(stmts)
+        },
+    },
+    Composite {
+        name: "qpe",
+        description: "phase estimation of a phase gate of the given angle, writing angle / (2 PI) \
+                      into the first target (counting qubits) from the eigenstate 1 of the second",
+        targets: Some(2),
+        params: 1,
+        expand: |targets, params| {
+            let (counting, eigenstate) = (&targets[0], &targets[1]);
+            if eigenstate.len() != 1 {
+                return Err(format!(
+                    "qpe's eigenstate is a single qubit, but {} were given",
+                    eigenstate.len()
+                ));
+            }
+            let mut stmts = each("h", counting);
+            // The k-th counting qubit (from the most significant) applies the
+            // phase 2^(m - 1 - k) times
+            for (k, qubit) in counting.iter().enumerate() {
+                let power = 1 << (counting.len() - 1 - k);
+                stmts.extend(controlled_phase(
+                    qubit,
+                    &eigenstate[0],
+                    &times(&params[0], power, 1),
+                ));
+            }
+            stmts.extend(inverse(qft(counting)));
+            Ok(stmts)
+        },
+    },
+    Composite {
+        name: "add",
+        description: "adds the first register into the second (modulo its size), \
+                      in Fourier space so no ancillas are needed",
+        targets: Some(2),
+        params: 0,
+        expand: |targets, _| add(&targets[0], &targets[1]),
+    },
+    Composite {
+        name: "sub",
+        description: "subtracts the first register from the second (modulo its size)",
+        targets: Some(2),
+        params: 0,
+        expand: |targets, _| add(&targets[0], &targets[1]).map(inverse),
+    },
+];
+
+/// A gate application of a registry gate
+fn gate(name: &str, targets: &[&Target], controls: &[Target], params: Vec<Param>) -> Stmt {
+    Stmt::Gate {
+        gate: name.to_string(),
+        kind: gates::signature(name).kind,
+        targets: targets.iter().map(|&target| target.clone()).collect(),
+        controls: controls.to_vec(),
+        params,
+    }
+}
+
+/// A single qubit gate on each qubit in turn
+fn each(name: &str, qubits: &[Target]) -> Vec<Stmt> {
+    qubits
+        .iter()
+        .map(|qubit| gate(name, &[qubit], &[], vec![]))
+        .collect()
+}
+
+/// Every target's qubits as one register, which needs at least `count` qubits
+fn at_least(count: usize, name: &str, targets: &[Vec<Target>]) -> Result<Vec<Target>, String> {
+    let qubits = targets.concat();
+    if qubits.len() < count {
+        return Err(format!(
+            "{} acts on at least {} qubit(s), but {} were given",
+            name,
+            count,
+            qubits.len()
+        ));
+    }
+    Ok(qubits)
+}
+
+/// `angle * num / den`, which stays an exact fraction of PI if it was one
+fn times(angle: &Param, num: i64, den: i64) -> Param {
+    match *angle {
+        Param::PI(a, b) => {
+            // rz is periodic in 4 PI, so the fraction can be kept in [0, 4 PI)
+            let (num, den) = ((a as i64 * num), (b as i64 * den));
+            let num = num.rem_euclid(4 * den);
+            let divisor = gcd(num, den);
+            Param::PI((num / divisor) as i32, (den / divisor) as i32)
+        }
+        _ => Param::Float(angle.value() * num as f64 / den as f64),
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    match b {
+        0 => a.abs().max(1),
+        _ => gcd(b, a % b),
+    }
+}
+
+/// A phase of `angle` on `target` when `control` is 1 (up to a global phase),
+/// from rz and cnot
+fn controlled_phase(control: &Target, target: &Target, angle: &Param) -> Vec<Stmt> {
+    let controls = [control.clone()];
+    vec![
+        gate("rz", &[target], &[], vec![times(angle, 1, 2)]),
+        gate("cnot", &[target], &controls, vec![]),
+        gate("rz", &[target], &[], vec![times(angle, -1, 2)]),
+        gate("cnot", &[target], &controls, vec![]),
+        gate("rz", &[control], &[], vec![times(angle, 1, 2)]),
+    ]
+}
+
+/// The quantum Fourier transform without its final swaps, which leaves the
+/// i-th qubit holding the phase 2 PI * (x mod 2^(n - i)) / 2^(n - i) of the
+/// value x the n qubits held
+fn fourier(qubits: &[Target]) -> Vec<Stmt> {
+    let mut stmts = vec![];
+    for (i, qubit) in qubits.iter().enumerate() {
+        stmts.push(gate("h", &[qubit], &[], vec![]));
+        for (j, control) in qubits.iter().enumerate().skip(i + 1) {
+            stmts.extend(controlled_phase(
+                control,
+                qubit,
+                &Param::PI(1, 1 << (j - i)),
+            ));
+        }
+    }
+    stmts
+}
+
+fn qft(qubits: &[Target]) -> Vec<Stmt> {
+    let mut stmts = fourier(qubits);
+    let n = qubits.len();
+    for i in 0..n / 2 {
+        stmts.push(gate("swap", &[&qubits[i], &qubits[n - 1 - i]], &[], vec![]));
+    }
+    stmts
+}
+
+/// Adds the register `a` into `b` by adding its value to the phases the
+/// Fourier transform of `b` holds (Draper's adder)
+fn add(a: &[Target], b: &[Target]) -> Result<Vec<Stmt>, String> {
+    if a.len() != b.len() {
+        return Err(format!(
+            "registers of the same size can be added, but they have {} and {} qubits",
+            a.len(),
+            b.len()
+        ));
+    }
+    let mut stmts = fourier(b);
+    for (i, qubit) in b.iter().enumerate() {
+        // Bits of a more significant than the i-th add whole turns, which do nothing
+        for (j, control) in a.iter().enumerate().skip(i) {
+            stmts.extend(controlled_phase(
+                control,
+                qubit,
+                &Param::PI(1, 1 << (j - i)),
+            ));
+        }
+    }
+    stmts.extend(inverse(fourier(b)));
+    Ok(stmts)
+}
+
+/// The gates undoing the given ones, which are registry gates that either
+/// have an inverse or are rotations
+fn inverse(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts
+        .into_iter()
+        .rev()
+        .map(|stmt| match stmt {
+            Stmt::Gate {
+                gate,
+                kind,
+                targets,
+                controls,
+                params,
+            } => {
+                let signature = gates::signature(&gate);
+                let (gate, params) = match signature.inverse {
+                    Some(inverse) => (inverse.to_string(), params),
+                    None => (
+                        gate,
+                        params.iter().map(|param| times(param, -1, 1)).collect(),
+                    ),
+                };
+                Stmt::Gate {
+                    gate,
+                    kind,
+                    targets,
+                    controls,
+                    params,
+                }
+            }
+            stmt => stmt,
+        })
+        .collect()
+}
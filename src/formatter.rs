@@ -0,0 +1,237 @@
+//! The canonical layout of Quill source, which `quill fmt` prints. Every
+//! statement goes on its own line with single spaces between its words and
+//! keywords capitalized as in the docs, comments keep their text (and stay
+//! after the statement they follow on its line), runs of blank lines shrink to
+//! one, and every gate is written under one name (its registry name, unless
+//! configured otherwise). Programs can be written in either dialect, which is
+//! how `quill translate` works.
+use crate::ast::{Program, RespectExpr, Statement, Stmt, ValueExpr};
+use crate::gates;
+use crate::parser::{dialect_header, Dialect};
+
+/// How to format a program
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct FormatConfig {
+    /// Names to write gates under instead of their registry names, like "cx"
+    /// for cnot. Holds at most one name per gate.
+    preferred: Vec<&'static str>,
+    /// The keywords to write statements with
+    pub dialect: Dialect,
+}
+
+impl FormatConfig {
+    /// Writes the gates these names belong to under them, so
+    /// `FormatConfig::new(&["cx", "ccx"])` writes "cx" for every cnot and
+    /// "ccx" for every toffoli
+    pub fn new(preferred: &[&str]) -> Result<FormatConfig, String> {
+        let mut config = FormatConfig::default();
+        for name in preferred {
+            let gate = gates::lookup(name).ok_or_else(|| format!("{:?} isn't a gate!", name))?;
+            // Keep the registry's copy of the name, which lives forever
+            let name = std::iter::once(gate.name)
+                .chain(gate.aliases.iter().copied())
+                .find(|alias| alias == name)
+                .unwrap();
+            if let Some(other) = config.preferred.iter().find(|other| gate.is_named(other)) {
+                return Err(format!(
+                    "{} and {} are the same gate, so only one can be preferred!",
+                    other, name
+                ));
+            }
+            config.preferred.push(name);
+        }
+        Ok(config)
+    }
+
+    /// The name to write a gate under
+    fn name_of(&self, gate: &str) -> &str {
+        let gate = gates::signature(gate);
+        self.preferred
+            .iter()
+            .find(|name| gate.is_named(name))
+            .copied()
+            .unwrap_or(gate.name)
+    }
+}
+
+/// Lays out a program canonically. Blank lines are found from the gaps
+/// between statements' line numbers, so the program should come straight from
+/// the parser. A dialect header is rewritten for the configured dialect, and
+/// one is added if the program is in modern English.
+pub fn format(program: &Program, config: &FormatConfig) -> String {
+    let mut source = String::new();
+    let has_header = program.stmts.first().is_some_and(|stmt| {
+        stmt.line_no == 1
+            && matches!(&stmt.kind, Stmt::Comment(text) if dialect_header(text).is_some())
+    });
+    if config.dialect == Dialect::Modern && !has_header {
+        source.push_str(Dialect::Modern.header());
+        source.push('\n');
+    }
+    let mut previous = None;
+    let mut stmts = program.stmts.as_slice();
+    if has_header {
+        source.push_str(config.dialect.header());
+        source.push('\n');
+        previous = Some(stmts[0].line_no);
+        stmts = &stmts[1..];
+    }
+    lay_out(stmts, config, "", &mut previous, &mut source);
+    source
+}
+
+/// Writes out statements one per line behind an indent, given the line the
+/// statement before them was on. Gate definitions write their bodies indented
+/// by four more spaces.
+fn lay_out(
+    stmts: &[Statement],
+    config: &FormatConfig,
+    indent: &str,
+    previous: &mut Option<i32>,
+    source: &mut String,
+) {
+    for stmt in stmts {
+        // A comment on the same line as a statement stays after it
+        if let Stmt::Comment(text) = &stmt.kind {
+            if *previous == Some(stmt.line_no) {
+                source.pop();
+                source.push_str(&format!(" //{}\n", text.trim_end()));
+                continue;
+            }
+        }
+        if previous.is_some_and(|line_no| stmt.line_no > line_no + 1) {
+            source.push('\n');
+        }
+        *previous = Some(stmt.line_no);
+        let kind = match &stmt.kind {
+            Stmt::Gate {
+                gate,
+                kind,
+                targets,
+                controls,
+                params,
+            } => Stmt::Gate {
+                gate: config.name_of(gate).to_string(),
+                kind: *kind,
+                targets: targets.clone(),
+                controls: controls.clone(),
+                params: params.clone(),
+            },
+            Stmt::Comment(text) => Stmt::Comment(text.trim_end().to_string()),
+            Stmt::GateDef { name, qubits, body } => {
+                let header = match config.dialect {
+                    Dialect::MiddleEnglish => format!("Devyse {} on {}", name, qubits.join(", ")),
+                    Dialect::Modern => format!("gate {} on {}", name, qubits.join(", ")),
+                };
+                source.push_str(&format!("{}{}\n", indent, header));
+                lay_out(body, config, &format!("{}    ", indent), previous, source);
+                let end = match config.dialect {
+                    Dialect::MiddleEnglish => "Ende",
+                    Dialect::Modern => "end",
+                };
+                source.push_str(&format!("{}{}\n", indent, end));
+                // Ende is taken to be on the line after the body, since the
+                // program doesn't keep where it was
+                *previous = previous.map(|line_no| line_no + 1);
+                continue;
+            }
+            kind => kind.clone(),
+        };
+        let line = match config.dialect {
+            Dialect::MiddleEnglish => kind.to_string(),
+            Dialect::Modern => modern(&kind),
+        };
+        source.push_str(indent);
+        source.push_str(&line);
+        source.push('\n');
+    }
+}
+
+/// Writes a statement in modern English, with keywords in lower case
+fn modern(stmt: &Stmt) -> String {
+    let join = |items: Vec<String>, separator: &str| items.join(separator);
+    match stmt {
+        Stmt::Assign {
+            respect,
+            ty,
+            name,
+            value,
+        } => {
+            let please = match respect {
+                RespectExpr::Maistow => "please ",
+                RespectExpr::Canstow => "",
+            };
+            let ty = match ty {
+                ValueExpr::Qubit => "qubit",
+                ValueExpr::QReg => "qreg",
+                ValueExpr::CBit => "cbit",
+                ValueExpr::CReg => "creg",
+            };
+            format!("{}let {} {} = {}", please, ty, name, value)
+        }
+        Stmt::Gate {
+            gate,
+            targets,
+            controls,
+            params,
+            ..
+        } => {
+            let targets = targets.iter().map(ToString::to_string).collect();
+            let mut line = format!("apply {} to {}", gate, join(targets, " and "));
+            if !controls.is_empty() {
+                let controls = controls.iter().map(ToString::to_string).collect();
+                line.push_str(&format!(" controlled by {}", join(controls, ", ")));
+            }
+            if !params.is_empty() {
+                let params = params.iter().map(ToString::to_string).collect();
+                line.push_str(&format!(" with [{}]", join(params, ", ")));
+            }
+            line
+        }
+        Stmt::Measure {
+            measured,
+            recipient,
+        } => format!("measure {} into {}", measured, recipient),
+        Stmt::Return { shots } => format!("return {}", shots),
+        Stmt::Comment(text) => format!("//{}", text),
+        Stmt::Import { path } => format!("import \"{}\"", path),
+        Stmt::Constant { name, value } => format!("const {} = {}", name, value),
+        Stmt::Call {
+            gate,
+            targets,
+            params,
+        } => {
+            let targets = targets.iter().map(ToString::to_string).collect();
+            let mut line = format!("apply {} to {}", gate, join(targets, " and "));
+            if !params.is_empty() {
+                let params = params.iter().map(ToString::to_string).collect();
+                line.push_str(&format!(" with [{}]", join(params, ", ")));
+            }
+            line
+        }
+        // `format` writes out definitions itself, since their bodies are indented
+        Stmt::GateDef { .. } => unreachable!(),
+    }
+}
+
+/// The first line (numbered from 1) where the source isn't laid out the way
+/// `format` would, along with what it should be (None past the end of the
+/// formatted program), or None if it's already formatted
+pub fn first_difference(source: &str, formatted: &str) -> Option<(usize, Option<String>)> {
+    if source == formatted {
+        return None;
+    }
+    let mut expected = formatted.split_inclusive('\n');
+    for (i, line) in source.split_inclusive('\n').enumerate() {
+        match expected.next() {
+            Some(want) if want == line => {}
+            want => return Some((i + 1, want.map(|want| want.trim_end().to_string()))),
+        }
+    }
+    // The source is a prefix of the formatted program
+    let line = source.split_inclusive('\n').count() + 1;
+    Some((
+        line,
+        expected.next().map(|want| want.trim_end().to_string()),
+    ))
+}
@@ -0,0 +1,497 @@
+//! The gate registry, which is the single source of truth for every gate Quill
+//! supports. The parser, type checker, and anything that needs to know what a
+//! gate does all consult this table, so adding a gate is one entry in `GATES`.
+use crate::ast::GateExpr;
+use num_complex::Complex64;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+/// A square unitary matrix, stored row by row. Multi-qubit matrices use the
+/// order the qubits are written in, with the first qubit as the most
+/// significant bit of the row / column index.
+pub type Matrix = Vec<Vec<Complex64>>;
+
+/// How many "control on" qubits a gate takes
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Controls {
+    None,
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Controls {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Controls::None => count == 0,
+            Controls::Exactly(n) => count == n,
+            Controls::AtLeast(n) => count >= n,
+        }
+    }
+}
+
+/// The Pauli operators, standing in for the bases they are diagonal in
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Pauli {
+    X,
+    Y,
+    Z,
+}
+
+/// Everything we need to know about a gate. `qubits` counts the qubits the
+/// gate acts on directly (excluding controls), and `matrix` builds the unitary
+/// for those qubits alone from the gate's parameters; a controlled gate applies
+/// it only when every control qubit is 1. `inverse` names the gate that undoes
+/// this one on the same qubits (itself, for self-inverse gates), and is left
+/// out for parameterized gates, whose inverses depend on their parameters.
+/// `basis` is the Pauli basis the gate is diagonal in on each of its target
+/// qubits, if there is one (control qubits are always diagonal in Z), which is
+/// what decides whether two gates commute.
+#[derive(Debug)]
+pub struct GateSignature {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub kind: GateExpr,
+    pub qubits: usize,
+    pub params: usize,
+    pub inverse: Option<&'static str>,
+    pub basis: Option<Pauli>,
+    pub controls: Controls,
+    pub matrix: fn(&[f64]) -> Matrix,
+}
+
+impl GateSignature {
+    /// Whether the given name refers to this gate, either directly or through an alias.
+    /// Like keywords, gate names are case insensitive.
+    pub fn is_named(&self, name: &str) -> bool {
+        std::iter::once(self.name)
+            .chain(self.aliases.iter().copied())
+            .any(|other| other.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Looks up a gate by its name or any of its aliases
+pub fn lookup(name: &str) -> Option<&'static GateSignature> {
+    GATES.iter().find(|gate| gate.is_named(name))
+}
+
+/// Looks up a gate that a parsed program applies. The parser only builds gate
+/// applications for gates in the registry, so anything else is a bug.
+pub fn signature(name: &str) -> &'static GateSignature {
+    lookup(name).unwrap_or_else(|| panic!("{:?} isn't in the gate registry", name))
+}
+
+pub static GATES: &[GateSignature] = &[
+    // Single qubit gates
+    GateSignature {
+        name: "h",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("h"),
+        basis: None,
+        controls: Controls::None,
+        matrix: |_| {
+            real(&[
+                [FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+                [FRAC_1_SQRT_2, -FRAC_1_SQRT_2],
+            ])
+        },
+    },
+    GateSignature {
+        name: "x",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("x"),
+        basis: Some(Pauli::X),
+        controls: Controls::None,
+        matrix: |_| pauli_x(),
+    },
+    GateSignature {
+        name: "y",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("y"),
+        basis: Some(Pauli::Y),
+        controls: Controls::None,
+        matrix: |_| pauli_y(),
+    },
+    GateSignature {
+        name: "z",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("z"),
+        basis: Some(Pauli::Z),
+        controls: Controls::None,
+        matrix: |_| pauli_z(),
+    },
+    GateSignature {
+        name: "s",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("sadj"),
+        basis: Some(Pauli::Z),
+        controls: Controls::None,
+        matrix: |_| phase(std::f64::consts::FRAC_PI_2),
+    },
+    GateSignature {
+        name: "sadj",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("s"),
+        basis: Some(Pauli::Z),
+        controls: Controls::None,
+        matrix: |_| phase(-std::f64::consts::FRAC_PI_2),
+    },
+    GateSignature {
+        name: "t",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("tadj"),
+        basis: Some(Pauli::Z),
+        controls: Controls::None,
+        matrix: |_| phase(std::f64::consts::FRAC_PI_4),
+    },
+    GateSignature {
+        name: "tadj",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("t"),
+        basis: Some(Pauli::Z),
+        controls: Controls::None,
+        matrix: |_| phase(-std::f64::consts::FRAC_PI_4),
+    },
+    GateSignature {
+        name: "sx",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("sxadj"),
+        basis: Some(Pauli::X),
+        controls: Controls::None,
+        matrix: |_| sqrt_x(1.0),
+    },
+    GateSignature {
+        name: "sxadj",
+        aliases: &[],
+        kind: GateExpr::Q1Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("sx"),
+        basis: Some(Pauli::X),
+        controls: Controls::None,
+        matrix: |_| sqrt_x(-1.0),
+    },
+    // Single qubit parameterized gates
+    GateSignature {
+        name: "rx",
+        aliases: &[],
+        kind: GateExpr::Q1ParamGate,
+        qubits: 1,
+        params: 1,
+        inverse: None,
+        basis: Some(Pauli::X),
+        controls: Controls::None,
+        matrix: |p| rotation(&pauli_x(), p[0]),
+    },
+    GateSignature {
+        name: "ry",
+        aliases: &[],
+        kind: GateExpr::Q1ParamGate,
+        qubits: 1,
+        params: 1,
+        inverse: None,
+        basis: Some(Pauli::Y),
+        controls: Controls::None,
+        matrix: |p| rotation(&pauli_y(), p[0]),
+    },
+    GateSignature {
+        name: "rz",
+        aliases: &[],
+        kind: GateExpr::Q1ParamGate,
+        qubits: 1,
+        params: 1,
+        inverse: None,
+        basis: Some(Pauli::Z),
+        controls: Controls::None,
+        matrix: |p| rotation(&pauli_z(), p[0]),
+    },
+    GateSignature {
+        name: "u3",
+        aliases: &[],
+        kind: GateExpr::Q1ParamGate,
+        qubits: 1,
+        params: 3,
+        inverse: None,
+        basis: None,
+        controls: Controls::None,
+        matrix: |p| u3(p[0], p[1], p[2]),
+    },
+    // Double qubit gates
+    GateSignature {
+        name: "cnot",
+        aliases: &["cx"],
+        kind: GateExpr::Q2Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("cnot"),
+        basis: Some(Pauli::X),
+        controls: Controls::Exactly(1),
+        matrix: |_| pauli_x(),
+    },
+    GateSignature {
+        name: "cz",
+        aliases: &[],
+        kind: GateExpr::Q2Gate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("cz"),
+        basis: Some(Pauli::Z),
+        controls: Controls::Exactly(1),
+        matrix: |_| pauli_z(),
+    },
+    GateSignature {
+        name: "swap",
+        aliases: &[],
+        kind: GateExpr::Q2Gate,
+        qubits: 2,
+        params: 0,
+        inverse: Some("swap"),
+        basis: None,
+        controls: Controls::None,
+        matrix: |_| {
+            real(&[
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ])
+        },
+    },
+    // Double qubit parameterized gates
+    GateSignature {
+        name: "rxx",
+        aliases: &[],
+        kind: GateExpr::Q2ParamGate,
+        qubits: 2,
+        params: 1,
+        inverse: None,
+        basis: Some(Pauli::X),
+        controls: Controls::None,
+        matrix: |p| rotation(&kron(&pauli_x(), &pauli_x()), p[0]),
+    },
+    GateSignature {
+        name: "ryy",
+        aliases: &[],
+        kind: GateExpr::Q2ParamGate,
+        qubits: 2,
+        params: 1,
+        inverse: None,
+        basis: Some(Pauli::Y),
+        controls: Controls::None,
+        matrix: |p| rotation(&kron(&pauli_y(), &pauli_y()), p[0]),
+    },
+    GateSignature {
+        name: "rzz",
+        aliases: &[],
+        kind: GateExpr::Q2ParamGate,
+        qubits: 2,
+        params: 1,
+        inverse: None,
+        basis: Some(Pauli::Z),
+        controls: Controls::None,
+        matrix: |p| rotation(&kron(&pauli_z(), &pauli_z()), p[0]),
+    },
+    // Multi-controlled gates
+    GateSignature {
+        name: "toffoli",
+        aliases: &["ccx"],
+        kind: GateExpr::QMultiGate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("toffoli"),
+        basis: Some(Pauli::X),
+        controls: Controls::Exactly(2),
+        matrix: |_| pauli_x(),
+    },
+    GateSignature {
+        name: "mcx",
+        aliases: &[],
+        kind: GateExpr::QMultiGate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("mcx"),
+        basis: Some(Pauli::X),
+        controls: Controls::AtLeast(1),
+        matrix: |_| pauli_x(),
+    },
+    GateSignature {
+        name: "mcy",
+        aliases: &[],
+        kind: GateExpr::QMultiGate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("mcy"),
+        basis: Some(Pauli::Y),
+        controls: Controls::AtLeast(1),
+        matrix: |_| pauli_y(),
+    },
+    GateSignature {
+        name: "mcz",
+        aliases: &[],
+        kind: GateExpr::QMultiGate,
+        qubits: 1,
+        params: 0,
+        inverse: Some("mcz"),
+        basis: Some(Pauli::Z),
+        controls: Controls::AtLeast(1),
+        matrix: |_| pauli_z(),
+    },
+];
+
+// Helper functions for building the matrices in the table above
+
+fn real<const N: usize>(rows: &[[f64; N]; N]) -> Matrix {
+    rows.iter()
+        .map(|row| row.iter().map(|&x| Complex64::new(x, 0.0)).collect())
+        .collect()
+}
+
+fn pauli_x() -> Matrix {
+    real(&[[0.0, 1.0], [1.0, 0.0]])
+}
+
+fn pauli_y() -> Matrix {
+    vec![
+        vec![Complex64::new(0.0, 0.0), Complex64::new(0.0, -1.0)],
+        vec![Complex64::new(0.0, 1.0), Complex64::new(0.0, 0.0)],
+    ]
+}
+
+fn pauli_z() -> Matrix {
+    real(&[[1.0, 0.0], [0.0, -1.0]])
+}
+
+/// diag(1, e^(i * angle)), which covers s, t, and their adjoints
+fn phase(angle: f64) -> Matrix {
+    vec![
+        vec![Complex64::new(1.0, 0.0), Complex64::new(0.0, 0.0)],
+        vec![Complex64::new(0.0, 0.0), Complex64::from_polar(1.0, angle)],
+    ]
+}
+
+/// The square root of x, sx = [[1 + i, 1 - i], [1 - i, 1 + i]] / 2, or its
+/// inverse (the complex conjugate) when `sign` is -1
+fn sqrt_x(sign: f64) -> Matrix {
+    let (plus, minus) = (
+        Complex64::new(0.5, 0.5 * sign),
+        Complex64::new(0.5, -0.5 * sign),
+    );
+    vec![vec![plus, minus], vec![minus, plus]]
+}
+
+/// exp(-i * theta / 2 * P) for a Pauli string P, which squares to the identity,
+/// so the exponential is cos(theta / 2) * I - i * sin(theta / 2) * P
+fn rotation(pauli: &Matrix, theta: f64) -> Matrix {
+    let (sin, cos) = (theta / 2.0).sin_cos();
+    pauli
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(j, &p)| {
+                    let identity = if i == j { cos } else { 0.0 };
+                    Complex64::new(identity, 0.0) - Complex64::new(0.0, sin) * p
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn u3(theta: f64, phi: f64, lambda: f64) -> Matrix {
+    let (sin, cos) = (theta / 2.0).sin_cos();
+    vec![
+        vec![
+            Complex64::new(cos, 0.0),
+            -Complex64::from_polar(sin, lambda),
+        ],
+        vec![
+            Complex64::from_polar(sin, phi),
+            Complex64::from_polar(cos, phi + lambda),
+        ],
+    ]
+}
+
+/// The matrix product a * b, which applies b first and then a
+pub fn matmul(a: &Matrix, b: &Matrix) -> Matrix {
+    (0..a.len())
+        .map(|i| {
+            (0..b.len())
+                .map(|j| (0..b.len()).map(|k| a[i][k] * b[k][j]).sum())
+                .collect()
+        })
+        .collect()
+}
+
+/// The conjugate transpose of a matrix, which is its inverse when it's unitary
+pub fn dagger(matrix: &Matrix) -> Matrix {
+    (0..matrix.len())
+        .map(|i| (0..matrix.len()).map(|j| matrix[j][i].conj()).collect())
+        .collect()
+}
+
+/// The tensor (Kronecker) product of two matrices
+pub fn kron(a: &Matrix, b: &Matrix) -> Matrix {
+    let n = b.len();
+    (0..a.len() * n)
+        .map(|i| {
+            (0..a.len() * n)
+                .map(|j| a[i / n][j / n] * b[i % n][j % n])
+                .collect()
+        })
+        .collect()
+}
+
+/// Writes a single qubit unitary as e^(i * phase) * u3(theta, phi, lambda),
+/// returning (phase, [theta, phi, lambda]). The global phase is kept exactly,
+/// since it stops being global once the gate is controlled.
+pub fn u3_angles(unitary: &Matrix) -> (f64, [f64; 3]) {
+    const TOLERANCE: f64 = 1e-9;
+    let [a, b] = [unitary[0][0], unitary[0][1]];
+    let [c, d] = [unitary[1][0], unitary[1][1]];
+    // u3's top left entry is real and non-negative, so the phase is whatever makes it so. When
+    // it's zero (theta = PI), phi is free, and we pick the phase that makes it zero instead.
+    let phase = if a.norm() > TOLERANCE {
+        a.arg()
+    } else {
+        c.arg()
+    };
+    let rotate = Complex64::from_polar(1.0, -phase);
+    let (a, b, c, d) = (a * rotate, b * rotate, c * rotate, d * rotate);
+
+    let theta = 2.0 * c.norm().atan2(a.norm());
+    let phi = if c.norm() > TOLERANCE { c.arg() } else { 0.0 };
+    // Without the off diagonal entries (theta = 0), only the bottom right one, e^(i(phi + lambda)),
+    // is left to find lambda from
+    let lambda = if b.norm() > TOLERANCE {
+        (-b).arg()
+    } else {
+        d.arg() - phi
+    };
+    (phase, [theta, phi, lambda])
+}
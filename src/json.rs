@@ -0,0 +1,218 @@
+//! Just enough JSON for the language server and coupling maps: a value type,
+//! a parser, and printing values back out compactly
+use pest::iterators::Pair;
+use pest::Parser;
+use pest_derive::Parser;
+use std::fmt;
+
+#[derive(Parser)]
+#[grammar = "json.pest"]
+struct JsonParser;
+
+/// A JSON value. Objects keep their members in the order they were written.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(source: &str) -> Result<Json, String> {
+        let pair = JsonParser::parse(Rule::Json, source)
+            .map_err(|error| format!("Invalid JSON:\n{}", error))?
+            .next()
+            .unwrap();
+        Ok(build_value(pair))
+    }
+
+    /// The member of an object with the given key, if this is an object and
+    /// has one
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of keys down through nested objects
+    pub fn at(&self, path: &[&str]) -> Option<&Json> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The value as a non-negative whole number, if it is one
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(number) if number >= 0.0 && number.fract() == 0.0 => Some(number as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Builds an object out of (key, value) pairs
+pub fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+impl From<&str> for Json {
+    fn from(string: &str) -> Json {
+        Json::String(string.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(string: String) -> Json {
+        Json::String(string)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(number: usize) -> Json {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Json {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+fn build_value(pair: Pair<Rule>) -> Json {
+    match pair.as_rule() {
+        Rule::Object => Json::Object(
+            pair.into_inner()
+                .map(|member| {
+                    let mut inner = member.into_inner();
+                    let key = unescape(inner.next().unwrap().into_inner().as_str());
+                    (key, build_value(inner.next().unwrap()))
+                })
+                .collect(),
+        ),
+        Rule::Array => Json::Array(pair.into_inner().map(build_value).collect()),
+        Rule::String => Json::String(unescape(pair.into_inner().as_str())),
+        // The grammar only matches numbers Rust can read
+        Rule::Number => Json::Number(pair.as_str().parse().unwrap()),
+        Rule::True => Json::Bool(true),
+        Rule::False => Json::Bool(false),
+        Rule::Null => Json::Null,
+        rule => unreachable!("{:?} isn't a value", rule),
+    }
+}
+
+/// Decodes the escapes of a string the grammar has already checked.
+/// Characters outside the Basic Multilingual Plane are escaped as a pair of
+/// UTF-16 surrogates, and unpaired surrogates become U+FFFD.
+fn unescape(text: &str) -> String {
+    let mut string = String::new();
+    let mut chars = text.chars();
+    let mut units: Vec<u16> = vec![];
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.extend(char::decode_utf16(units.drain(..)).map(|c| c.unwrap_or('\u{FFFD}')));
+            string.push(c);
+            continue;
+        }
+        let escaped = chars.next().unwrap();
+        if escaped == 'u' {
+            let hex: String = chars.by_ref().take(4).collect();
+            units.push(u16::from_str_radix(&hex, 16).unwrap());
+            continue;
+        }
+        string.extend(char::decode_utf16(units.drain(..)).map(|c| c.unwrap_or('\u{FFFD}')));
+        string.push(match escaped {
+            'b' => '\u{08}',
+            'f' => '\u{0C}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            other => other,
+        });
+    }
+    string.extend(char::decode_utf16(units).map(|c| c.unwrap_or('\u{FFFD}')));
+    string
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+                write!(f, "{}", *number as i64)
+            }
+            Json::Number(number) if number.is_finite() => write!(f, "{}", number),
+            // JSON has no infinities or NaN
+            Json::Number(_) => write!(f, "null"),
+            Json::String(string) => write_string(f, string),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
@@ -1,4 +1,16 @@
 pub mod parser;
 pub mod ast;
+pub mod gates;
 pub mod optimizations;
 pub mod type_checker;
+pub mod linearity;
+pub mod circuit;
+pub mod formatter;
+pub mod simulator;
+pub mod backends;
+pub mod routing;
+pub mod repl;
+pub mod lsp;
+pub mod modules;
+pub mod composites;
+pub mod json;
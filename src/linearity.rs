@@ -0,0 +1,190 @@
+//! Dataflow pass tracking how each qubit is used over the course of a Quill
+//! program, which catches logic bugs that are well typed but almost certainly
+//! not what the author meant (like applying gates to a qubit that has already
+//! been measured)
+use crate::ast::{Program, Stmt, ValueExpr};
+use crate::type_checker::{checked_range, checked_width, SymbolTable};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The state of a single qubit at a given point in the program
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum QubitState {
+    /// Created (or reinitialized) and not yet touched by anything
+    Fresh,
+    /// Has had at least one gate applied since it was created
+    InUse,
+    /// Has been measured, and so collapsed, since it was last touched
+    Measured,
+}
+
+/// A non-fatal issue found while analyzing a program, tied to the line it was found on
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Warning {
+    pub line_no: i32,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: warning: {}", self.line_no, self.message)
+    }
+}
+
+/// Walks the program in order, tracking the state of every qubit and whether
+/// every cbit is ever written to. We warn when:
+/// - A gate is applied to a qubit after it was measured, without the qubit
+///   being reinitialized (through another assignment) in between
+/// - A qubit that is in use is reinitialized before it was ever measured,
+///   which silently throws its state away
+/// - A qubit is never used by any gate or measurement
+/// - A cbit is never written to by a measurement
+///
+/// This expects a program that has already passed the type checker, whose
+/// symbol table is used to look up the type of every variable. Slices are
+/// still checked against the widths variables have at each line, which is
+/// the only way this can fail.
+pub fn check_linearity(program: &Program, symbols: &SymbolTable) -> Result<Vec<Warning>, String> {
+    let mut warnings = vec![];
+    // Stores entries of <Variable Name, State of each qubit in the variable>
+    let mut qubits: HashMap<&str, Vec<QubitState>> = HashMap::new();
+    // Stores entries of <Variable Name, Whether each cbit in the variable was written>
+    let mut cbits: HashMap<&str, Vec<bool>> = HashMap::new();
+    // Every touched qubit is remembered, since reinitialization resets the state
+    let mut used: HashMap<&str, Vec<bool>> = HashMap::new();
+    // Variables in the order (and on the line) they were first declared, for reporting
+    let mut declared: Vec<(&str, i32)> = vec![];
+    // Widths as of the current line, since a variable may be redeclared with a new width
+    let mut widths: HashMap<String, usize> = HashMap::new();
+
+    for stmt in &program.stmts {
+        let line_no = stmt.line_no;
+        match &stmt.kind {
+            Stmt::Assign { name, value, .. } => {
+                let name = name.as_str();
+                let width = checked_width(value, line_no)?;
+                widths.insert(name.to_string(), width);
+                match symbols.types[name] {
+                    ValueExpr::Qubit | ValueExpr::QReg => {
+                        if let Some(states) = qubits.get(name) {
+                            let discarded: Vec<usize> = indices_in(states, QubitState::InUse);
+                            if !discarded.is_empty() {
+                                warnings.push(Warning {
+                                    line_no,
+                                    message: format!(
+                                        "{} is reinitialized while {} still in use, so {} state is discarded without ever being measured",
+                                        describe(name, &discarded, width),
+                                        if discarded.len() == 1 { "it is" } else { "they are" },
+                                        if discarded.len() == 1 { "its" } else { "their" }
+                                    ),
+                                });
+                            }
+                        } else {
+                            declared.push((name, line_no));
+                        }
+                        let touched = used.entry(name).or_default();
+                        touched.resize(touched.len().max(width), false);
+                        qubits.insert(name, vec![QubitState::Fresh; width]);
+                    }
+                    ValueExpr::CBit | ValueExpr::CReg => {
+                        if !cbits.contains_key(name) {
+                            declared.push((name, line_no));
+                        }
+                        let written = cbits.entry(name).or_default();
+                        written.resize(written.len().max(width), false);
+                    }
+                }
+            }
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                ..
+            } => {
+                for operand in targets.iter().chain(controls) {
+                    let (name, start, end) = checked_range(operand, &widths, line_no)?;
+                    let states = qubits.get_mut(name).unwrap();
+                    let measured: Vec<usize> = (start..=end)
+                        .filter(|&i| states[i] == QubitState::Measured)
+                        .collect();
+                    if !measured.is_empty() {
+                        warnings.push(Warning {
+                            line_no,
+                            message: format!(
+                                "{} is applied to {} after it was measured, without reinitializing it first",
+                                gate,
+                                describe(name, &measured, states.len())
+                            ),
+                        });
+                    }
+                    states[start..=end].fill(QubitState::InUse);
+                    used.get_mut(name).unwrap()[start..=end].fill(true);
+                }
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let (qname, qstart, qend) = checked_range(measured, &widths, line_no)?;
+                let (cname, cstart, cend) = checked_range(recipient, &widths, line_no)?;
+                qubits.get_mut(qname).unwrap()[qstart..=qend].fill(QubitState::Measured);
+                used.get_mut(qname).unwrap()[qstart..=qend].fill(true);
+                cbits.get_mut(cname).unwrap()[cstart..=cend].fill(true);
+            }
+            Stmt::Return { .. } | Stmt::Comment(_) => {}
+            _ => unreachable!("modules are linked before analysis"),
+        }
+    }
+
+    for (name, decl_line) in declared {
+        let width = widths[name].max(used.get(name).or(cbits.get(name)).unwrap().len());
+        if let Some(touched) = used.get(name) {
+            let unused = indices_in(touched, false);
+            if !unused.is_empty() {
+                warnings.push(Warning {
+                    line_no: decl_line,
+                    message: format!(
+                        "{} is never used by any gate or measurement",
+                        describe(name, &unused, width)
+                    ),
+                });
+            }
+        } else if let Some(written) = cbits.get(name) {
+            let unwritten = indices_in(written, false);
+            if !unwritten.is_empty() {
+                warnings.push(Warning {
+                    line_no: decl_line,
+                    message: format!(
+                        "{} is never written to by a measurement",
+                        describe(name, &unwritten, width)
+                    ),
+                });
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Collects the indices of every element equal to the given value
+fn indices_in<T: PartialEq>(elements: &[T], value: T) -> Vec<usize> {
+    elements
+        .iter()
+        .enumerate()
+        .filter(|(_, elem)| **elem == value)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Names the given elements of a variable, e.g. `q1`, `qr[2]`, or `qr[0, 3]`,
+/// using the bare name when the whole variable is covered
+fn describe(name: &str, indices: &[usize], width: usize) -> String {
+    if indices.len() == width && width == 1 {
+        return name.to_string();
+    }
+    if indices.len() == width {
+        return format!("all of {}", name);
+    }
+    let indices: Vec<String> = indices.iter().map(|i| i.to_string()).collect();
+    format!("{}[{}]", name, indices.join(", "))
+}
@@ -0,0 +1,557 @@
+//! What the language server knows about a Quill file: its diagnostics, what to
+//! show on hover, where variables are defined, what to complete, and how to
+//! highlight it. Everything past the diagnostics works line by line, so it
+//! keeps working while the file being edited doesn't parse.
+use crate::ast::{GateExpr, RespectExpr, Stmt, ValueExpr};
+use crate::composites::{self, COMPOSITES};
+use crate::gates::{self, Controls, GATES};
+use crate::linearity::check_linearity;
+use crate::modules::link;
+use crate::parser::{dialect_of, is_modern_keyword, parse, parse_statement, Dialect};
+use crate::type_checker::{check_statement, type_check, SymbolTable};
+use pest::error::LineColLocation;
+
+/// Every keyword of the language, with what it means in modern English
+pub const KEYWORDS: &[(&str, &str)] = &[
+    ("Maistow", "\"may thou\": declares a variable, respectfully"),
+    ("Canstow", "\"can thou\": declares a variable"),
+    ("create", "part of a declaration"),
+    ("oo", "\"one\": part of a declaration"),
+    ("with", "introduces a value or a gate's parameters"),
+    ("value", "part of a declaration"),
+    ("Thy", "\"your\": applies a gate"),
+    ("shalt", "\"shall\": part of a gate application"),
+    ("target", "names the qubits a gate acts on"),
+    ("and", "joins a second target or the controls"),
+    ("control", "introduces the qubits a gate is controlled on"),
+    ("on", "introduces the qubits a gate is controlled on"),
+    ("Rede", "\"read\": measures qubits"),
+    (
+        "quyken",
+        "\"quicken\": names the cbits a measurement goes into",
+    ),
+    (
+        "Return",
+        "ends the program, running it the given number of times",
+    ),
+    // The modern dialect
+    ("please", "makes a declaration respectful, like Maistow"),
+    ("let", "declares a variable, like Canstow"),
+    ("apply", "applies a gate, like Thy"),
+    ("to", "names the qubits a gate acts on"),
+    (
+        "controlled",
+        "introduces the qubits a gate is controlled on",
+    ),
+    ("by", "introduces the qubits a gate is controlled on"),
+    ("measure", "measures qubits, like Rede"),
+    ("into", "names the cbits a measurement goes into"),
+    // Modules
+    (
+        "Fecche",
+        "\"fetch\": imports the gates and constants of another file",
+    ),
+    ("Devyse", "\"devise\": defines a gate out of other gates"),
+    ("Ende", "\"end\": ends a gate's definition"),
+    ("Certes", "\"certainly\": declares a constant"),
+    ("import", "imports another file, like Fecche"),
+    ("gate", "defines a gate, like Devyse"),
+    ("end", "ends a gate's definition, like Ende"),
+    ("const", "declares a constant, like Certes"),
+];
+
+/// The types a variable can be declared with
+pub const TYPES: &[&str] = &["qubit", "qreg", "cbit", "creg"];
+
+/// A range within a single line. Columns count UTF-16 code units, like the
+/// Language Server Protocol does.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// The kinds of token that get highlighted. The order is the legend the
+/// server advertises, so a kind's index is its token type.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Gate,
+    Variable,
+    Number,
+    Comment,
+}
+
+/// The name of each `TokenKind` in the protocol, in legend order
+pub const TOKEN_TYPES: &[&str] = &[
+    "keyword", "type", "function", "variable", "number", "comment",
+];
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Token {
+    pub span: Span,
+    pub kind: TokenKind,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CompletionKind {
+    Keyword,
+    Type,
+    Gate,
+    Variable,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: String,
+}
+
+/// A variable being assigned to, and the span of its name
+struct Declaration {
+    name: String,
+    respect: RespectExpr,
+    ty: ValueExpr,
+    /// Left out when the value is malformed (like a register of 0 qubits)
+    width: Option<usize>,
+    span: Span,
+}
+
+/// The syntax errors of the file, or its type errors if it parses, followed by
+/// the linearity warnings of a file that checks. Imports are found relative
+/// to the working directory.
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    diagnostics_at(source, "untitled.ql")
+}
+
+/// The diagnostics of the file at `path`, whose imports are found relative to
+/// the directory it's in
+pub fn diagnostics_at(source: &str, path: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = source.lines().collect();
+    let program = match parse(source) {
+        Ok(program) => program,
+        Err(errors) => {
+            return errors
+                .errors
+                .into_iter()
+                .map(|error| {
+                    let (line, column) = match error.line_col {
+                        LineColLocation::Pos(position) => position,
+                        LineColLocation::Span(start, _) => start,
+                    };
+                    let line = line.saturating_sub(1).min(lines.len().saturating_sub(1));
+                    let text = lines.get(line).copied().unwrap_or("");
+                    let offset = text
+                        .char_indices()
+                        .nth(column.saturating_sub(1))
+                        .map_or(text.len(), |(offset, _)| offset);
+                    // Underline the word the error points at, or a single character
+                    let end = word_end(text, offset).max(next_char(text, offset));
+                    Diagnostic {
+                        span: span(text, line, offset, end),
+                        severity: Severity::Error,
+                        message: error.variant.message().to_string(),
+                    }
+                })
+                .collect();
+        }
+    };
+    // Problems in the files it imports go on the first line
+    let program = match link(&program, path) {
+        Ok(program) => program,
+        Err(error) => {
+            let (line_no, message) = match error.strip_prefix(path).and_then(|rest| {
+                let (line_no, message) = rest.strip_prefix(':')?.split_once(": ")?;
+                Some((line_no.parse::<usize>().ok()?, message.to_string()))
+            }) {
+                Some(found) => found,
+                None => (1, error),
+            };
+            return vec![Diagnostic {
+                span: line_span(&lines, line_no),
+                severity: Severity::Error,
+                message,
+            }];
+        }
+    };
+    let checked = type_check(&program).and_then(|symbols| check_linearity(&program, &symbols));
+    let warnings = match checked {
+        Ok(warnings) => warnings,
+        Err(error) => {
+            // Errors start with their line number, except for a misplaced Return
+            let (line_no, message) = match error.split_once(": ") {
+                Some((line_no, message)) if line_no.parse::<usize>().is_ok() => {
+                    (line_no.parse::<usize>().unwrap(), message.to_string())
+                }
+                _ => (1, error.clone()),
+            };
+            return vec![Diagnostic {
+                span: line_span(&lines, line_no),
+                severity: Severity::Error,
+                message,
+            }];
+        }
+    };
+    warnings
+        .into_iter()
+        .map(|warning| Diagnostic {
+            span: line_span(&lines, warning.line_no as usize),
+            severity: Severity::Warning,
+            message: warning.message,
+        })
+        .collect()
+}
+
+/// What to show when hovering over a variable (its type and width), a gate
+/// (its signature), or a keyword (what it means)
+pub fn hover(source: &str, line: usize, character: usize) -> Option<String> {
+    let text = source.lines().nth(line)?;
+    let (start, end, kind) = token_at(text, character, dialect_of(source))?;
+    let word = &text[start..end];
+    match kind {
+        TokenKind::Keyword => KEYWORDS
+            .iter()
+            .find(|(keyword, _)| keyword.eq_ignore_ascii_case(word))
+            .map(|(keyword, meaning)| format!("`{}`: {}", keyword, meaning)),
+        TokenKind::Gate => match composites::lookup(word) {
+            Some(composite) => Some(format!("`{}`: {}", word, composite.description)),
+            None => gates::lookup(word).map(|gate| format!("`{}`: {}", word, signature(gate))),
+        },
+        TokenKind::Variable => {
+            let declarations = declarations(source);
+            let declaration = binding(&declarations, word, line)?;
+            let ty = match declaration.ty {
+                ValueExpr::Qubit => "qubit".to_string(),
+                ValueExpr::CBit => "cbit".to_string(),
+                ValueExpr::QReg => plural("qreg of", declaration.width, "qubit"),
+                ValueExpr::CReg => plural("creg of", declaration.width, "cbit"),
+            };
+            Some(format!(
+                "`{}`: {} (declared on line {} with {:?})",
+                word,
+                ty,
+                declaration.span.line + 1,
+                declaration.respect
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn plural(ty: &str, width: Option<usize>, element: &str) -> String {
+    match width {
+        Some(1) => format!("{} 1 {}", ty, element),
+        Some(width) => format!("{} {} {}s", ty, width, element),
+        None => ty.trim_end_matches(" of").to_string(),
+    }
+}
+
+/// Where the variable at the given position was assigned, which is the last
+/// assignment to it on or before the line (or the first one at all, for
+/// uses before it's declared)
+pub fn definition(source: &str, line: usize, character: usize) -> Option<Span> {
+    let text = source.lines().nth(line)?;
+    let (start, end, kind) = token_at(text, character, dialect_of(source))?;
+    if kind != TokenKind::Variable {
+        return None;
+    }
+    binding(&declarations(source), &text[start..end], line).map(|declaration| declaration.span)
+}
+
+/// What could go at the given position: statement keywords (of the file's
+/// dialect) at the start of a line, gates after "Thy" or "apply", types after
+/// "oo" or "let", and variables anywhere else
+pub fn completions(source: &str, line: usize, character: usize) -> Vec<Completion> {
+    let starts = match dialect_of(source) {
+        Dialect::MiddleEnglish => ["Maistow", "Canstow", "Thy", "Rede", "Return"],
+        Dialect::Modern => ["let", "please", "apply", "measure", "return"],
+    };
+    let text = source.lines().nth(line).unwrap_or("");
+    let before = &text[..byte_offset(text, character)];
+    // Keywords are case insensitive
+    let mut words: Vec<String> = before.split_whitespace().map(str::to_lowercase).collect();
+    // The word being typed doesn't count as context
+    if !before.ends_with(char::is_whitespace) {
+        words.pop();
+    }
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    match words.as_slice() {
+        [] => starts
+            .iter()
+            .map(|keyword| Completion {
+                label: keyword.to_string(),
+                kind: CompletionKind::Keyword,
+                detail: KEYWORDS
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(keyword))
+                    .unwrap()
+                    .1
+                    .to_string(),
+            })
+            .collect(),
+        ["thy" | "apply"] => GATES
+            .iter()
+            .flat_map(|gate| {
+                let detail = signature(gate);
+                std::iter::once(gate.name)
+                    .chain(gate.aliases.iter().copied())
+                    .map(move |name| Completion {
+                        label: name.to_string(),
+                        kind: CompletionKind::Gate,
+                        detail: detail.clone(),
+                    })
+            })
+            .chain(COMPOSITES.iter().map(|composite| Completion {
+                label: composite.name.to_string(),
+                kind: CompletionKind::Gate,
+                detail: composite.description.to_string(),
+            }))
+            .collect(),
+        [.., "oo" | "let"] => TYPES
+            .iter()
+            .map(|ty| Completion {
+                label: ty.to_string(),
+                kind: CompletionKind::Type,
+                detail: "type".to_string(),
+            })
+            .collect(),
+        _ => {
+            let mut completions: Vec<Completion> = vec![];
+            for declaration in declarations(source) {
+                if completions.iter().all(|c| c.label != declaration.name) {
+                    completions.push(Completion {
+                        label: declaration.name,
+                        kind: CompletionKind::Variable,
+                        detail: format!("{:?}", declaration.ty).to_lowercase(),
+                    });
+                }
+            }
+            completions
+        }
+    }
+}
+
+/// Every token worth highlighting, in order
+pub fn semantic_tokens(source: &str) -> Vec<Token> {
+    let dialect = dialect_of(source);
+    let mut tokens = vec![];
+    for (line, text) in source.lines().enumerate() {
+        for (start, end, kind) in tokens_of(text, dialect) {
+            tokens.push(Token {
+                span: span(text, line, start, end),
+                kind,
+            });
+        }
+    }
+    tokens
+}
+
+/// Describes what a gate takes, and what kind of gate it is
+fn signature(gate: &gates::GateSignature) -> String {
+    let kind = match gate.kind {
+        GateExpr::Q1Gate | GateExpr::Q1ParamGate => "single qubit gate",
+        GateExpr::Q2Gate | GateExpr::Q2ParamGate => "two qubit gate",
+        GateExpr::ToffoliGate | GateExpr::QMultiGate => "multi-qubit gate",
+    };
+    let mut parts = vec![match gate.qubits {
+        1 => "1 target".to_string(),
+        n => format!("{} targets", n),
+    }];
+    match gate.controls {
+        Controls::None => {}
+        Controls::Exactly(1) => parts.push("1 control".to_string()),
+        Controls::Exactly(n) => parts.push(format!("{} controls", n)),
+        Controls::AtLeast(n) => parts.push(format!("{} or more controls", n)),
+    }
+    match gate.params {
+        0 => {}
+        1 => parts.push("1 parameter".to_string()),
+        n => parts.push(format!("{} parameters", n)),
+    }
+    let mut description = format!("{} ({})", kind, parts.join(", "));
+    if !gate.aliases.is_empty() {
+        description.push_str(&format!(", also called {}", gate.aliases.join(", ")));
+    }
+    description
+}
+
+/// Every line that parses as an assignment on its own, so that a mistake
+/// elsewhere in the file doesn't hide the variables
+fn declarations(source: &str) -> Vec<Declaration> {
+    let dialect = dialect_of(source);
+    let mut declarations = vec![];
+    for (line, text) in source.lines().enumerate() {
+        let stmt = match parse_statement(text, line as i32 + 1, dialect) {
+            Ok(stmt) => stmt,
+            Err(_) => continue,
+        };
+        if let Stmt::Assign {
+            respect, ty, name, ..
+        } = &stmt.kind
+        {
+            let mut symbols = SymbolTable::default();
+            let width = check_statement(&stmt, &mut symbols)
+                .ok()
+                .map(|_| symbols.widths[name]);
+            // The name is the only variable in an assignment
+            let (start, end, _) = tokens_of(text, dialect)
+                .into_iter()
+                .find(|(_, _, kind)| *kind == TokenKind::Variable)
+                .unwrap();
+            declarations.push(Declaration {
+                name: name.clone(),
+                respect: *respect,
+                ty: *ty,
+                width,
+                span: span(text, line, start, end),
+            });
+        }
+    }
+    declarations
+}
+
+/// The assignment in effect for a variable used on the given line
+fn binding<'a>(
+    declarations: &'a [Declaration],
+    name: &str,
+    line: usize,
+) -> Option<&'a Declaration> {
+    let mut named = declarations.iter().filter(|d| d.name == name);
+    let first = named.clone().next();
+    named.rfind(|d| d.span.line <= line).or(first)
+}
+
+/// The span of a whole line (1-indexed, as statements number them) without
+/// its indentation
+fn line_span(lines: &[&str], line_no: usize) -> Span {
+    let line = line_no.saturating_sub(1).min(lines.len().saturating_sub(1));
+    let text = lines.get(line).copied().unwrap_or("");
+    let start = text.len() - text.trim_start().len();
+    span(text, line, start, text.trim_end().len().max(start))
+}
+
+/// Splits a line into the tokens that get highlighted, as (start byte, end
+/// byte, kind). Gates are the words right after "Thy", "apply", "Devyse", or
+/// "gate", and operators (like brackets, "+", and "..") and paths aren't
+/// highlighted. Modern keywords are only keywords in modern English files.
+fn tokens_of(text: &str, dialect: Dialect) -> Vec<(usize, usize, TokenKind)> {
+    let mut tokens = vec![];
+    let mut previous = "";
+    let mut offset = 0;
+    while offset < text.len() {
+        let rest = &text[offset..];
+        if rest.starts_with("//") {
+            tokens.push((offset, text.len(), TokenKind::Comment));
+            break;
+        }
+        // The path of an import
+        if let Some(path) = rest.strip_prefix('"') {
+            offset += path.find('"').map_or(rest.len(), |end| end + 2);
+            continue;
+        }
+        let c = rest.chars().next().unwrap();
+        if !c.is_ascii_alphanumeric() {
+            offset += c.len_utf8();
+            continue;
+        }
+        let end = word_end(text, offset);
+        let word = &text[offset..end];
+        // Numbers may have a decimal point
+        let end =
+            if c.is_ascii_digit() && text[end..].starts_with('.') && !text[end..].starts_with("..")
+            {
+                word_end(text, end + 1)
+            } else {
+                end
+            };
+        let is = |keyword: &str| keyword.eq_ignore_ascii_case(word);
+        let keyword = KEYWORDS.iter().any(|(keyword, _)| is(keyword))
+            && (dialect == Dialect::Modern || !is_modern_keyword(word));
+        let kind = if keyword {
+            TokenKind::Keyword
+        } else if TYPES.iter().any(|ty| is(ty)) {
+            TokenKind::Type
+        } else if c.is_ascii_digit() || ["b0", "b1"].contains(&word) || is("PI") {
+            TokenKind::Number
+        } else if ["Thy", "apply", "Devyse", "gate"]
+            .iter()
+            .any(|keyword| previous.eq_ignore_ascii_case(keyword))
+        {
+            TokenKind::Gate
+        } else {
+            TokenKind::Variable
+        };
+        // A negative number takes its sign along
+        let start = match text[..offset].strip_suffix('-') {
+            Some(before)
+                if kind == TokenKind::Number && !before.ends_with(char::is_alphanumeric) =>
+            {
+                offset - 1
+            }
+            _ => offset,
+        };
+        tokens.push((start, end, kind));
+        previous = word;
+        offset = end;
+    }
+    tokens
+}
+
+/// The token under (or just before) the given column, as in `tokens_of`
+fn token_at(text: &str, character: usize, dialect: Dialect) -> Option<(usize, usize, TokenKind)> {
+    let offset = byte_offset(text, character);
+    tokens_of(text, dialect)
+        .into_iter()
+        .find(|(start, end, _)| *start <= offset && offset <= *end)
+}
+
+/// The end of the alphanumeric word starting at the given byte
+fn word_end(text: &str, offset: usize) -> usize {
+    text[offset..]
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .map_or(text.len(), |length| offset + length)
+}
+
+/// The byte after the character at the given byte
+fn next_char(text: &str, offset: usize) -> usize {
+    text[offset..]
+        .chars()
+        .next()
+        .map_or(offset, |c| offset + c.len_utf8())
+}
+
+fn span(text: &str, line: usize, start: usize, end: usize) -> Span {
+    Span {
+        line,
+        start: text[..start].encode_utf16().count(),
+        end: text[..end].encode_utf16().count(),
+    }
+}
+
+/// The byte of the line at the given column, counted in UTF-16 code units
+fn byte_offset(text: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (offset, c) in text.char_indices() {
+        if units >= character {
+            return offset;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
@@ -0,0 +1,342 @@
+//! A Language Server Protocol server for Quill, which editors talk to over
+//! stdio. It keeps every open file in memory (always sent whole), and answers
+//! from `analysis`.
+pub mod analysis;
+
+use crate::json::{object, Json};
+use analysis::{CompletionKind, Severity, Span, TOKEN_TYPES};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// JSON-RPC error codes
+const PARSE_ERROR: usize = 32700;
+const INVALID_REQUEST: usize = 32600;
+const METHOD_NOT_FOUND: usize = 32601;
+const INVALID_PARAMS: usize = 32602;
+
+/// Reads messages from `input` and answers them on `output` until the client
+/// says to exit or hangs up. Returns whether it was asked to shut down first,
+/// which is what decides the exit code.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::default();
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                write_message(
+                    &mut output,
+                    &error_response(Json::Null, PARSE_ERROR, &error),
+                )?;
+                continue;
+            }
+        };
+        let method = match message.get("method").and_then(Json::as_str) {
+            Some(method) => method,
+            // Responses to requests we never send
+            None if message.get("id").is_some() && message.get("method").is_none() => continue,
+            None => {
+                let id = message.get("id").cloned().unwrap_or(Json::Null);
+                let reply = error_response(id, INVALID_REQUEST, "Messages need a method!");
+                write_message(&mut output, &reply)?;
+                continue;
+            }
+        };
+        if method == "exit" {
+            return Ok(server.shut_down);
+        }
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let reply = server.handle(method, &params);
+        for notification in server.notifications.drain(..) {
+            write_message(&mut output, &notification)?;
+        }
+        // Notifications (which have no id) never get an answer
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        let response = match reply {
+            Ok(result) => object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                ("result", result),
+            ]),
+            Err((code, message)) => error_response(id, code, &message),
+        };
+        write_message(&mut output, &response)?;
+    }
+    Ok(false)
+}
+
+#[derive(Default)]
+struct Server {
+    /// Stores entries of <Document URI, Its text>
+    documents: HashMap<String, String>,
+    /// Notifications to send once the current message is handled
+    notifications: Vec<Json>,
+    shut_down: bool,
+}
+
+/// A JSON-RPC error, as (code without its minus sign, message)
+type Failure = (usize, String);
+
+impl Server {
+    fn handle(&mut self, method: &str, params: &Json) -> Result<Json, Failure> {
+        match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "textDocument/didOpen" => {
+                let uri = string(params, &["textDocument", "uri"])?;
+                let text = string(params, &["textDocument", "text"])?;
+                self.open(uri, text.to_string());
+                Ok(Json::Null)
+            }
+            "textDocument/didChange" => {
+                let uri = string(params, &["textDocument", "uri"])?;
+                // We ask for whole documents, so the last change has all of it
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str)
+                    .ok_or_else(|| invalid("contentChanges"))?;
+                self.open(uri, text.to_string());
+                Ok(Json::Null)
+            }
+            "textDocument/didClose" => {
+                let uri = string(params, &["textDocument", "uri"])?;
+                self.documents.remove(uri);
+                self.notifications.push(diagnostics(uri, vec![]));
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => {
+                let (source, line, character) = self.position(params)?;
+                Ok(match analysis::hover(source, line, character) {
+                    Some(text) => object(vec![(
+                        "contents",
+                        object(vec![("kind", "markdown".into()), ("value", text.into())]),
+                    )]),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/definition" => {
+                let (source, line, character) = self.position(params)?;
+                let uri = string(params, &["textDocument", "uri"])?;
+                Ok(match analysis::definition(source, line, character) {
+                    Some(span) => object(vec![("uri", uri.into()), ("range", range(span))]),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/completion" => {
+                let (source, line, character) = self.position(params)?;
+                let items = analysis::completions(source, line, character)
+                    .into_iter()
+                    .map(|completion| {
+                        // The protocol's CompletionItemKind numbers
+                        let kind: usize = match completion.kind {
+                            CompletionKind::Keyword => 14,
+                            CompletionKind::Type => 25,
+                            CompletionKind::Gate => 3,
+                            CompletionKind::Variable => 6,
+                        };
+                        object(vec![
+                            ("label", completion.label.into()),
+                            ("kind", kind.into()),
+                            ("detail", completion.detail.into()),
+                        ])
+                    })
+                    .collect::<Vec<Json>>();
+                Ok(items.into())
+            }
+            "textDocument/semanticTokens/full" => {
+                let uri = string(params, &["textDocument", "uri"])?;
+                let source = self.document(uri)?;
+                // Each token is relative to the one before it
+                let mut data: Vec<usize> = vec![];
+                let (mut line, mut start) = (0, 0);
+                for token in analysis::semantic_tokens(source) {
+                    if token.span.line != line {
+                        start = 0;
+                    }
+                    data.extend([
+                        token.span.line - line,
+                        token.span.start - start,
+                        token.span.end - token.span.start,
+                        token.kind as usize,
+                        0,
+                    ]);
+                    line = token.span.line;
+                    start = token.span.start;
+                }
+                Ok(object(vec![("data", data.into())]))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("{} isn't supported!", method))),
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: String) {
+        // Imports are found next to the file, when it's on disk
+        let path = uri.strip_prefix("file://").unwrap_or("untitled.ql");
+        let found = analysis::diagnostics_at(&text, path)
+            .into_iter()
+            .map(|diagnostic| {
+                let severity: usize = match diagnostic.severity {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                };
+                object(vec![
+                    ("range", range(diagnostic.span)),
+                    ("severity", severity.into()),
+                    ("source", "quill".into()),
+                    ("message", diagnostic.message.into()),
+                ])
+            })
+            .collect();
+        self.notifications.push(diagnostics(uri, found));
+        self.documents.insert(uri.to_string(), text);
+    }
+
+    fn document(&self, uri: &str) -> Result<&str, Failure> {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, format!("{} isn't open!", uri)))
+    }
+
+    /// The document and position a request is about
+    fn position(&self, params: &Json) -> Result<(&str, usize, usize), Failure> {
+        let source = self.document(string(params, &["textDocument", "uri"])?)?;
+        let number = |key: &str| {
+            params
+                .at(&["position", key])
+                .and_then(Json::as_usize)
+                .ok_or_else(|| invalid(&format!("position.{}", key)))
+        };
+        Ok((source, number("line")?, number("character")?))
+    }
+}
+
+/// What the server can do, in answer to "initialize"
+fn capabilities() -> Json {
+    let legend = object(vec![
+        ("tokenTypes", TOKEN_TYPES.to_vec().into()),
+        ("tokenModifiers", Vec::<Json>::new().into()),
+    ]);
+    object(vec![
+        (
+            "capabilities",
+            object(vec![
+                // Whole documents are sent on every change
+                ("textDocumentSync", 1.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                (
+                    "completionProvider",
+                    object(vec![("triggerCharacters", vec![" "].into())]),
+                ),
+                (
+                    "semanticTokensProvider",
+                    object(vec![("legend", legend), ("full", true.into())]),
+                ),
+            ]),
+        ),
+        (
+            "serverInfo",
+            object(vec![
+                ("name", "quill-lsp".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+fn string<'a>(params: &'a Json, path: &[&str]) -> Result<&'a str, Failure> {
+    params
+        .at(path)
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid(&path.join(".")))
+}
+
+fn invalid(field: &str) -> Failure {
+    (
+        INVALID_PARAMS,
+        format!("{} is missing or has the wrong type!", field),
+    )
+}
+
+fn range(span: Span) -> Json {
+    let position = |character: usize| {
+        object(vec![
+            ("line", span.line.into()),
+            ("character", character.into()),
+        ])
+    };
+    object(vec![
+        ("start", position(span.start)),
+        ("end", position(span.end)),
+    ])
+}
+
+fn diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", diagnostics.into()),
+            ]),
+        ),
+    ])
+}
+
+fn error_response(id: Json, code: usize, message: &str) -> Json {
+    object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            object(vec![
+                ("code", Json::Number(-(code as f64))),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+/// Reads the body of the next message, or None once the input ends
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            // Blank lines between messages aren't a header block's end
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap()];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
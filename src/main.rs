@@ -1,25 +1,440 @@
+use quill::ast::{ASTNode, Program};
+use quill::backends::{qasm, qasm3, qir, qiskit};
+use quill::formatter::{first_difference, format, FormatConfig};
+use quill::gates;
+use quill::linearity::*;
+use quill::modules::{link, located};
+use quill::optimizations::translation::BasisTranslation;
 use quill::optimizations::*;
 use quill::parser::*;
+use quill::repl::Session;
+use quill::routing::{route, CouplingMap};
+use quill::simulator::{self, Rng};
 use quill::type_checker::*;
+use std::io::{IsTerminal, Read, Write};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use quill::ast::ASTNode;
+const USAGE: &str = "Usage: quill <command> [options] [file]
 
-fn main() {
-    // TODO: Make a CLI interface for running Quill with .ql files
-    // Files I've tested: test.quill, assignments.quill
-    let raw_file: String =
-        std::fs::read_to_string("src/test.quill").expect("can't read quill file");
-    let ast = parse(&raw_file).expect("failed parse");
-    // println!("{:?} \n -- \n", ast);
+Commands:
+  check      Parse and type check the program, and warn about likely mistakes
+  run        Simulate the program and print a histogram of its outcomes
+               --shots <n>   How many times to run it (default: its Return, or 1024)
+               --seed <n>    Seed the measurements, for repeatable runs
+  build      Compile the program, after the optimizations its respect earns it
+               --target <t>  One of qasm, qasm3, qir, or qiskit (required)
+               --coupling <file>  Route onto the device in this JSON coupling map
+               --keep-declarations  Keep qubits nothing uses, instead of removing them
+               -o <file>     Write the output here instead of to stdout
+  repl       Run statements one at a time as they're typed (:help lists its commands)
+               --seed <n>    Seed the measurements, for repeatable runs
+  fmt        Print the program in canonical form
+               --check       Print nothing, and fail if the program isn't in canonical form
+               --prefer <names>  Write these gates under the given aliases (like cx,ccx)
+  translate  Print the program in the other dialect, in canonical form
+               --dialect <d> Either modern or middle-english (required)
+               --prefer <names>  Write these gates under the given aliases (like cx,ccx)
+               -o <file>     Write the output here instead of to stdout
+  ast        Print the syntax tree of the program
+  help       Print this message
 
-    // NOTE: The threshold is to check if we use the optimizations!
-    let threshold_passed = respect_ratio(&ast);
-    println!("{}", threshold_passed);
+The program is read from the given file, or from stdin if there is none (or it's -).
 
-    type_check(&ast);
+Exit codes: 0 on success, 1 if the program has errors, 2 if the command line
+is wrong or a file can't be read or written.";
 
-    // println!("{:?}", ast); // this works now
-    ASTNode::print_nodes(&ast, 0);
+/// Why a command failed, which decides the exit code
+enum Failure {
+    /// The program itself is wrong
+    Program(String),
+    /// The command line is wrong
+    Usage(String),
+    /// A file couldn't be read or written
+    Io(String),
+}
+
+/// The command line, split into the command, its options, and the file
+#[derive(Default)]
+struct Options {
+    command: String,
+    path: Option<String>,
+    shots: Option<usize>,
+    seed: Option<u64>,
+    target: Option<String>,
+    coupling: Option<String>,
+    output: Option<String>,
+    check: bool,
+    keep_declarations: bool,
+    prefer: Option<String>,
+    dialect: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, Failure> {
+    let mut args = args.iter();
+    let mut options = Options {
+        command: args.next().cloned().unwrap_or_else(|| "help".to_string()),
+        ..Options::default()
+    };
+    let allowed: &[&str] = match options.command.as_str() {
+        "run" => &["--shots", "--seed"],
+        "repl" => &["--seed"],
+        "build" => &["--target", "--coupling", "--keep-declarations", "-o"],
+        "fmt" => &["--check", "--prefer"],
+        "translate" => &["--dialect", "--prefer", "-o"],
+        "check" | "ast" | "help" => &[],
+        command => return Err(Failure::Usage(format!("Unknown command {:?}!", command))),
+    };
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            if options.path.is_some() {
+                return Err(Failure::Usage("Only one file can be given!".to_string()));
+            }
+            options.path = Some(arg.clone());
+            continue;
+        }
+        if !allowed.contains(&arg.as_str()) {
+            return Err(Failure::Usage(format!(
+                "{} doesn't take the option {}!",
+                options.command, arg
+            )));
+        }
+        // The only options that take no value
+        if arg == "--check" {
+            options.check = true;
+            continue;
+        }
+        if arg == "--keep-declarations" {
+            options.keep_declarations = true;
+            continue;
+        }
+        let value = match args.next() {
+            Some(value) => value.clone(),
+            None => return Err(Failure::Usage(format!("{} needs a value!", arg))),
+        };
+        let number = |value: &str| -> Result<u64, Failure> {
+            value
+                .parse()
+                .map_err(|_| Failure::Usage(format!("{} needs a number, not {:?}!", arg, value)))
+        };
+        match arg.as_str() {
+            "--shots" => match number(&value)? {
+                0 => return Err(Failure::Usage("--shots needs at least 1 shot!".to_string())),
+                shots => options.shots = Some(shots as usize),
+            },
+            "--seed" => options.seed = Some(number(&value)?),
+            "--target" => options.target = Some(value),
+            "--coupling" => options.coupling = Some(value),
+            "-o" => options.output = Some(value),
+            "--prefer" => options.prefer = Some(value),
+            "--dialect" => options.dialect = Some(value),
+            _ => unreachable!(),
+        }
+    }
+    Ok(options)
+}
+
+fn read(path: &str) -> Result<String, Failure> {
+    let result = match path {
+        "-" => {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source).map(|_| source)
+        }
+        _ => std::fs::read_to_string(path),
+    };
+    result.map_err(|error| Failure::Io(format!("Can't read {}: {}", path, error)))
+}
+
+/// Reads the program's source, returning it along with the name to report
+/// problems under
+fn read_source(options: &Options) -> Result<(String, String), Failure> {
+    let path = options.path.as_deref().unwrap_or("-");
+    let name = match path {
+        "-" => "<stdin>",
+        _ => path,
+    };
+    Ok((name.to_string(), read(path)?))
+}
+
+fn parse_source(name: &str, source: &str) -> Result<Program, Failure> {
+    parse(source).map_err(|error| {
+        Failure::Program(format!(
+            "Can't parse the program\n{}",
+            error.with_path(name)
+        ))
+    })
+}
+
+/// Reads and parses the program, returning it along with the name to report
+/// problems under
+fn load(options: &Options) -> Result<(String, Program), Failure> {
+    let (name, source) = read_source(options)?;
+    let program = parse_source(&name, &source)?;
+    Ok((name, program))
+}
+
+/// Loads the program, linked with the files it imports
+fn load_linked(options: &Options) -> Result<(String, Program), Failure> {
+    let (name, program) = load(options)?;
+    let program = link(&program, &name).map_err(Failure::Program)?;
+    Ok((name, program))
+}
+
+/// The gate names given with --prefer
+fn preferred(options: &Options) -> Vec<&str> {
+    match &options.prefer {
+        Some(names) => names.split(',').map(str::trim).collect(),
+        None => vec![],
+    }
+}
+
+fn fmt(options: &Options) -> Result<(), Failure> {
+    let (name, source) = read_source(options)?;
+    let mut config = FormatConfig::new(&preferred(options)).map_err(Failure::Usage)?;
+    config.dialect = dialect_of(&source);
+    let formatted = format(&parse_source(&name, &source)?, &config);
+    if !options.check {
+        print!("{}", formatted);
+        return Ok(());
+    }
+    match first_difference(&source, &formatted) {
+        None => Ok(()),
+        Some((line, Some(expected))) => Err(Failure::Program(format!(
+            "{}:{}: isn't formatted, it should be\n    {}",
+            name, line, expected
+        ))),
+        Some((line, None)) => Err(Failure::Program(format!(
+            "{}:{}: isn't formatted, the program should end before here",
+            name, line
+        ))),
+    }
+}
+
+/// Rewrites the program in the given dialect
+fn translate(options: &Options) -> Result<(), Failure> {
+    let dialect = match &options.dialect {
+        Some(name) => Dialect::from_name(name).ok_or_else(|| {
+            Failure::Usage(format!(
+                "Unknown dialect {:?}, expected modern or middle-english!",
+                name
+            ))
+        })?,
+        None => return Err(Failure::Usage("translate needs a --dialect!".to_string())),
+    };
+    let (name, program) = load(options)?;
+    let mut config = FormatConfig::new(&preferred(options)).map_err(Failure::Usage)?;
+    config.dialect = dialect;
+    let translated = format(&program, &config);
+    // Middle English programs can name things with modern keywords, which
+    // modern English can't read back
+    parse(&translated).map_err(|error| {
+        Failure::Program(format!(
+            "Can't translate the program\n{}",
+            error.with_path(&format!("{} (translated)", name))
+        ))
+    })?;
+    write_output(options, &translated)
+}
+
+/// Writes to the -o file if one was given, and to stdout otherwise
+fn write_output(options: &Options, output: &str) -> Result<(), Failure> {
+    match &options.output {
+        Some(path) => std::fs::write(path, output)
+            .map_err(|error| Failure::Io(format!("Can't write {}: {}", path, error))),
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
+
+fn check(name: &str, program: &Program) -> Result<SymbolTable, Failure> {
+    type_check(program).map_err(|error| Failure::Program(located(name, &error)))
+}
+
+/// The gates a target can emit, and its emitter
+type Backend = (
+    &'static [&'static str],
+    fn(&Program, &SymbolTable) -> Result<String, String>,
+);
+
+fn backend(target: &str) -> Result<Backend, Failure> {
+    match target {
+        "qasm" => Ok((qasm::GATES, qasm::emit)),
+        "qasm3" => Ok((qasm3::GATES, qasm3::emit)),
+        "qir" => Ok((qir::GATES, qir::emit)),
+        "qiskit" => Ok((qiskit::GATES, qiskit::emit)),
+        _ => Err(Failure::Usage(format!(
+            "Unknown target {:?}, expected one of qasm, qasm3, qir, or qiskit!",
+            target
+        ))),
+    }
+}
+
+fn build(options: &Options) -> Result<(), Failure> {
+    let target = match &options.target {
+        Some(target) => target,
+        None => return Err(Failure::Usage("build needs a --target!".to_string())),
+    };
+    let (supported, emit) = backend(target)?;
+    let coupling = match &options.coupling {
+        Some(path) => Some(CouplingMap::from_json(&read(path)?).map_err(Failure::Usage)?),
+        None => None,
+    };
+    let (name, mut program) = load_linked(options)?;
+    let mut symbols = check(&name, &program)?;
+
+    // NOTE: The respect threshold decides which optimizations we use!
+    let level = earned_level(&program);
+    let passes = PassOptions {
+        remove_declarations: !options.keep_declarations,
+    };
+    let report = PassManager::with_options(&passes)
+        .run(&mut program, &mut symbols, level)
+        .map_err(|error| Failure::Program(located(&name, &error)))?;
+    eprintln!("{}", report);
+
+    // Routing can only bring two qubits together at a time, so anything bigger
+    // is decomposed first
+    let basis: Vec<&str> = supported
+        .iter()
+        .copied()
+        .filter(|gate| coupling.is_none() || gates::signature(gate).qubits + controls(gate) <= 2)
+        .collect();
+    let translation = BasisTranslation::new(&basis).map_err(Failure::Program)?;
+    translation
+        .run(&mut program, &symbols)
+        .map_err(|error| Failure::Program(located(&name, &error)))?;
+    // The translation rewrites the program too, so what we knew about it may
+    // be out of date
+    symbols = check(&name, &program)?;
+    if let Some(coupling) = &coupling {
+        let layout = route(&mut program, &symbols, coupling)
+            .map_err(|error| Failure::Program(located(&name, &error)))?;
+        eprint!("{}", layout);
+        symbols = check(&name, &program)?;
+    }
+
+    let output =
+        emit(&program, &symbols).map_err(|error| Failure::Program(located(&name, &error)))?;
+    write_output(options, &output)
+}
+
+/// The number of controls a gate from the registry takes at least
+fn controls(gate: &str) -> usize {
+    match gates::signature(gate).controls {
+        gates::Controls::None => 0,
+        gates::Controls::Exactly(n) | gates::Controls::AtLeast(n) => n,
+    }
+}
+
+/// The seed to measure with, which is the time unless one was given
+fn seed(options: &Options) -> u64 {
+    options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0)
+    })
+}
+
+fn run(options: &Options) -> Result<(), Failure> {
+    let (name, program) = load_linked(options)?;
+    let symbols = check(&name, &program)?;
+    let shots = options.shots.unwrap_or_else(|| {
+        program
+            .stmts
+            .iter()
+            .find_map(|stmt| match stmt.kind {
+                quill::ast::Stmt::Return { shots } => Some(shots as usize),
+                _ => None,
+            })
+            .unwrap_or(1024)
+    });
+    let counts = simulator::run(&program, &symbols, shots, &mut Rng::new(seed(options)))
+        .map_err(|error| Failure::Program(located(&name, &error)))?;
+
+    print!("{}", simulator::histogram(&counts));
+    Ok(())
+}
+
+/// Reads statements and commands from stdin until it ends or `:quit`, only
+/// prompting for them when someone is typing
+fn repl(options: &Options) -> Result<(), Failure> {
+    if options.path.is_some() {
+        return Err(Failure::Usage(
+            "repl reads from stdin, use :load to run a file!".to_string(),
+        ));
+    }
+    let interactive = std::io::stdin().is_terminal();
+    if interactive {
+        println!("Quill REPL, type :help for help");
+    }
+    let mut session = Session::new(seed(options));
+    let mut line = String::new();
+    loop {
+        if interactive {
+            print!("> ");
+            std::io::stdout().flush().ok();
+        }
+        line.clear();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(error) => return Err(Failure::Io(format!("Can't read stdin: {}", error))),
+        }
+        if matches!(line.trim(), ":quit" | ":q") {
+            return Ok(());
+        }
+        match session.eval(&line) {
+            Ok(reply) if reply.is_empty() => {}
+            Ok(reply) => println!("{}", reply),
+            Err(error) => eprintln!("error: {}", error),
+        }
+    }
+}
 
-    println!("---");
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = parse_args(&args).and_then(|options| match options.command.as_str() {
+        "check" => {
+            let (name, program) = load_linked(&options)?;
+            let symbols = check(&name, &program)?;
+            let warnings = check_linearity(&program, &symbols)
+                .map_err(|error| Failure::Program(located(&name, &error)))?;
+            for warning in warnings {
+                eprintln!("{}:{}", name, warning);
+            }
+            Ok(())
+        }
+        "run" => run(&options),
+        "build" => build(&options),
+        "repl" => repl(&options),
+        "fmt" => fmt(&options),
+        "translate" => translate(&options),
+        "ast" => {
+            let (_, program) = load(&options)?;
+            ASTNode::print_nodes(&program.to_tree(), 0);
+            Ok(())
+        }
+        _ => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Program(message)) => {
+            eprintln!("error: {}", message);
+            ExitCode::from(1)
+        }
+        Err(Failure::Usage(message)) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Io(message)) => {
+            eprintln!("error: {}", message);
+            ExitCode::from(2)
+        }
+    }
 }
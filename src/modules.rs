@@ -0,0 +1,493 @@
+//! Linking a program with the files it imports. Each file is a module named
+//! after its file stem, so the gates and constants of `bell.ql` are used as
+//! `bell.pair` by the files that fetch it. Linking replaces every call of a
+//! defined gate with the gates of its body, and every constant with its
+//! value, leaving a program the rest of the compiler can work with.
+use crate::ast::{Param, Program, Statement, Stmt, Target, ValueExpr};
+use crate::composites::{self, Composite};
+use crate::parser::parse;
+use crate::type_checker::{check_statement, SymbolTable};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The files bundled with Quill, which are imported as "std/<name>"
+const STDLIB: &[(&str, &str)] = &[
+    ("std/bell.ql", include_str!("stdlib/bell.ql")),
+    ("std/grover.ql", include_str!("stdlib/grover.ql")),
+    ("std/qft.ql", include_str!("stdlib/qft.ql")),
+];
+
+/// A gate defined in Quill, with its body already expanded into gates from
+/// the registry acting on its qubits
+#[derive(Debug, Clone)]
+struct Definition {
+    qubits: Vec<String>,
+    body: Vec<Stmt>,
+}
+
+/// What a file makes available to the files importing it
+#[derive(Debug, Default, Clone)]
+struct Module {
+    gates: HashMap<String, Definition>,
+    constants: HashMap<String, Param>,
+}
+
+/// Everything a file can refer to while it's linked: its own gates and
+/// constants, and the modules it imported by name
+#[derive(Default)]
+struct Scope {
+    module: Module,
+    imports: HashMap<String, String>,
+}
+
+/// Loads modules for linking, parsing each file only once no matter how many
+/// files import it
+#[derive(Default)]
+pub struct Loader {
+    /// Modules by their canonical path (or their "std/" path)
+    modules: HashMap<String, Module>,
+    /// The files being loaded, each one imported by the one before it
+    loading: Vec<String>,
+}
+
+impl Loader {
+    pub fn new() -> Loader {
+        Loader::default()
+    }
+
+    /// Links a program read from the file at `path`, whose imports are found
+    /// relative to the directory it's in. Errors are given as
+    /// "file:line: message".
+    pub fn link(&mut self, program: &Program, path: &str) -> Result<Program, String> {
+        let key = std::fs::canonicalize(path)
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|_| path.to_string());
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        self.loading.push(key);
+        let linked = self.link_file(program, path, dir, true);
+        self.loading.pop();
+        Ok(Program { stmts: linked?.0 })
+    }
+
+    /// Links the statements of a file, returning them along with what the
+    /// file makes available to others. Only the program being run may hold
+    /// anything other than imports, definitions, and constants.
+    fn link_file(
+        &mut self,
+        program: &Program,
+        name: &str,
+        dir: &Path,
+        main: bool,
+    ) -> Result<(Vec<Statement>, Module), String> {
+        let mut scope = Scope::default();
+        let mut symbols = SymbolTable::default();
+        let mut stmts = vec![];
+        for stmt in &program.stmts {
+            let line_no = stmt.line_no;
+            let linked = match &stmt.kind {
+                Stmt::Import { path } => {
+                    let (namespace, key) = self.import(path, name, dir, line_no)?;
+                    if scope.imports.insert(namespace.clone(), key).is_some() {
+                        return Err(located(
+                            name,
+                            &format!("{}: a module named {} was already imported!", line_no, namespace),
+                        ));
+                    }
+                    vec![]
+                }
+                Stmt::Constant { name: constant, value } => {
+                    let value = self.constant(value, &scope, line_no).map_err(|e| located(name, &e))?;
+                    if scope.module.constants.insert(constant.clone(), value).is_some() {
+                        return Err(located(
+                            name,
+                            &format!("{}: the constant {} was already declared!", line_no, constant),
+                        ));
+                    }
+                    vec![]
+                }
+                Stmt::GateDef {
+                    name: gate,
+                    qubits,
+                    body,
+                } => {
+                    let definition = self.define(qubits, body, &scope).map_err(|e| located(name, &e))?;
+                    scope.module.gates.insert(gate.clone(), definition);
+                    vec![]
+                }
+                Stmt::Comment(_) => vec![stmt.clone()],
+                _ if !main => {
+                    return Err(located(
+                        name,
+                        &format!(
+                            "{}: imported files can only hold imports, gate definitions, and constants!",
+                            line_no
+                        ),
+                    ))
+                }
+                kind => self
+                    .expand(kind, line_no, &scope, &symbols)
+                    .map_err(|e| located(name, &e))?,
+            };
+            // Only the variables are needed here, to know what registers given
+            // to calls hold, so problems are left for the type checker to report
+            for stmt in &linked {
+                let _ = check_statement(stmt, &mut symbols);
+            }
+            stmts.extend(linked);
+        }
+        Ok((stmts, scope.module))
+    }
+
+    /// Loads the module at `path`, as imported on a line of the file `from`,
+    /// returning its name and its key in `modules`
+    fn import(
+        &mut self,
+        path: &str,
+        from: &str,
+        dir: &Path,
+        line_no: i32,
+    ) -> Result<(String, String), String> {
+        let error = |message: String| located(from, &format!("{}: {}", line_no, message));
+        let namespace = Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (key, display, source) = match STDLIB.iter().find(|(name, _)| *name == path) {
+            Some((name, source)) => (name.to_string(), name.to_string(), source.to_string()),
+            None if path.starts_with("std/") => {
+                return Err(error(format!(
+                    "there's no module {} in the standard library!",
+                    path
+                )))
+            }
+            None => {
+                let file = dir.join(path);
+                let key = std::fs::canonicalize(&file)
+                    .map_err(|e| error(format!("can't read {}: {}", path, e)))?
+                    .display()
+                    .to_string();
+                let source = match self.modules.contains_key(&key) {
+                    true => String::new(),
+                    false => std::fs::read_to_string(&file)
+                        .map_err(|e| error(format!("can't read {}: {}", path, e)))?,
+                };
+                (key, file.display().to_string(), source)
+            }
+        };
+        if let Some(start) = self.loading.iter().position(|loading| *loading == key) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain(std::iter::once(&key))
+                .map(|key| file_name(key))
+                .collect();
+            return Err(error(format!(
+                "imports go in a circle: {}!",
+                cycle.join(" -> ")
+            )));
+        }
+        if !self.modules.contains_key(&key) {
+            let file_dir = Path::new(&display).parent().unwrap_or(Path::new(""));
+            let program = parse(&source).map_err(|errors| {
+                format!("Can't parse {}\n{}", path, errors.with_path(&display))
+            })?;
+            self.loading.push(key.clone());
+            let loaded = self.link_file(&program, &display, file_dir, false);
+            self.loading.pop();
+            self.modules.insert(key.clone(), loaded?.1);
+        }
+        Ok((namespace, key))
+    }
+
+    /// Expands the body of a gate definition in terms of its qubits, and type
+    /// checks it
+    fn define(
+        &self,
+        qubits: &[String],
+        body: &[Statement],
+        scope: &Scope,
+    ) -> Result<Definition, String> {
+        let mut symbols = SymbolTable::default();
+        for qubit in qubits {
+            symbols.types.insert(qubit.clone(), ValueExpr::Qubit);
+            symbols.widths.insert(qubit.clone(), 1);
+        }
+        let mut expanded = vec![];
+        for stmt in body {
+            if matches!(stmt.kind, Stmt::Comment(_)) {
+                continue;
+            }
+            for stmt in self.expand(&stmt.kind, stmt.line_no, scope, &symbols)? {
+                check_statement(&stmt, &mut symbols)?;
+                expanded.push(stmt.kind);
+            }
+        }
+        Ok(Definition {
+            qubits: qubits.to_vec(),
+            body: expanded,
+        })
+    }
+
+    /// Replaces the constants in a statement with their values, and a call
+    /// with the body of the gate it calls. Whole registers given to a call
+    /// stand for each of their qubits in turn.
+    fn expand(
+        &self,
+        kind: &Stmt,
+        line_no: i32,
+        scope: &Scope,
+        symbols: &SymbolTable,
+    ) -> Result<Vec<Statement>, String> {
+        let kind = match kind {
+            Stmt::Gate {
+                gate,
+                kind,
+                targets,
+                controls,
+                params,
+            } => Stmt::Gate {
+                gate: gate.clone(),
+                kind: *kind,
+                targets: targets.clone(),
+                controls: controls.clone(),
+                params: params
+                    .iter()
+                    .map(|param| self.constant(param, scope, line_no))
+                    .collect::<Result<_, _>>()?,
+            },
+            Stmt::Call {
+                gate,
+                targets,
+                params,
+            } => {
+                if let Some(composite) = composites::lookup(gate) {
+                    let params: Vec<Param> = params
+                        .iter()
+                        .map(|param| self.constant(param, scope, line_no))
+                        .collect::<Result<_, _>>()?;
+                    let groups = groups_of(targets, symbols, line_no)?;
+                    return expand_composite(composite, &groups, &params, line_no);
+                }
+                let definition = self.lookup(gate, scope, line_no)?;
+                let qubits = groups_of(targets, symbols, line_no)?.concat();
+                if qubits.len() != definition.qubits.len() {
+                    return Err(format!(
+                        "{}: {} acts on {} qubit(s), but {} were given!",
+                        line_no,
+                        gate,
+                        definition.qubits.len(),
+                        qubits.len()
+                    ));
+                }
+                let assigned: HashMap<&str, &Target> = definition
+                    .qubits
+                    .iter()
+                    .map(String::as_str)
+                    .zip(&qubits)
+                    .collect();
+                let substitute = |targets: &[Target]| -> Vec<Target> {
+                    targets
+                        .iter()
+                        .map(|target| (*assigned[target.name()]).clone())
+                        .collect()
+                };
+                return Ok(definition
+                    .body
+                    .iter()
+                    .map(|stmt| {
+                        let Stmt::Gate {
+                            gate,
+                            kind,
+                            targets,
+                            controls,
+                            params,
+                        } = stmt
+                        else {
+                            unreachable!()
+                        };
+                        Statement {
+                            line_no,
+                            kind: Stmt::Gate {
+                                gate: gate.clone(),
+                                kind: *kind,
+                                targets: substitute(targets),
+                                controls: substitute(controls),
+                                params: params.clone(),
+                            },
+                        }
+                    })
+                    .collect());
+            }
+            kind => kind.clone(),
+        };
+        Ok(vec![Statement { line_no, kind }])
+    }
+
+    /// Finds a defined gate, which is either the file's own or, when it's
+    /// named like "bell.pair", from the module it was imported from
+    fn lookup<'a>(
+        &'a self,
+        gate: &str,
+        scope: &'a Scope,
+        line_no: i32,
+    ) -> Result<&'a Definition, String> {
+        let (module, name) = self.module_of(gate, scope, line_no)?;
+        module.gates.get(name).ok_or_else(|| {
+            format!(
+                "{}: there's no gate {} in {}!",
+                line_no,
+                name,
+                gate.split('.').next().unwrap()
+            )
+        })
+    }
+
+    /// The value of a parameter, looking up the constant it names if it does
+    fn constant(&self, param: &Param, scope: &Scope, line_no: i32) -> Result<Param, String> {
+        let Param::Constant(constant) = param else {
+            return Ok(param.clone());
+        };
+        let (module, name) = self.module_of(constant, scope, line_no)?;
+        module
+            .constants
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("{}: there's no constant named {}!", line_no, constant))
+    }
+
+    /// The module a name like "bell.pair" comes from and the name within it,
+    /// or the file's own module if the name doesn't have a module in front
+    fn module_of<'a, 'b>(
+        &'a self,
+        name: &'b str,
+        scope: &'a Scope,
+        line_no: i32,
+    ) -> Result<(&'a Module, &'b str), String> {
+        match name.split_once('.') {
+            Some((namespace, name)) => match scope.imports.get(namespace) {
+                Some(key) => Ok((&self.modules[key], name)),
+                None => Err(format!(
+                    "{}: no module named {} has been imported!",
+                    line_no, namespace
+                )),
+            },
+            None => Ok((&scope.module, name)),
+        }
+    }
+}
+
+/// The gates a composite turns into, given the qubits each of its targets
+/// stands for
+fn expand_composite(
+    composite: &Composite,
+    groups: &[Vec<Target>],
+    params: &[Param],
+    line_no: i32,
+) -> Result<Vec<Statement>, String> {
+    if let Some(count) = composite.targets.filter(|count| *count != groups.len()) {
+        return Err(format!(
+            "{}: {} takes {} target(s), but {} were given!",
+            line_no,
+            composite.name,
+            count,
+            groups.len()
+        ));
+    }
+    if params.len() != composite.params {
+        return Err(format!(
+            "{}: {} takes {} parameter(s), but {} were given!",
+            line_no,
+            composite.name,
+            composite.params,
+            params.len()
+        ));
+    }
+    let stmts = (composite.expand)(groups, params).map_err(|e| format!("{}: {}!", line_no, e))?;
+    Ok(stmts
+        .into_iter()
+        .map(|kind| Statement { line_no, kind })
+        .collect())
+}
+
+/// Expands a call of a built-in composite, like one typed into the REPL, with
+/// the variables declared so far. Anything else is given back as it is.
+pub fn expand_builtin(stmt: Statement, symbols: &SymbolTable) -> Result<Vec<Statement>, String> {
+    let line_no = stmt.line_no;
+    match &stmt.kind {
+        Stmt::Call {
+            gate,
+            targets,
+            params,
+        } => match composites::lookup(gate) {
+            Some(composite) => {
+                let groups = groups_of(targets, symbols, line_no)?;
+                expand_composite(composite, &groups, params, line_no)
+            }
+            None => Ok(vec![stmt]),
+        },
+        _ => Ok(vec![stmt]),
+    }
+}
+
+/// The qubits each of a call's targets stands for
+fn groups_of(
+    targets: &[Target],
+    symbols: &SymbolTable,
+    line_no: i32,
+) -> Result<Vec<Vec<Target>>, String> {
+    targets
+        .iter()
+        .map(|target| qubits_of(target, symbols, line_no))
+        .collect()
+}
+
+/// The qubits a call's target stands for, one at a time
+fn qubits_of(target: &Target, symbols: &SymbolTable, line_no: i32) -> Result<Vec<Target>, String> {
+    match target {
+        Target::Name(name) => match symbols.types.get(name) {
+            Some(ValueExpr::Qubit) => Ok(vec![target.clone()]),
+            Some(ValueExpr::QReg) => Ok((0..symbols.widths[name])
+                .map(|i| Target::Slice {
+                    name: name.clone(),
+                    start: i,
+                    end: i,
+                })
+                .collect()),
+            Some(ty) => Err(format!("{}: Qubit expected, {:?} given!", line_no, ty)),
+            None => Err(format!(
+                "{}: Unknown variable {:?} given, not a qubit!",
+                line_no, name
+            )),
+        },
+        // Bounds are left to the type checker, which sees the gates this call
+        // turns into
+        Target::Slice { name, start, end } => Ok((*start..=*end)
+            .map(|i| Target::Slice {
+                name: name.clone(),
+                start: i,
+                end: i,
+            })
+            .collect()),
+    }
+}
+
+/// The name of a file, without the directories it's in
+fn file_name(key: &str) -> String {
+    Path::new(key)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// Puts the file name in front of a message, joining it with the line number
+/// if the message starts with one
+pub fn located(name: &str, message: &str) -> String {
+    match message.split_once(": ") {
+        Some((line, rest)) if line.parse::<i32>().is_ok() => format!("{}:{}: {}", name, line, rest),
+        _ => format!("{}: {}", name, message),
+    }
+}
+
+/// Links a program read from the file at `path` with the files it imports
+pub fn link(program: &Program, path: &str) -> Result<Program, String> {
+    Loader::new().link(program, path)
+}
@@ -0,0 +1,77 @@
+//! Peephole pass removing pairs of gates that undo each other
+use super::commutation::CommutationDag;
+use super::{qubits_of, Pass};
+use crate::ast::{Program, Stmt, Target};
+use crate::gates;
+use crate::type_checker::SymbolTable;
+use std::collections::HashSet;
+
+/// Removes pairs of gates that cancel out, like `h h`, `cnot cnot` on the same
+/// wires, or `s sadj`. The pair doesn't need to be adjacent, as long as the
+/// first gate commutes with everything in between (like the `rz` in
+/// `s rz sadj`, or gates on other qubits).
+/// Removing a pair can expose another (as in `h x x h`), so we keep going
+/// until there is nothing left to cancel.
+pub struct CancelInverses;
+
+impl Pass for CancelInverses {
+    fn name(&self) -> &'static str {
+        "cancel-inverses"
+    }
+
+    fn run(&self, program: &mut Program, _symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        while let Some((first, second)) = find_pair(program) {
+            // Remove the later statement first so the earlier index stays valid
+            program.stmts.remove(second);
+            program.stmts.remove(first);
+        }
+        Ok(vec![])
+    }
+}
+
+/// Finds the first gate that can be commuted up against its inverse
+fn find_pair(program: &Program) -> Option<(usize, usize)> {
+    let dag = CommutationDag::new(program);
+    (0..program.stmts.len()).find_map(|first| {
+        if !matches!(program.stmts[first].kind, Stmt::Gate { .. }) {
+            return None;
+        }
+        let second = dag
+            .partners(program, first)
+            .into_iter()
+            .find(|&second| cancels(&program.stmts[first].kind, &program.stmts[second].kind))?;
+        Some((first, second))
+    })
+}
+
+/// Whether the second gate undoes the first: it has to be the first gate's
+/// inverse, acting on exactly the same targets (in order) and controls
+fn cancels(first: &Stmt, second: &Stmt) -> bool {
+    match (first, second) {
+        (
+            Stmt::Gate {
+                gate: gate1,
+                targets: targets1,
+                controls: controls1,
+                ..
+            },
+            Stmt::Gate {
+                gate: gate2,
+                targets: targets2,
+                controls: controls2,
+                ..
+            },
+        ) => {
+            let (sig1, sig2) = (gates::signature(gate1), gates::signature(gate2));
+            sig1.inverse == Some(sig2.name)
+                && expand(targets1) == expand(targets2)
+                && HashSet::<(&str, usize)>::from_iter(expand(controls1))
+                    == HashSet::from_iter(expand(controls2))
+        }
+        _ => false,
+    }
+}
+
+fn expand(targets: &[Target]) -> Vec<(&str, usize)> {
+    targets.iter().flat_map(qubits_of).collect()
+}
@@ -0,0 +1,144 @@
+//! Commutation analysis, and the dependency DAG it induces over a program
+use super::{qubits_of, wires};
+use crate::ast::{Program, Stmt};
+use crate::gates::{self, Pauli};
+
+/// Whether two statements can be swapped without changing what the program
+/// does. Two gates commute when, on every qubit they share, both are diagonal
+/// in the same Pauli basis: control qubits are diagonal in Z, and targets in
+/// whatever basis the registry gives the gate (so `rz` commutes through the
+/// control of a `cnot`, and `x` through its target). Measurements,
+/// assignments, and returns commute with nothing that mentions their
+/// variables, and returns with nothing at all.
+pub fn commutes(first: &Stmt, second: &Stmt) -> bool {
+    match (first, second) {
+        (Stmt::Comment(_), _) | (_, Stmt::Comment(_)) => true,
+        (Stmt::Return { .. }, _) | (_, Stmt::Return { .. }) => false,
+        (Stmt::Gate { .. }, Stmt::Gate { .. }) => {
+            let (bases1, bases2) = (bases(first), bases(second));
+            bases1.iter().all(|(qubit1, basis1)| {
+                bases2
+                    .iter()
+                    .filter(|(qubit2, _)| qubit1 == qubit2)
+                    .all(|(_, basis2)| basis1.is_some() && basis1 == basis2)
+            })
+        }
+        _ => {
+            let mentioned1 = variables(first);
+            !variables(second).iter().any(|var| mentioned1.contains(var))
+        }
+    }
+}
+
+/// The basis a gate is diagonal in on each of the qubits it touches, targets
+/// first and then controls
+fn bases(stmt: &Stmt) -> Vec<((&str, usize), Option<Pauli>)> {
+    match stmt {
+        Stmt::Gate {
+            gate,
+            targets,
+            controls,
+            ..
+        } => {
+            let basis = gates::signature(gate).basis;
+            let targets = targets
+                .iter()
+                .flat_map(qubits_of)
+                .map(|qubit| (qubit, basis));
+            let controls = controls
+                .iter()
+                .flat_map(qubits_of)
+                .map(|qubit| (qubit, Some(Pauli::Z)));
+            targets.chain(controls).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// The names of the variables a statement mentions
+fn variables(stmt: &Stmt) -> Vec<&str> {
+    match stmt {
+        Stmt::Gate {
+            targets, controls, ..
+        } => targets
+            .iter()
+            .chain(controls)
+            .map(|target| target.name())
+            .collect(),
+        Stmt::Measure {
+            measured,
+            recipient,
+        } => vec![measured.name(), recipient.name()],
+        Stmt::Assign { name, .. } => vec![name],
+        Stmt::Return { .. } | Stmt::Comment(_) => vec![],
+        _ => unreachable!("modules are linked before optimizing"),
+    }
+}
+
+/// The dependencies between the statements of a program, where statement `j`
+/// depends on an earlier statement `i` whenever the two don't commute. Nodes
+/// are the indices of the program's statements, and every dependency is kept
+/// as an edge (not just the ones a transitive reduction would leave), so the
+/// statements a given one has to stay behind are all of its successors.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CommutationDag {
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+}
+
+impl CommutationDag {
+    pub fn new(program: &Program) -> CommutationDag {
+        let stmts = &program.stmts;
+        let mut successors = vec![vec![]; stmts.len()];
+        let mut predecessors = vec![vec![]; stmts.len()];
+        for j in 0..stmts.len() {
+            for i in 0..j {
+                if !commutes(&stmts[i].kind, &stmts[j].kind) {
+                    successors[i].push(j);
+                    predecessors[j].push(i);
+                }
+            }
+        }
+        CommutationDag {
+            successors,
+            predecessors,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.successors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.successors.is_empty()
+    }
+
+    /// The later statements that depend on the given one, in program order
+    pub fn successors(&self, index: usize) -> &[usize] {
+        &self.successors[index]
+    }
+
+    /// The earlier statements the given one depends on, in program order
+    pub fn predecessors(&self, index: usize) -> &[usize] {
+        &self.predecessors[index]
+    }
+
+    /// The statements the gate at `index` can be moved forward to sit right
+    /// before, by commuting it past everything in between. These are the later
+    /// gates sharing a qubit with it, up to and including its first successor.
+    pub fn partners(&self, program: &Program, index: usize) -> Vec<usize> {
+        let stmts = &program.stmts;
+        let qubits = wires(&stmts[index].kind);
+        let last = self.successors[index]
+            .first()
+            .copied()
+            .unwrap_or(stmts.len() - 1);
+        (index + 1..=last)
+            .filter(|&other| {
+                wires(&stmts[other].kind)
+                    .iter()
+                    .any(|qubit| qubits.contains(qubit))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,119 @@
+//! Pass removing gates that can't affect any measurement
+use super::Pass;
+use crate::ast::{Program, Stmt, Target, ValueExpr};
+use crate::type_checker::{checked_range, checked_width, SymbolTable};
+use std::collections::{HashMap, HashSet};
+
+/// Removes every gate whose qubits are never measured afterwards, directly or
+/// through a multi-qubit gate linking them to a qubit that is, since those
+/// gates can't change the histogram. This is a backward liveness analysis: a
+/// qubit is live when a later measurement reads it, a gate on a live qubit
+/// makes all of its qubits live, and reassigning a variable kills its qubits.
+/// With `remove_declarations`, qubits and qregs that nothing uses anymore once
+/// the dead gates are gone have their assignments removed as well.
+pub struct EliminateDeadCode {
+    pub remove_declarations: bool,
+}
+
+impl Pass for EliminateDeadCode {
+    fn name(&self) -> &'static str {
+        "eliminate-dead-code"
+    }
+
+    fn run(&self, program: &mut Program, _symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        let qubits = qubits_read(program)?;
+        let mut live: HashSet<(String, usize)> = HashSet::new();
+        let mut dead = vec![false; program.stmts.len()];
+        for (index, stmt) in program.stmts.iter().enumerate().rev() {
+            match &stmt.kind {
+                Stmt::Measure { .. } => live.extend(qubits[index].iter().cloned()),
+                Stmt::Gate {
+                    targets, controls, ..
+                } => {
+                    let qubits = &qubits[index];
+                    if !qubits.iter().any(|qubit| live.contains(qubit)) {
+                        dead[index] = true;
+                    } else if targets.len() + controls.len() > 1 {
+                        // The qubits of a multi-qubit gate influence each other (a range given
+                        // to a single qubit gate is just that gate on each qubit separately)
+                        live.extend(qubits.iter().cloned());
+                    }
+                }
+                Stmt::Assign { name, .. } => live.retain(|(var, _)| var != name),
+                Stmt::Return { .. } | Stmt::Comment(_) => {}
+                _ => unreachable!("modules are linked before optimizing"),
+            }
+        }
+
+        // Anything still mentioned once the dead gates are gone is in use
+        let mut used: HashSet<&str> = HashSet::new();
+        for (index, stmt) in program.stmts.iter().enumerate() {
+            match &stmt.kind {
+                Stmt::Gate {
+                    targets, controls, ..
+                } if !dead[index] => {
+                    used.extend(targets.iter().chain(controls).map(|target| target.name()))
+                }
+                Stmt::Measure { measured, .. } => {
+                    used.insert(measured.name());
+                }
+                _ => {}
+            }
+        }
+        if self.remove_declarations {
+            for (index, stmt) in program.stmts.iter().enumerate() {
+                if let Stmt::Assign { ty, name, .. } = &stmt.kind {
+                    if matches!(ty, ValueExpr::Qubit | ValueExpr::QReg)
+                        && !used.contains(name.as_str())
+                    {
+                        dead[index] = true;
+                    }
+                }
+            }
+        }
+
+        let notes = program
+            .stmts
+            .iter()
+            .zip(&dead)
+            .filter(|(_, dead)| **dead)
+            .map(|(stmt, _)| format!("{}: removed \"{}\"", stmt.line_no, stmt.kind))
+            .collect();
+        let mut index = 0;
+        program.stmts.retain(|_| {
+            index += 1;
+            !dead[index - 1]
+        });
+        Ok(notes)
+    }
+}
+
+/// Every qubit each gate and measurement of the program covers, by the index
+/// of its statement. Widths are tracked from the top, since a variable can be
+/// redeclared with a different width, which the liveness analysis working
+/// from the bottom can't see.
+fn qubits_read(program: &Program) -> Result<Vec<Vec<(String, usize)>>, String> {
+    let mut widths: HashMap<String, usize> = HashMap::new();
+    let mut qubits = vec![];
+    for stmt in &program.stmts {
+        let line_no = stmt.line_no;
+        let operands: Vec<&Target> = match &stmt.kind {
+            Stmt::Assign { name, value, .. } => {
+                widths.insert(name.clone(), checked_width(value, line_no)?);
+                vec![]
+            }
+            Stmt::Gate {
+                targets, controls, ..
+            } => targets.iter().chain(controls).collect(),
+            Stmt::Measure { measured, .. } => vec![measured],
+            _ => vec![],
+        };
+        let mut read = vec![];
+        for operand in operands {
+            let (name, start, end) = checked_range(operand, &widths, line_no)?;
+            read.extend((start..=end).map(|i| (name.to_string(), i)));
+        }
+        qubits.push(read);
+    }
+    Ok(qubits)
+}
@@ -0,0 +1,321 @@
+//! Pass lowering multi-controlled gates into one and two qubit gates
+use super::resynthesis::angle_param;
+use super::Pass;
+use crate::ast::{Program, RespectExpr, Statement, Stmt, Target, Value, ValueExpr, GENERATED};
+use crate::gates::{self, dagger, matmul, u3_angles, Matrix};
+use crate::type_checker::SymbolTable;
+use num_complex::Complex64;
+use std::f64::consts::PI;
+
+/// The gates a decomposition is allowed to produce
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BasisSet {
+    /// u3 and cnot
+    U3Cnot,
+    /// h, t, tadj, and cnot (Clifford + T)
+    CliffordT,
+}
+
+impl BasisSet {
+    pub fn gates(&self) -> &'static [&'static str] {
+        match self {
+            BasisSet::U3Cnot => &["u3", "cnot"],
+            BasisSet::CliffordT => &["h", "t", "tadj", "cnot"],
+        }
+    }
+}
+
+/// Lowers every toffoli, mcx, mcy, and mcz into gates from the basis set,
+/// exactly (up to a global phase). Two controls use the standard six cnot
+/// toffoli circuit, and more controls either use a chain of toffolis computing
+/// the AND of the controls into clean ancilla qubits (which are uncomputed
+/// again right after), or, without ancillas, recursively split off one control
+/// at a time using square roots of the gate (Barenco et al., lemma 7.5), which
+/// takes exponentially many gates in the number of controls.
+///
+/// Clifford + T can't express a gate with three or more controls without an
+/// ancilla, so those always get one in that basis (with a note saying so). The
+/// ancillas are a single qreg that every decomposition shares, declared at the
+/// top of the program on the line of the first gate that uses it, and marked
+/// as generated so it doesn't count toward the program's respect.
+pub struct DecomposeMultiControlled {
+    pub basis: BasisSet,
+    pub ancillas: bool,
+}
+
+impl Pass for DecomposeMultiControlled {
+    fn name(&self) -> &'static str {
+        "decompose-multi-controlled"
+    }
+
+    fn run(&self, program: &mut Program, symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        let mut notes = vec![];
+        let mut ancillas_needed = 0;
+        let mut first_use = None;
+        for stmt in &program.stmts {
+            if let Some(controls) = multi_controlled(&stmt.kind) {
+                if controls >= 3 && (self.ancillas || self.basis == BasisSet::CliffordT) {
+                    ancillas_needed = ancillas_needed.max(controls - 2);
+                    first_use.get_or_insert(stmt.line_no);
+                    if !self.ancillas {
+                        notes.push(format!(
+                            "{}: {} controls can't be decomposed into {{{}}} without ancillas, so {} were used",
+                            stmt.line_no,
+                            controls,
+                            self.basis.gates().join(", "),
+                            controls - 2
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut stmts = vec![];
+        let ancilla = (1..)
+            .map(|i| match i {
+                1 => "ancilla".to_string(),
+                _ => format!("ancilla{}", i),
+            })
+            .find(|name| !symbols.types.contains_key(name))
+            .unwrap();
+        if let Some(line_no) = first_use {
+            stmts.push(Statement {
+                line_no,
+                kind: Stmt::Assign {
+                    respect: RespectExpr::Maistow,
+                    ty: ValueExpr::QReg,
+                    name: ancilla.clone(),
+                    value: Value::QReg(vec![("0".to_string(), ancillas_needed)]),
+                },
+            });
+            stmts.push(Statement {
+                line_no,
+                kind: Stmt::Comment(GENERATED.to_string()),
+            });
+        }
+        for stmt in program.stmts.drain(..) {
+            let (gate, targets, controls) = match &stmt.kind {
+                Stmt::Gate {
+                    gate,
+                    targets,
+                    controls,
+                    ..
+                } if multi_controlled(&stmt.kind).is_some() => (gate, targets, controls),
+                _ => {
+                    stmts.push(stmt);
+                    continue;
+                }
+            };
+            let mut emitter = Emitter {
+                basis: self.basis,
+                line_no: stmt.line_no,
+                stmts: vec![],
+            };
+            let ancillas: Vec<Target> = (0..controls.len().saturating_sub(2))
+                .map(|i| Target::Slice {
+                    name: ancilla.clone(),
+                    start: i,
+                    end: i,
+                })
+                .collect();
+            let use_ancillas = self.ancillas || self.basis == BasisSet::CliffordT;
+            let target = &targets[0];
+            // Conjugating the target turns the controlled X into a controlled Y or Z
+            match gates::signature(gate).name {
+                "mcy" => emitter.phase(target, 6),
+                "mcz" => emitter.h(target),
+                _ => {}
+            }
+            if use_ancillas {
+                emitter.mcx_with_ancillas(controls, target, &ancillas);
+            } else {
+                emitter.controlled(&pauli_x(), controls, target);
+            }
+            match gates::signature(gate).name {
+                "mcy" => emitter.phase(target, 2),
+                "mcz" => emitter.h(target),
+                _ => {}
+            }
+            stmts.extend(emitter.stmts);
+        }
+        program.stmts = stmts;
+        Ok(notes)
+    }
+}
+
+/// The number of controls of a gate application that needs decomposing
+fn multi_controlled(stmt: &Stmt) -> Option<usize> {
+    match stmt {
+        Stmt::Gate { gate, controls, .. } => {
+            let name = gates::signature(gate).name;
+            ["toffoli", "mcx", "mcy", "mcz"]
+                .contains(&name)
+                .then_some(controls.len())
+        }
+        _ => None,
+    }
+}
+
+/// Builds the statements of a decomposition, in the chosen basis
+struct Emitter {
+    basis: BasisSet,
+    line_no: i32,
+    stmts: Vec<Statement>,
+}
+
+impl Emitter {
+    fn gate(&mut self, gate: &str, targets: &[&Target], controls: &[&Target], params: &[f64]) {
+        self.stmts.push(Statement {
+            line_no: self.line_no,
+            kind: Stmt::Gate {
+                gate: gate.to_string(),
+                kind: gates::signature(gate).kind,
+                targets: targets.iter().map(|&target| target.clone()).collect(),
+                controls: controls.iter().map(|&control| control.clone()).collect(),
+                params: params.iter().map(|&param| angle_param(param)).collect(),
+            },
+        });
+    }
+
+    fn cnot(&mut self, control: &Target, target: &Target) {
+        self.gate("cnot", &[target], &[control], &[]);
+    }
+
+    fn u3(&mut self, target: &Target, theta: f64, phi: f64, lambda: f64) {
+        self.gate("u3", &[target], &[], &[theta, phi, lambda]);
+    }
+
+    fn h(&mut self, target: &Target) {
+        match self.basis {
+            BasisSet::U3Cnot => self.u3(target, PI / 2.0, 0.0, PI),
+            BasisSet::CliffordT => self.gate("h", &[target], &[], &[]),
+        }
+    }
+
+    /// The phase gate diag(1, e^(i * PI * eighths / 4)), which is that many t's
+    fn phase(&mut self, target: &Target, eighths: usize) {
+        match self.basis {
+            BasisSet::U3Cnot => self.u3(target, 0.0, 0.0, PI * eighths as f64 / 4.0),
+            BasisSet::CliffordT => {
+                // Going the shorter way around, since tadj undoes a t
+                let (gate, count) = if eighths <= 4 {
+                    ("t", eighths)
+                } else {
+                    ("tadj", 8 - eighths)
+                };
+                for _ in 0..count {
+                    self.gate(gate, &[target], &[], &[]);
+                }
+            }
+        }
+    }
+
+    /// The textbook toffoli circuit (Nielsen & Chuang, figure 4.9)
+    fn toffoli(&mut self, first: &Target, second: &Target, target: &Target) {
+        self.h(target);
+        self.cnot(second, target);
+        self.phase(target, 7);
+        self.cnot(first, target);
+        self.phase(target, 1);
+        self.cnot(second, target);
+        self.phase(target, 7);
+        self.cnot(first, target);
+        self.phase(second, 1);
+        self.phase(target, 1);
+        self.h(target);
+        self.cnot(first, second);
+        self.phase(first, 1);
+        self.phase(second, 7);
+        self.cnot(first, second);
+    }
+
+    /// Computes the AND of the controls into the ancillas one control at a
+    /// time, flips the target on the last of them, and uncomputes them again
+    fn mcx_with_ancillas(&mut self, controls: &[Target], target: &Target, ancillas: &[Target]) {
+        match controls.len() {
+            1 => return self.cnot(&controls[0], target),
+            2 => return self.toffoli(&controls[0], &controls[1], target),
+            _ => {}
+        }
+        let mut chain = vec![(&controls[0], &controls[1], &ancillas[0])];
+        for i in 1..ancillas.len() {
+            chain.push((&controls[i + 1], &ancillas[i - 1], &ancillas[i]));
+        }
+        for &(first, second, result) in &chain {
+            self.toffoli(first, second, result);
+        }
+        self.toffoli(controls.last().unwrap(), ancillas.last().unwrap(), target);
+        for &(first, second, result) in chain.iter().rev() {
+            self.toffoli(first, second, result);
+        }
+    }
+
+    /// Applies the single qubit unitary to the target when every control is 1,
+    /// using u3s and cnots only
+    fn controlled(&mut self, unitary: &Matrix, controls: &[Target], target: &Target) {
+        match controls.len() {
+            1 => self.controlled_once(unitary, &controls[0], target),
+            2 if *unitary == pauli_x() => self.toffoli(&controls[0], &controls[1], target),
+            _ => {
+                // C^k(U) = C(V) C^(k - 1)(X) C(V^dagger) C^(k - 1)(X) C^(k - 1)(V) where V^2 = U,
+                // with the single controls on the last control qubit
+                let root = sqrt(unitary);
+                let (last, rest) = controls.split_last().unwrap();
+                self.controlled(&root, std::slice::from_ref(last), target);
+                self.controlled(&pauli_x(), rest, last);
+                self.controlled(&dagger(&root), std::slice::from_ref(last), target);
+                self.controlled(&pauli_x(), rest, last);
+                self.controlled(&root, rest, target);
+            }
+        }
+    }
+
+    /// A single controlled unitary e^(i * alpha) * u3(theta, phi, lambda), as
+    /// the controlled u3 followed by the phase applied to the control
+    fn controlled_once(&mut self, unitary: &Matrix, control: &Target, target: &Target) {
+        if *unitary == pauli_x() {
+            return self.cnot(control, target);
+        }
+        let (alpha, [theta, phi, lambda]) = u3_angles(unitary);
+        self.u3(control, 0.0, 0.0, (lambda + phi) / 2.0 + alpha);
+        self.u3(target, 0.0, 0.0, (lambda - phi) / 2.0);
+        self.cnot(control, target);
+        self.u3(target, -theta / 2.0, 0.0, -(phi + lambda) / 2.0);
+        self.cnot(control, target);
+        self.u3(target, theta / 2.0, phi, 0.0);
+    }
+}
+
+fn pauli_x() -> Matrix {
+    (gates::signature("x").matrix)(&[])
+}
+
+/// A square root of a 2x2 unitary: by Cayley-Hamilton, (U + sI) / t squares to
+/// U when s^2 = det(U) and t^2 = tr(U) + 2s
+fn sqrt(unitary: &Matrix) -> Matrix {
+    let det = unitary[0][0] * unitary[1][1] - unitary[0][1] * unitary[1][0];
+    let trace = unitary[0][0] + unitary[1][1];
+    let mut s = det.sqrt();
+    if (trace + 2.0 * s).norm() < 1e-9 {
+        // The other root of the determinant avoids dividing by zero
+        s = -s;
+    }
+    let t = (trace + 2.0 * s).sqrt();
+    let identity = [
+        [Complex64::new(1.0, 0.0), Complex64::new(0.0, 0.0)],
+        [Complex64::new(0.0, 0.0), Complex64::new(1.0, 0.0)],
+    ];
+    let root: Matrix = (0..2)
+        .map(|i| {
+            (0..2)
+                .map(|j| (unitary[i][j] + s * identity[i][j]) / t)
+                .collect()
+        })
+        .collect();
+    debug_assert!(matmul(&root, &root)
+        .iter()
+        .flatten()
+        .zip(unitary.iter().flatten())
+        .all(|(a, b)| (a - b).norm() < 1e-6));
+    root
+}
@@ -0,0 +1,43 @@
+//! Pass running other passes over and over until they stop finding anything
+use super::Pass;
+use crate::ast::Program;
+use crate::type_checker::{type_check, SymbolTable};
+
+/// The most rounds we run before giving up on the program settling down
+const MAX_ROUNDS: usize = 16;
+
+/// Runs its passes in order, again and again, until a whole round leaves the
+/// program unchanged. One pass can open up work for another, like
+/// resynthesis turning a run into an `h` that cancels against the next gate,
+/// or dead code elimination leaving two rotations next to each other.
+pub struct RepeatUntilFixed {
+    pub passes: Vec<Box<dyn Pass>>,
+}
+
+impl Pass for RepeatUntilFixed {
+    fn name(&self) -> &'static str {
+        "repeat-until-fixed"
+    }
+
+    fn run(&self, program: &mut Program, symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        let mut notes = vec![];
+        // Like in the pass manager, each pass sees the variables the ones
+        // before it left
+        let mut symbols = symbols.clone();
+        for _ in 0..MAX_ROUNDS {
+            let before = program.clone();
+            for pass in &self.passes {
+                notes.extend(pass.run(program, &symbols)?);
+                symbols = type_check(program)?;
+            }
+            if *program == before {
+                return Ok(notes);
+            }
+        }
+        notes.push(format!(
+            "stopped after {} rounds, before the program settled",
+            MAX_ROUNDS
+        ));
+        Ok(notes)
+    }
+}
@@ -0,0 +1,291 @@
+/// Define optimization passes + pass to check number of Maistow's vs All Assignments for ratio
+/// calculation
+use crate::ast::{Program, RespectExpr, Statement, Stmt, Target};
+use crate::circuit::Circuit;
+use crate::type_checker::{type_check, SymbolTable};
+use std::fmt;
+
+pub mod cancellation;
+pub mod commutation;
+pub mod dead_code;
+pub mod decomposition;
+pub mod fixed_point;
+pub mod resynthesis;
+pub mod rotations;
+pub mod translation;
+
+/// Calculates the ratio of assignments that use Maistow, which is what the
+/// program's respect is judged by (NaN when there are no assignments at all).
+/// Assignments Quill generated itself don't count.
+fn maistow_ratio(program: &Program) -> f64 {
+    let mut maistows: f64 = 0.0;
+    let mut total: f64 = 0.0;
+    for (index, stmt) in program.stmts.iter().enumerate() {
+        if let Stmt::Assign { respect, .. } = stmt.kind {
+            if program.is_generated(index) {
+                continue;
+            }
+            total += 1.0;
+            if respect == RespectExpr::Maistow {
+                maistows += 1.0
+            }
+        }
+    }
+    maistows / total
+}
+
+/// Whether the program is respectful enough to earn any optimizations at all,
+/// which requires between 50% and 90% of its assignments to use Maistow
+pub fn respect_ratio(program: &Program) -> bool {
+    let ratio = maistow_ratio(program);
+    (0.5..=0.9).contains(&ratio)
+}
+
+/// How hard the pass manager works on a program. Every level runs all of the
+/// passes of the levels below it.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+}
+
+/// The optimization level a program has earned through its respect. Programs
+/// outside the respect threshold get no optimizations, and within it, the more
+/// Maistows the better (up to the 90% cap, past which it's just flattery)
+pub fn earned_level(program: &Program) -> OptLevel {
+    if !respect_ratio(program) {
+        return OptLevel::O0;
+    }
+    match maistow_ratio(program) {
+        ratio if ratio < 0.65 => OptLevel::O1,
+        ratio if ratio < 0.8 => OptLevel::O2,
+        _ => OptLevel::O3,
+    }
+}
+
+/// An optimization pass, which rewrites a type checked program in place. It
+/// returns notes on anything the user should hear about, like what it removed,
+/// or why the program couldn't be rewritten.
+pub trait Pass {
+    fn name(&self) -> &'static str;
+    fn run(&self, program: &mut Program, symbols: &SymbolTable) -> Result<Vec<String>, String>;
+}
+
+/// How a single pass changed the program it ran on
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PassResult {
+    pub name: &'static str,
+    pub gates_before: usize,
+    pub gates_after: usize,
+    pub depth_before: usize,
+    pub depth_after: usize,
+    pub notes: Vec<String>,
+}
+
+/// Which passes ran at a given level, in the order they ran
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PassReport {
+    pub level: OptLevel,
+    pub results: Vec<PassResult>,
+}
+
+impl fmt::Display for PassReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Optimization level {:?}", self.level)?;
+        if self.results.is_empty() {
+            return write!(f, ", no passes ran");
+        }
+        for result in &self.results {
+            write!(
+                f,
+                "\n  {}: gates {} -> {}, depth {} -> {}",
+                result.name,
+                result.gates_before,
+                result.gates_after,
+                result.depth_before,
+                result.depth_after
+            )?;
+            for note in &result.notes {
+                write!(f, "\n    {}", note)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Choices about how the passes Quill ships with behave
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PassOptions {
+    /// Whether dead code elimination also removes the declarations of qubits
+    /// nothing uses anymore
+    pub remove_declarations: bool,
+}
+
+impl Default for PassOptions {
+    fn default() -> Self {
+        PassOptions {
+            remove_declarations: true,
+        }
+    }
+}
+
+/// Holds the registry of passes, each along with the lowest level it runs at
+pub struct PassManager {
+    passes: Vec<(OptLevel, Box<dyn Pass>)>,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    /// A pass manager with every pass Quill ships with registered
+    pub fn new() -> PassManager {
+        PassManager::with_options(&PassOptions::default())
+    }
+
+    /// A pass manager with every pass Quill ships with registered, set up
+    /// with the given options
+    pub fn with_options(options: &PassOptions) -> PassManager {
+        let mut manager = PassManager { passes: vec![] };
+        manager.register(OptLevel::O1, Box::new(cancellation::CancelInverses));
+        manager.register(OptLevel::O1, Box::new(rotations::MergeRotations));
+        manager.register(OptLevel::O2, Box::new(resynthesis::ResynthesizeSingleQubit));
+        manager.register(
+            OptLevel::O2,
+            Box::new(dead_code::EliminateDeadCode {
+                remove_declarations: options.remove_declarations,
+            }),
+        );
+        manager.register(
+            OptLevel::O3,
+            Box::new(fixed_point::RepeatUntilFixed {
+                passes: vec![
+                    Box::new(cancellation::CancelInverses),
+                    Box::new(rotations::MergeRotations),
+                    Box::new(resynthesis::ResynthesizeSingleQubit),
+                    Box::new(dead_code::EliminateDeadCode {
+                        remove_declarations: options.remove_declarations,
+                    }),
+                ],
+            }),
+        );
+        manager
+    }
+
+    /// Registers a pass to run at the given level and above. Passes run in the
+    /// order they were registered in.
+    pub fn register(&mut self, level: OptLevel, pass: Box<dyn Pass>) {
+        self.passes.push((level, pass));
+    }
+
+    /// Runs every pass registered at or below the given level over the
+    /// program, stopping at the first one that fails. Passes can declare or
+    /// remove variables, so the symbol table is rebuilt after each one.
+    pub fn run(
+        &self,
+        program: &mut Program,
+        symbols: &mut SymbolTable,
+        level: OptLevel,
+    ) -> Result<PassReport, String> {
+        let mut results = vec![];
+        for (min_level, pass) in &self.passes {
+            if *min_level > level {
+                continue;
+            }
+            let (gates_before, depth_before) = (gate_count(program), depth(program));
+            let notes = pass.run(program, symbols)?;
+            *symbols = type_check(program)?;
+            results.push(PassResult {
+                name: pass.name(),
+                gates_before,
+                gates_after: gate_count(program),
+                depth_before,
+                depth_after: depth(program),
+                notes,
+            });
+        }
+        Ok(PassReport { level, results })
+    }
+}
+
+/// Expands a gate operand into every qubit it covers, as (variable, index)
+/// pairs. Bare names in gate applications are always single qubits.
+pub(crate) fn qubits_of(target: &Target) -> Vec<(&str, usize)> {
+    match target {
+        Target::Name(name) => vec![(name, 0)],
+        Target::Slice { name, start, end } => (*start..=*end).map(|i| (name.as_str(), i)).collect(),
+    }
+}
+
+/// Every qubit a gate application touches, targets first and then controls
+pub(crate) fn wires(stmt: &Stmt) -> Vec<(&str, usize)> {
+    match stmt {
+        Stmt::Gate {
+            targets, controls, ..
+        } => targets.iter().chain(controls).flat_map(qubits_of).collect(),
+        _ => vec![],
+    }
+}
+
+/// The number of gates the program applies, where a single qubit gate over a
+/// range counts once per qubit in the range
+pub fn gate_count(program: &Program) -> usize {
+    program
+        .stmts
+        .iter()
+        .map(|stmt| match &stmt.kind {
+            Stmt::Gate { targets, .. } if targets.len() == 1 => qubits_of(&targets[0]).len(),
+            Stmt::Gate { .. } => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// The number of layers of gates in the program, where gates on disjoint
+/// qubits can share a layer. Like in `gate_count`, a single qubit gate over a
+/// range is a separate gate on each qubit in the range.
+pub fn depth(program: &Program) -> usize {
+    let mut gates = vec![];
+    for stmt in &program.stmts {
+        match &stmt.kind {
+            Stmt::Gate {
+                gate,
+                kind,
+                targets,
+                controls,
+                params,
+            } if targets.len() == 1 && controls.is_empty() => {
+                for (name, i) in qubits_of(&targets[0]) {
+                    let target = match &targets[0] {
+                        Target::Name(_) => targets[0].clone(),
+                        Target::Slice { .. } => Target::Slice {
+                            name: name.to_string(),
+                            start: i,
+                            end: i,
+                        },
+                    };
+                    gates.push(Statement {
+                        line_no: stmt.line_no,
+                        kind: Stmt::Gate {
+                            gate: gate.clone(),
+                            kind: *kind,
+                            targets: vec![target],
+                            controls: vec![],
+                            params: params.clone(),
+                        },
+                    });
+                }
+            }
+            Stmt::Gate { .. } => gates.push(stmt.clone()),
+            _ => {}
+        }
+    }
+    Circuit::from_program(&Program { stmts: gates })
+        .layers()
+        .len()
+}
@@ -0,0 +1,168 @@
+//! Pass fusing runs of single qubit gates into a single u3
+use super::rotations::reduce;
+use super::Pass;
+use crate::ast::{GateExpr, Param, Program, Stmt, Target};
+use crate::gates::{self, matmul, u3_angles, Matrix};
+use crate::type_checker::SymbolTable;
+use num_complex::Complex64;
+use std::collections::HashMap;
+use std::f64::consts::{PI, TAU};
+
+/// How close two numbers have to be for us to treat them as equal
+const TOLERANCE: f64 = 1e-9;
+
+/// The largest denominator we try when writing an angle as a fraction of PI
+const MAX_DENOMINATOR: i32 = 16;
+
+/// Fuses every run of two or more single qubit gates on the same qubit (with
+/// nothing else touching the qubit in between) into the 2x2 unitary they
+/// multiply out to, and re-emits it as one u3 through a ZYZ decomposition. Runs
+/// that multiply out to the identity (up to a global phase) are dropped. Gates
+/// over a range of qubits are left alone, and end any run they touch.
+pub struct ResynthesizeSingleQubit;
+
+impl Pass for ResynthesizeSingleQubit {
+    fn name(&self) -> &'static str {
+        "resynthesize-1q"
+    }
+
+    fn run(&self, program: &mut Program, _symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        // Stores entries of <Qubit, Indices of the statements in its current run>
+        let mut runs: HashMap<(String, usize), Vec<usize>> = HashMap::new();
+        let mut finished: Vec<Vec<usize>> = vec![];
+        for (index, stmt) in program.stmts.iter().enumerate() {
+            if let Some(qubit) = single_qubit_gate(&stmt.kind) {
+                runs.entry(qubit).or_default().push(index);
+                continue;
+            }
+            // Anything else ends the runs of every qubit it could touch
+            let ends_run = |(name, i): &(String, usize)| -> bool {
+                match &stmt.kind {
+                    Stmt::Gate {
+                        targets, controls, ..
+                    } => targets
+                        .iter()
+                        .chain(controls)
+                        .any(|target| covers(target, name, *i)),
+                    Stmt::Measure { measured, .. } => measured.name() == name,
+                    Stmt::Assign { name: assigned, .. } => assigned == name,
+                    Stmt::Return { .. } => true,
+                    Stmt::Comment(_) => false,
+                    _ => unreachable!("modules are linked before optimizing"),
+                }
+            };
+            let ended: Vec<(String, usize)> =
+                runs.keys().filter(|q| ends_run(q)).cloned().collect();
+            for qubit in ended {
+                finished.push(runs.remove(&qubit).unwrap());
+            }
+        }
+        finished.extend(runs.into_values());
+
+        // The fused gate takes the place of the last gate of its run, and the rest are removed
+        let mut removed = vec![false; program.stmts.len()];
+        for run in finished.into_iter().filter(|run| run.len() >= 2) {
+            let unitary = run.iter().fold(identity(), |acc, &index| {
+                matmul(&gate_matrix(&program.stmts[index].kind), &acc)
+            });
+            let (last, rest) = run.split_last().unwrap();
+            for &index in rest {
+                removed[index] = true;
+            }
+            match zyz(&unitary) {
+                Some(params) => {
+                    if let Stmt::Gate { targets, .. } = &program.stmts[*last].kind {
+                        program.stmts[*last].kind = Stmt::Gate {
+                            gate: "u3".to_string(),
+                            kind: GateExpr::Q1ParamGate,
+                            targets: targets.clone(),
+                            controls: vec![],
+                            params: params.into_iter().map(angle_param).collect(),
+                        };
+                    }
+                }
+                None => removed[*last] = true,
+            }
+        }
+        let mut index = 0;
+        program.stmts.retain(|_| {
+            index += 1;
+            !removed[index - 1]
+        });
+        Ok(vec![])
+    }
+}
+
+/// If the statement is an uncontrolled single qubit gate on one qubit, the qubit it acts on
+fn single_qubit_gate(stmt: &Stmt) -> Option<(String, usize)> {
+    match stmt {
+        Stmt::Gate {
+            gate,
+            targets,
+            controls,
+            ..
+        } if controls.is_empty() && gates::signature(gate).qubits == 1 => match &targets[0] {
+            Target::Name(name) => Some((name.clone(), 0)),
+            Target::Slice { name, start, end } if start == end => Some((name.clone(), *start)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether the target includes the given qubit
+fn covers(target: &Target, name: &str, index: usize) -> bool {
+    match target {
+        Target::Name(var) => var == name,
+        Target::Slice {
+            name: var,
+            start,
+            end,
+        } => var == name && (*start..=*end).contains(&index),
+    }
+}
+
+fn gate_matrix(stmt: &Stmt) -> Matrix {
+    match stmt {
+        Stmt::Gate { gate, params, .. } => {
+            let params: Vec<f64> = params.iter().map(|param| param.value()).collect();
+            (gates::signature(gate).matrix)(&params)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn identity() -> Matrix {
+    vec![
+        vec![Complex64::new(1.0, 0.0), Complex64::new(0.0, 0.0)],
+        vec![Complex64::new(0.0, 0.0), Complex64::new(1.0, 0.0)],
+    ]
+}
+
+/// Finds the (theta, phi, lambda) of the u3 equal to the unitary up to a global
+/// phase, or None if the unitary is the identity (up to a global phase). Since
+/// u3(theta, phi, lambda) = e^(i(phi + lambda)/2) * Rz(phi) * Ry(theta) * Rz(lambda),
+/// this is the ZYZ decomposition of the unitary.
+fn zyz(unitary: &Matrix) -> Option<[f64; 3]> {
+    let (_, [theta, phi, lambda]) = u3_angles(unitary);
+    let is_identity = theta.abs() < TOLERANCE && {
+        let total = (phi + lambda).rem_euclid(TAU);
+        total < TOLERANCE || TAU - total < TOLERANCE
+    };
+    (!is_identity).then_some([theta, phi, lambda])
+}
+
+/// Writes an angle as an exact fraction of PI if it's (numerically) one with a
+/// small denominator, and as a float otherwise
+pub(super) fn angle_param(angle: f64) -> Param {
+    let angle = angle.rem_euclid(TAU);
+    for den in 1..=MAX_DENOMINATOR {
+        let num = angle / PI * den as f64;
+        if (num - num.round()).abs() < TOLERANCE * den as f64 {
+            let num = num.round() as i64;
+            // A full turn rounds back to nothing
+            return reduce(num % (2 * den as i64), den as i64);
+        }
+    }
+    Param::Float(angle)
+}
@@ -0,0 +1,146 @@
+//! Pass merging commuting rotations and removing the ones that do nothing
+use super::commutation::CommutationDag;
+use super::{qubits_of, Pass};
+use crate::ast::{Param, Program, Stmt, Target};
+use crate::type_checker::SymbolTable;
+use std::collections::HashSet;
+use std::f64::consts::TAU;
+
+/// The gates whose angles add up when they're applied one after another
+const ROTATIONS: [&str; 6] = ["rx", "ry", "rz", "rxx", "ryy", "rzz"];
+
+/// How close to a multiple of 2 * PI a float angle has to be to count as identity
+const TOLERANCE: f64 = 1e-9;
+
+/// Merges rotations of the same kind on the same qubits into one, by adding up
+/// their angles, whenever everything between them commutes with the rotation
+/// (like the `cnot` in `rz(q1) cnot(q2, control q1) rz(q1)`). Then removes
+/// every rotation whose angle is a multiple of 2 * PI (these are the identity,
+/// up to a global phase). Angles
+/// are normalized into [0, 2 * PI), and when every angle involved is written
+/// in terms of PI, the result is kept as an exact fraction of PI.
+pub struct MergeRotations;
+
+impl Pass for MergeRotations {
+    fn name(&self) -> &'static str {
+        "merge-rotations"
+    }
+
+    fn run(&self, program: &mut Program, _symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        while let Some((first, second)) = find_pair(program) {
+            let angle = match &program.stmts[second].kind {
+                Stmt::Gate { params, .. } => params[0].clone(),
+                _ => unreachable!(),
+            };
+            if let Stmt::Gate { params, .. } = &mut program.stmts[first].kind {
+                params[0] = normalize(add(params[0].clone(), angle));
+            }
+            program.stmts.remove(second);
+        }
+
+        program.stmts.retain(|stmt| match &stmt.kind {
+            Stmt::Gate { gate, params, .. } if ROTATIONS.contains(&gate.as_str()) => {
+                !is_identity(normalize(params[0].clone()))
+            }
+            _ => true,
+        });
+        for stmt in &mut program.stmts {
+            if let Stmt::Gate { gate, params, .. } = &mut stmt.kind {
+                if ROTATIONS.contains(&gate.as_str()) {
+                    params[0] = normalize(params[0].clone());
+                }
+            }
+        }
+        Ok(vec![])
+    }
+}
+
+/// Finds the first rotation that can be commuted up against the same rotation
+/// on the same qubits. The merged rotation stays where the first one was,
+/// which is fine since the second commutes with everything the first does.
+fn find_pair(program: &Program) -> Option<(usize, usize)> {
+    let dag = CommutationDag::new(program);
+    (0..program.stmts.len()).find_map(|first| {
+        let gate1 = match &program.stmts[first].kind {
+            Stmt::Gate { gate, .. } if ROTATIONS.contains(&gate.as_str()) => gate,
+            _ => return None,
+        };
+        let partners = dag.partners(program, first);
+        partners.into_iter().find_map(|second| {
+            match (&program.stmts[first].kind, &program.stmts[second].kind) {
+                (
+                    Stmt::Gate {
+                        targets: targets1, ..
+                    },
+                    Stmt::Gate {
+                        gate: gate2,
+                        targets: targets2,
+                        ..
+                    },
+                ) if gate1 == gate2 && same_qubits(targets1, targets2) => Some((first, second)),
+                _ => None,
+            }
+        })
+    })
+}
+
+/// The two qubit rotations are symmetric, so only the set of qubits matters
+fn same_qubits(targets1: &[Target], targets2: &[Target]) -> bool {
+    let expand = |targets: &[Target]| -> Vec<(String, usize)> {
+        targets
+            .iter()
+            .flat_map(qubits_of)
+            .map(|(name, i)| (name.to_string(), i))
+            .collect()
+    };
+    let (qubits1, qubits2) = (expand(targets1), expand(targets2));
+    qubits1.len() == qubits2.len()
+        && HashSet::<&(String, usize)>::from_iter(&qubits1) == HashSet::from_iter(&qubits2)
+}
+
+/// Adds two angles, keeping the sum as an exact fraction of PI when both are
+fn add(first: Param, second: Param) -> Param {
+    match (&first, &second) {
+        (&Param::PI(num1, den1), &Param::PI(num2, den2)) => reduce(
+            num1 as i64 * den2 as i64 + num2 as i64 * den1 as i64,
+            den1 as i64 * den2 as i64,
+        ),
+        _ => Param::Float(first.value() + second.value()),
+    }
+}
+
+/// Brings an angle into [0, 2 * PI)
+fn normalize(angle: Param) -> Param {
+    match angle {
+        // PI * num / den is in [0, 2 * PI) exactly when num is in [0, 2 * den)
+        Param::PI(num, den) => reduce((num as i64).rem_euclid(2 * den as i64), den as i64),
+        Param::Int(val) if (0.0..TAU).contains(&(val as f64)) => angle,
+        _ => Param::Float(angle.value().rem_euclid(TAU)),
+    }
+}
+
+fn is_identity(angle: Param) -> bool {
+    match angle {
+        Param::PI(num, _) => num == 0,
+        _ => angle.value() < TOLERANCE || TAU - angle.value() < TOLERANCE,
+    }
+}
+
+/// Builds PI * num / den in lowest terms, falling back to a float if the
+/// fraction can't be written with the integers the PI construct allows
+pub(super) fn reduce(num: i64, den: i64) -> Param {
+    let divisor = gcd(num.abs(), den.abs()).max(1);
+    let (num, den) = (num / divisor, den / divisor);
+    match (i32::try_from(num), i32::try_from(den)) {
+        (Ok(num), Ok(den)) => Param::PI(num, den),
+        _ => Param::Float(std::f64::consts::PI * num as f64 / den as f64),
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
@@ -0,0 +1,376 @@
+//! Pass translating every gate into a user-chosen set of basis gates
+use super::decomposition::{BasisSet, DecomposeMultiControlled};
+use super::resynthesis::angle_param;
+use super::Pass;
+use crate::ast::{Param, Program, Statement, Stmt, Target};
+use crate::gates::{self, dagger, matmul, u3_angles, Controls, Matrix};
+use crate::type_checker::SymbolTable;
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+/// How close two numbers have to be for us to treat them as equal
+const TOLERANCE: f64 = 1e-9;
+
+/// The two qubit gates a basis can entangle with, in order of preference
+const ENTANGLERS: [&str; 5] = ["cnot", "cz", "rzz", "rxx", "ryy"];
+
+/// How a basis writes an arbitrary single qubit unitary, which is always some
+/// Euler decomposition of it
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Euler {
+    /// u3(theta, phi, lambda)
+    U3,
+    /// rz(lambda), ry(theta), rz(phi)
+    ZY,
+    /// rz(lambda - PI / 2), rx(theta), rz(phi + PI / 2)
+    ZX,
+    /// rz(lambda), sx, rz(theta + PI), sx, rz(phi + PI)
+    ZSx,
+    /// rx, ry, rx, from the ZYZ decomposition of the unitary conjugated by h
+    XY,
+}
+
+/// Rewrites every gate of a program into the given basis gates, like
+/// `rz, sx, cx` for IBM-like devices or `rx, ry, rzz` for trapped ions, so the
+/// result can go straight to a backend that only knows those gates. Gates
+/// already in the basis are left alone (angles and all), multi-controlled gates
+/// are decomposed first, two qubit gates are rewritten around whichever of
+/// cnot, cz, rzz, rxx, or ryy the basis has, and what's left over on single
+/// qubits is fused into one unitary at a time and written back out with an
+/// Euler decomposition. Every step is exact up to a global phase, and angles
+/// that are fractions of PI stay that way.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct BasisTranslation {
+    gates: Vec<&'static str>,
+    entangler: &'static str,
+    euler: Euler,
+}
+
+impl BasisTranslation {
+    /// Checks that the basis only has known gates with a fixed number of
+    /// qubits, and that every gate can be written in it
+    pub fn new(basis: &[&str]) -> Result<BasisTranslation, String> {
+        let mut gates = vec![];
+        for name in basis {
+            let signature = match gates::lookup(name) {
+                Some(signature) => signature,
+                None => return Err(format!("Unknown gate {:?} given in the basis!", name)),
+            };
+            if let Controls::AtLeast(_) = signature.controls {
+                return Err(format!(
+                    "{} takes any number of controls, so it can't be a basis gate!",
+                    name
+                ));
+            }
+            gates.push(signature.name);
+        }
+        let has = |gate: &str| gates.contains(&gate);
+
+        let entangler = match ENTANGLERS.into_iter().find(|gate| has(gate)) {
+            Some(entangler) => entangler,
+            None => {
+                return Err(format!(
+                    "The basis needs one of {} to entangle qubits with!",
+                    ENTANGLERS.join(", ")
+                ))
+            }
+        };
+        let euler = if has("u3") {
+            Euler::U3
+        } else if has("rz") && has("ry") {
+            Euler::ZY
+        } else if has("rz") && has("rx") {
+            Euler::ZX
+        } else if has("rz") && has("sx") {
+            Euler::ZSx
+        } else if has("rx") && has("ry") {
+            Euler::XY
+        } else {
+            return Err(
+                "The basis needs u3, two of rx, ry, and rz, or rz and sx to write single qubit gates with!"
+                    .to_string(),
+            );
+        };
+        Ok(BasisTranslation {
+            gates,
+            entangler,
+            euler,
+        })
+    }
+
+    /// The (canonical) names of the basis gates
+    pub fn gates(&self) -> &[&'static str] {
+        &self.gates
+    }
+
+    fn has(&self, gate: &str) -> bool {
+        self.gates.contains(&gate)
+    }
+}
+
+impl Pass for BasisTranslation {
+    fn name(&self) -> &'static str {
+        "translate-basis"
+    }
+
+    fn run(&self, program: &mut Program, symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        let decompose = DecomposeMultiControlled {
+            basis: BasisSet::U3Cnot,
+            ancillas: false,
+        };
+        let mut notes = vec![];
+        let mut stmts = vec![];
+        for stmt in program.stmts.drain(..) {
+            let gate = match &stmt.kind {
+                Stmt::Gate { gate, .. } => gates::signature(gate).name,
+                _ => {
+                    stmts.push(stmt);
+                    continue;
+                }
+            };
+            if self.has(gate) {
+                stmts.push(stmt);
+                continue;
+            }
+            // Multi-controlled gates outside the basis are brought down to
+            // u3 and cnot first, one gate at a time so the ones in it stay
+            let mut decomposed = Program { stmts: vec![stmt] };
+            notes.extend(decompose.run(&mut decomposed, symbols)?);
+            for stmt in decomposed.stmts {
+                let mut lowering = Lowering {
+                    translation: self,
+                    line_no: stmt.line_no,
+                    pieces: vec![],
+                };
+                lowering.gate(&stmt.kind);
+                stmts.extend(lowering.finish());
+            }
+        }
+        program.stmts = stmts;
+        Ok(notes)
+    }
+}
+
+/// Part of a gate's translation: either a basis gate, or a single qubit
+/// unitary that still has to be written in the basis
+enum Piece {
+    Native(Stmt),
+    Single(Target, Matrix),
+}
+
+/// Translates a single gate application
+struct Lowering<'a> {
+    translation: &'a BasisTranslation,
+    line_no: i32,
+    pieces: Vec<Piece>,
+}
+
+impl Lowering<'_> {
+    fn gate(&mut self, stmt: &Stmt) {
+        let (gate, targets, controls, params) = match stmt {
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                params,
+                ..
+            } => (gates::signature(gate), targets, controls, params),
+            _ => unreachable!(),
+        };
+        if gate.qubits == 1 && controls.is_empty() {
+            let values: Vec<f64> = params.iter().map(|param| param.value()).collect();
+            return self.single(&targets[0], (gate.matrix)(&values));
+        }
+        let (first, second) = (&targets[0], targets.get(1).unwrap_or(&targets[0]));
+        match gate.name {
+            "cnot" => self.cnot(&controls[0], first),
+            "cz" => {
+                self.single(first, hadamard());
+                self.cnot(&controls[0], first);
+                self.single(first, hadamard());
+            }
+            "swap" => {
+                self.cnot(first, second);
+                self.cnot(second, first);
+                self.cnot(first, second);
+            }
+            "rzz" => self.zz(first, second, params[0].clone()),
+            "rxx" => {
+                // XX = (H x H) ZZ (H x H)
+                self.both(first, second, hadamard());
+                self.zz(first, second, params[0].clone());
+                self.both(first, second, hadamard());
+            }
+            "ryy" => {
+                // YY = (V x V) ZZ (V x V)^dagger, where V = SH turns Z into Y
+                self.both(first, second, dagger(&sh()));
+                self.zz(first, second, params[0].clone());
+                self.both(first, second, sh());
+            }
+            // Multi-controlled gates were decomposed before translating
+            _ => unreachable!(),
+        }
+    }
+
+    fn native(
+        &mut self,
+        gate: &str,
+        targets: &[&Target],
+        controls: &[&Target],
+        params: Vec<Param>,
+    ) {
+        self.pieces.push(Piece::Native(Stmt::Gate {
+            gate: gate.to_string(),
+            kind: gates::signature(gate).kind,
+            targets: targets.iter().map(|&target| target.clone()).collect(),
+            controls: controls.iter().map(|&control| control.clone()).collect(),
+            params,
+        }));
+    }
+
+    /// A single qubit unitary, fused into the one right before it when that's
+    /// on the same target
+    fn single(&mut self, target: &Target, unitary: Matrix) {
+        if let Some(Piece::Single(last, previous)) = self.pieces.last_mut() {
+            if last == target {
+                *previous = matmul(&unitary, previous);
+                return;
+            }
+        }
+        self.pieces.push(Piece::Single(target.clone(), unitary));
+    }
+
+    fn both(&mut self, first: &Target, second: &Target, unitary: Matrix) {
+        self.single(first, unitary.clone());
+        self.single(second, unitary);
+    }
+
+    fn cnot(&mut self, control: &Target, target: &Target) {
+        match self.translation.entangler {
+            "cnot" => self.native("cnot", &[target], &[control], vec![]),
+            "cz" => {
+                self.single(target, hadamard());
+                self.native("cz", &[target], &[control], vec![]);
+                self.single(target, hadamard());
+            }
+            _ => {
+                // CZ = e^(i * PI / 4) * rzz(PI / 2) (rz(-PI / 2) x rz(-PI / 2))
+                self.single(target, hadamard());
+                self.zz(control, target, Param::PI(1, 2));
+                self.both(control, target, rz(-FRAC_PI_2));
+                self.single(target, hadamard());
+            }
+        }
+    }
+
+    fn zz(&mut self, first: &Target, second: &Target, angle: Param) {
+        if self.translation.has("rzz") {
+            self.native("rzz", &[first, second], &[], vec![angle]);
+        } else if self.translation.has("rxx") {
+            self.both(first, second, hadamard());
+            self.native("rxx", &[first, second], &[], vec![angle]);
+            self.both(first, second, hadamard());
+        } else if self.translation.has("ryy") {
+            self.both(first, second, sh());
+            self.native("ryy", &[first, second], &[], vec![angle]);
+            self.both(first, second, dagger(&sh()));
+        } else {
+            self.cnot(first, second);
+            self.single(second, rz(angle.value()));
+            self.cnot(first, second);
+        }
+    }
+
+    /// Writes out every piece as basis gates
+    fn finish(self) -> Vec<Statement> {
+        let mut stmts = vec![];
+        for piece in self.pieces {
+            match piece {
+                Piece::Native(kind) => stmts.push(kind),
+                Piece::Single(target, unitary) => {
+                    for (gate, angle) in euler(self.translation.euler, &unitary) {
+                        let params = angle.into_iter().map(angle_param).collect();
+                        stmts.push(Stmt::Gate {
+                            gate: gate.to_string(),
+                            kind: gates::signature(gate).kind,
+                            targets: vec![target.clone()],
+                            controls: vec![],
+                            params,
+                        });
+                    }
+                }
+            }
+        }
+        stmts
+            .into_iter()
+            .map(|kind| Statement {
+                line_no: self.line_no,
+                kind,
+            })
+            .collect()
+    }
+}
+
+/// The gates (with their angles, if any) of the Euler decomposition of a
+/// single qubit unitary, in the order they're applied. Rotations by a
+/// multiple of 2 * PI only change the global phase, so they're left out.
+fn euler(method: Euler, unitary: &Matrix) -> Vec<(&'static str, Vec<f64>)> {
+    let (_, [theta, phi, lambda]) = u3_angles(unitary);
+    let gates = match method {
+        Euler::U3 => {
+            if is_zero(theta) && is_zero(phi + lambda) {
+                return vec![];
+            }
+            return vec![("u3", vec![theta, phi, lambda])];
+        }
+        Euler::ZY => vec![("rz", lambda), ("ry", theta), ("rz", phi)],
+        Euler::ZX => vec![
+            ("rz", lambda - FRAC_PI_2),
+            ("rx", theta),
+            ("rz", phi + FRAC_PI_2),
+        ],
+        Euler::ZSx if is_zero(theta) => vec![("rz", phi + lambda)],
+        Euler::ZSx => {
+            return [
+                ("rz", Some(lambda)),
+                ("sx", None),
+                ("rz", Some(theta + PI)),
+                ("sx", None),
+                ("rz", Some(phi + PI)),
+            ]
+            .into_iter()
+            .filter(|(_, angle)| angle.is_none_or(|angle| !is_zero(angle)))
+            .map(|(gate, angle)| (gate, angle.into_iter().collect()))
+            .collect();
+        }
+        Euler::XY => {
+            // H rz H = rx and H ry H = ry^dagger, so U = H (H U H) H comes out as rx ry rx
+            let conjugated = matmul(&hadamard(), &matmul(unitary, &hadamard()));
+            let (_, [theta, phi, lambda]) = u3_angles(&conjugated);
+            vec![("rx", lambda), ("ry", -theta), ("rx", phi)]
+        }
+    };
+    gates
+        .into_iter()
+        .filter(|(_, angle)| !is_zero(*angle))
+        .map(|(gate, angle)| (gate, vec![angle]))
+        .collect()
+}
+
+/// Whether an angle is a multiple of 2 * PI
+fn is_zero(angle: f64) -> bool {
+    let angle = angle.rem_euclid(TAU);
+    angle < TOLERANCE || TAU - angle < TOLERANCE
+}
+
+fn hadamard() -> Matrix {
+    (gates::signature("h").matrix)(&[])
+}
+
+fn rz(angle: f64) -> Matrix {
+    (gates::signature("rz").matrix)(&[angle])
+}
+
+/// SH, which turns Z into Y (and X into -X) when conjugating by it
+fn sh() -> Matrix {
+    matmul(&(gates::signature("s").matrix)(&[]), &hadamard())
+}
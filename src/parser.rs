@@ -1,309 +1,918 @@
-use crate::ast::{ASTNode, GateExpr, NodeKind, RespectExpr, ValueExpr};
-use pest::error::Error;
-use pest::Parser;
+use crate::ast::{Param, Program, RespectExpr, Statement, Stmt, Target, Value, ValueExpr};
+use crate::composites;
+use crate::gates;
+use pest::error::{Error, ErrorVariant, InputLocation};
+use pest::iterators::Pair;
+use pest::{Parser, Position, Span};
 use pest_derive::Parser;
+use std::fmt;
 use std::result::Result;
 
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
 pub struct QuillParser;
 
-pub fn parse(source: &str) -> Result<ASTNode, Error<Rule>> {
-    let pairs = QuillParser::parse(Rule::Program, source)?;
+type ParseResult<T> = Result<T, Box<Error<Rule>>>;
 
-    let ast = pairs
-        .into_iter()
-        .map(|pair| build_node(pair).unwrap())
-        .collect();
+/// Every error found while parsing a program, in the order of the lines
+/// they're on
+#[derive(Debug)]
+pub struct ParseErrors {
+    pub errors: Vec<Error<Rule>>,
+}
 
-    let root = ASTNode {
-        children: Some(ast),
-        node_kind: NodeKind::Program,
-    };
+impl ParseErrors {
+    /// Shows the file name in every error
+    pub fn with_path(self, path: &str) -> ParseErrors {
+        ParseErrors {
+            errors: self
+                .errors
+                .into_iter()
+                .map(|error| error.with_path(path))
+                .collect(),
+        }
+    }
+}
 
-    Ok(root)
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            // A blank line between errors
+            if i > 0 {
+                write!(f, "\n\n")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
 }
 
-fn build_node(pair: pest::iterators::Pair<Rule>) -> Option<ASTNode> {
-    match pair.as_rule() {
-        Rule::AssignStmt => {
-            let mut pair = pair.into_inner();
-            let respect = match pair.next()?.as_str() {
-                "Maistow" => RespectExpr::Maistow,
-                "Canstow" => RespectExpr::Canstow,
-                _ => panic!("Unrecognized respect expression!"),
-            };
-            let val_type = match pair.next()?.as_str() {
-                "qreg" => ValueExpr::QReg,
-                "qubit" => ValueExpr::Qubit,
-                "creg" => ValueExpr::CReg,
-                "cbit" => ValueExpr::CBit,
-                unknown => panic!("{:?} is not a valid type to assign!", unknown),
-            };
-            let name = build_node(pair.next()?)?;
-            let value = build_node(pair.next()?)?;
+/// The words a program is written with. Middle English is the default, and a
+/// file is written in modern English when its first line is the header
+/// `// dialect: modern`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Dialect {
+    #[default]
+    MiddleEnglish,
+    Modern,
+}
 
-            let respect_node = ASTNode {
-                children: None,
-                node_kind: NodeKind::RespectType(respect),
-            };
-            let val_type_node = ASTNode {
-                children: None,
-                node_kind: NodeKind::ValueType(val_type),
-            };
+impl Dialect {
+    /// Reads a dialect's name, as written in a header or on the command line
+    pub fn from_name(name: &str) -> Option<Dialect> {
+        match name.trim().to_lowercase().as_str() {
+            "middle english" | "middle-english" | "middle" => Some(Dialect::MiddleEnglish),
+            "modern" | "modern english" | "modern-english" => Some(Dialect::Modern),
+            _ => None,
+        }
+    }
 
-            Some(ASTNode {
-                children: Some(vec![respect_node, val_type_node, name, value]),
-                node_kind: NodeKind::Assignment,
-            })
+    /// The header that selects this dialect
+    pub fn header(self) -> &'static str {
+        match self {
+            Dialect::MiddleEnglish => "// dialect: middle english",
+            Dialect::Modern => "// dialect: modern",
         }
-        Rule::GateStmt => {
-            let mut pair = pair.into_inner();
-            let gate_type = pair.next()?;
-            let target = build_node(pair.next()?)?;
-            let mut controls = None;
-            let mut params = None;
-            loop {
-                if let Some(next_rule) = pair.next() {
-                    match next_rule.as_rule() {
-                        Rule::ControlList => {
-                            controls = Some(build_node(next_rule)?);
-                        }
-                        Rule::ValList => {
-                            params = Some(build_node(next_rule)?);
-                            break;
-                        }
-                        _ => panic!(
-                            "Gate Application Statement does not support {} yet!",
-                            next_rule.as_str()
-                        ),
-                    }
-                } else {
-                    break;
-                }
-            }
-            build_gate_app(gate_type, target, controls, params)
+    }
+
+    /// The rules for a line of a program, and for a statement on its own
+    fn rules(self) -> (Rule, Rule) {
+        match self {
+            Dialect::MiddleEnglish => (Rule::ProgramLine, Rule::Statement),
+            Dialect::Modern => (Rule::ModernLine, Rule::ModernStatement),
         }
-        Rule::MeasureStmt => {
-            let mut pair = pair.into_inner();
-            let measured = build_node(pair.next()?)?; // Either Name or QRegSlice
-            let recipient = build_node(pair.next()?)?; // Either Name or CRegSlice
-            Some(ASTNode {
-                children: Some(vec![measured, recipient]),
-                node_kind: NodeKind::Measurement,
-            })
+    }
+}
+
+/// The dialect a comment (given without its "//") selects, if it's a dialect
+/// header at all
+pub fn dialect_header(comment: &str) -> Option<Result<Dialect, String>> {
+    let comment = comment.trim();
+    let name = comment
+        .get(..8)
+        .filter(|start| start.eq_ignore_ascii_case("dialect:"))
+        .map(|_| &comment[8..])?;
+    Some(Dialect::from_name(name).ok_or_else(|| {
+        format!(
+            "{:?} isn't a dialect, try `modern` or `middle english`",
+            name.trim()
+        )
+    }))
+}
+
+/// The dialect a program's header selects (Middle English if it doesn't have
+/// one, or names a dialect that doesn't exist)
+pub fn dialect_of(source: &str) -> Dialect {
+    source
+        .lines()
+        .next()
+        .and_then(|line| line.trim().strip_prefix("//"))
+        .and_then(dialect_header)
+        .and_then(Result::ok)
+        .unwrap_or_default()
+}
+
+/// Whether a word is one of the modern dialect's own keywords, which are only
+/// reserved in modern English
+pub fn is_modern_keyword(word: &str) -> bool {
+    QuillParser::parse(Rule::ModernKeyword, word).is_ok_and(|pairs| pairs.as_str() == word)
+}
+
+/// Parses a whole program, in the dialect its header selects. Statements never
+/// span lines, so a line that doesn't parse is skipped and the rest are still
+/// read, and every malformed line is reported at once.
+pub fn parse(source: &str) -> Result<Program, ParseErrors> {
+    let dialect = dialect_of(source);
+    let (rule, _) = dialect.rules();
+    let mut stmts = vec![];
+    let mut errors = vec![];
+    // The gates defined so far, and the definition being read (along with
+    // where its first line is), if any
+    let mut defined = vec![];
+    let mut open: Option<(Statement, usize)> = None;
+    let mut offset = 0;
+    for (i, line) in source.split_inclusive('\n').enumerate() {
+        let line_no = i as i32 + 1;
+        let parsed = QuillParser::parse(rule, line)
+            .map_err(|error| Box::new(explained(error, line, dialect)))
+            .and_then(|pairs| {
+                for pair in pairs.filter(|pair| pair.as_rule() != Rule::EOI) {
+                    read_pair(pair, line_no, &mut defined, &mut open, offset, &mut stmts)?;
+                }
+                Ok(())
+            });
+        if let Err(error) = parsed {
+            errors.push(relocate(*error, source, offset));
         }
-        Rule::ReturnStmt => {
-            let mut pair = pair.into_inner();
-            let shots = build_node(pair.next()?)?; // Int Node
-            Some(ASTNode {
-                children: Some(vec![shots]),
-                node_kind: NodeKind::Return,
-            })
+        offset += line.len();
+    }
+    if let Some((header, start)) = open {
+        let Stmt::GateDef { name, .. } = &header.kind else {
+            unreachable!()
+        };
+        let end = start + source[start..].lines().next().unwrap().trim_end().len();
+        errors.push(Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: format!("the definition of {} needs an `Ende`", name),
+            },
+            Span::new(source, start, end).unwrap(),
+        ));
+    }
+    // A header naming a dialect that doesn't exist
+    if let Some(Stmt::Comment(text)) = stmts
+        .first()
+        .filter(|stmt| stmt.line_no == 1)
+        .map(|stmt| &stmt.kind)
+    {
+        if let Some(Err(message)) = dialect_header(text) {
+            let end = source.lines().next().unwrap().trim_end().len();
+            let span = Span::new(source, 0, end).unwrap();
+            errors.insert(
+                0,
+                Error::new_from_span(ErrorVariant::CustomError { message }, span),
+            );
         }
-        Rule::QReg => {
-            let mut pair = pair.into_inner();
-            let qubit = build_node(pair.next()?)?;
-            let length = build_node(pair.next()?)?; // Should parse to Index node correctly
+    }
+    if errors.is_empty() {
+        Ok(Program { stmts })
+    } else {
+        Err(ParseErrors { errors })
+    }
+}
 
-            Some(ASTNode {
-                children: Some(vec![qubit, length]),
-                node_kind: NodeKind::QReg,
-            })
+/// Adds a statement read from a line of a program (which starts at `offset`)
+/// to the program, or to the body of the gate being defined. Gate definitions
+/// are put together here, since they span lines.
+fn read_pair(
+    pair: Pair<Rule>,
+    line_no: i32,
+    defined: &mut Vec<String>,
+    open: &mut Option<(Statement, usize)>,
+    offset: usize,
+    stmts: &mut Vec<Statement>,
+) -> ParseResult<()> {
+    match (pair.as_rule(), open.take()) {
+        (Rule::EndStmt | Rule::ModernEndStmt, Some((header, _))) => {
+            if let Stmt::GateDef { name, .. } = &header.kind {
+                defined.push(name.clone());
+            }
+            stmts.push(header);
         }
-        Rule::QRegTensor => {
-            let qregs: Vec<ASTNode> = pair
-                .into_inner()
-                .map(|pair| build_node(pair).unwrap())
-                .collect();
-            Some(ASTNode {
-                children: Some(qregs),
-                node_kind: NodeKind::QRegTensor,
-            })
+        (Rule::GateDefStmt | Rule::ModernGateDefStmt, Some(header)) => {
+            *open = Some(header);
+            return Err(custom_error(
+                &pair,
+                "gates can't be defined inside another gate's definition".to_string(),
+            ));
         }
-        Rule::QRegSlice => {
-            let mut pair = pair.into_inner();
-            let name = build_node(pair.next()?)?;
-            let mut indices = vec![];
-            let ind1 = build_node(pair.next()?)?; // Parse to Index
-            indices.push(ind1);
-            if let Some(next_rule) = pair.next() {
-                indices.push(build_node(next_rule)?);
+        (Rule::GateDefStmt | Rule::ModernGateDefStmt, None) => {
+            let kind = build_stmt(pair.clone(), defined)?;
+            let Stmt::GateDef { name, .. } = &kind else {
+                unreachable!()
+            };
+            if gates::lookup(name).is_some() || composites::lookup(name).is_some() {
+                return Err(custom_error(
+                    &pair,
+                    format!("{} is already a gate, so it can't be defined again", name),
+                ));
+            }
+            if defined.contains(name) {
+                return Err(custom_error(
+                    &pair,
+                    format!("{} was already defined earlier", name),
+                ));
             }
-            let indices_node = ASTNode {
-                children: Some(indices),
-                node_kind: NodeKind::Indices,
+            *open = Some((Statement { line_no, kind }, offset));
+        }
+        (_, Some((mut header, start))) => {
+            let kind = build_stmt(pair.clone(), defined);
+            // Put the definition back before reporting anything, so the rest
+            // of its body is still read
+            let Stmt::GateDef { body, .. } = &mut header.kind else {
+                unreachable!()
             };
-            Some(ASTNode {
-                children: Some(vec![name, indices_node]),
-                node_kind: NodeKind::QRegSlice,
-            })
+            let added = kind.and_then(|kind| match kind {
+                Stmt::Gate { .. } | Stmt::Call { .. } | Stmt::Comment(_) => {
+                    body.push(Statement { line_no, kind });
+                    Ok(())
+                }
+                _ => Err(custom_error(
+                    &pair,
+                    "only gate applications can go in a gate's definition".to_string(),
+                )),
+            });
+            *open = Some((header, start));
+            added?;
         }
-        Rule::Qubit => Some(ASTNode {
-            children: None,
-            node_kind: NodeKind::Qubit(pair.as_str().to_string()),
+        (_, None) => stmts.push(Statement {
+            line_no,
+            kind: build_stmt(pair, defined)?,
         }),
-        Rule::CReg => {
-            let mut pair = pair.into_inner();
-            let cbit = build_node(pair.next()?)?;
-            let length = build_node(pair.next()?)?;
-            Some(ASTNode {
-                children: Some(vec![cbit, length]),
-                node_kind: NodeKind::CReg,
-            })
+    }
+    Ok(())
+}
+
+/// Parses a single statement in the given dialect, like a line typed into the
+/// REPL, which is reported as being on line `line_no`
+pub fn parse_statement(source: &str, line_no: i32, dialect: Dialect) -> ParseResult<Statement> {
+    let (_, rule) = dialect.rules();
+    let pair = QuillParser::parse(rule, source)
+        .map_err(|error| Box::new(explained(error, source, dialect)))?
+        .next()
+        .unwrap();
+    Ok(Statement {
+        line_no,
+        kind: build_stmt(pair, &[])?,
+    })
+}
+
+/// Explains what the grammar expected in Quill's own words, instead of as a
+/// list of rules
+fn explained(error: Error<Rule>, line: &str, dialect: Dialect) -> Error<Rule> {
+    let line = line.lines().next().unwrap_or("");
+    match &error.variant {
+        ErrorVariant::ParsingError {
+            positives,
+            negatives,
+        } => {
+            let position = match error.location {
+                InputLocation::Pos(position) | InputLocation::Span((position, _)) => position,
+            };
+            let message = explain(
+                line,
+                position.min(line.len()),
+                positives,
+                negatives,
+                dialect,
+            );
+            Error::new_from_pos(
+                ErrorVariant::CustomError { message },
+                Position::new(line, position.min(line.len())).unwrap(),
+            )
         }
-        Rule::CRegTensor => {
-            let cregs: Vec<ASTNode> = pair
-                .into_inner()
-                .map(|pair| build_node(pair).unwrap())
+        ErrorVariant::CustomError { .. } => error,
+    }
+}
+
+/// Moves an error found in the line starting at `offset` in `source` to where
+/// it is in the whole source
+fn relocate(error: Error<Rule>, source: &str, offset: usize) -> Error<Rule> {
+    let (start, end) = match error.location {
+        InputLocation::Pos(position) => (position, position),
+        InputLocation::Span(span) => span,
+    };
+    let message = error.variant.message().to_string();
+    let variant = ErrorVariant::CustomError { message };
+    match Span::new(source, offset + start, offset + end) {
+        Some(span) if start < end => Error::new_from_span(variant, span),
+        _ => Error::new_from_pos(variant, Position::new(source, offset + start).unwrap()),
+    }
+}
+
+/// How each statement starts, an example of it, and the keyword phrases that
+/// come after its first word
+type Phrasing = (
+    &'static [&'static str],
+    &'static str,
+    &'static [&'static str],
+);
+
+const MIDDLE_ENGLISH: &[Phrasing] = &[
+    (
+        &["Maistow", "Canstow"],
+        "Maistow create oo qubit q with value 0",
+        &["create oo", "with value"],
+    ),
+    (
+        &["Thy"],
+        "Thy h shalt target q",
+        &["shalt target", "and", "and control on", "with"],
+    ),
+    (&["Rede"], "Rede q and quyken c", &["and quyken"]),
+    (&["Return"], "Return 100", &[]),
+    (&["Fecche"], "Fecche \"std/bell.ql\"", &[]),
+    (&["Devyse"], "Devyse pair on a, b", &["on"]),
+    (&["Ende"], "Ende", &[]),
+    (
+        &["Certes"],
+        "Certes quarter with value PI[1, 2]",
+        &["with value"],
+    ),
+];
+
+const MODERN: &[Phrasing] = &[
+    (&["let", "please"], "let qubit q = 0", &["let"]),
+    (
+        &["apply"],
+        "apply h to q",
+        &["to", "and", "controlled by", "with"],
+    ),
+    (&["measure"], "measure q into c", &["into"]),
+    (&["return"], "return 100", &[]),
+    (&["import"], "import \"std/bell.ql\"", &[]),
+    (&["gate"], "gate pair on a, b", &["on"]),
+    (&["end"], "end", &[]),
+    (&["const"], "const quarter = PI[1, 2]", &[]),
+];
+
+const TYPES: &[&str] = &["qubit", "qreg", "cbit", "creg"];
+
+/// Says what's wrong with a line that doesn't parse, given the rules the
+/// grammar tried (and those it tried to rule out) at `position` (a byte offset
+/// into the line). Misspelt or missing keywords get the phrase that was
+/// probably meant.
+fn explain(
+    line: &str,
+    position: usize,
+    positives: &[Rule],
+    negatives: &[Rule],
+    dialect: Dialect,
+) -> String {
+    let (phrasings, other) = match dialect {
+        Dialect::MiddleEnglish => (MIDDLE_ENGLISH, (MODERN, Dialect::Modern)),
+        Dialect::Modern => (MODERN, (MIDDLE_ENGLISH, Dialect::MiddleEnglish)),
+    };
+    let words = words(line);
+    // The words from the one the error is in onwards, and the ones before it
+    let after: Vec<String> = words
+        .iter()
+        .filter(|(start, word)| start + word.len() > position)
+        .map(|(_, word)| word.to_lowercase())
+        .collect();
+    let before: Vec<String> = words[..words.len() - after.len()]
+        .iter()
+        .map(|(_, word)| word.to_lowercase())
+        .collect();
+    let found = match line[position..].split_whitespace().next() {
+        Some(token) => format!("found `{}`", token),
+        None => "the line ends there".to_string(),
+    };
+
+    let first = before.first().or(after.first());
+    let phrasing = |phrasings: &'static [Phrasing]| {
+        first.and_then(|first| {
+            phrasings
+                .iter()
+                .find(|(starts, ..)| starts.iter().any(|start| start.eq_ignore_ascii_case(first)))
+        })
+    };
+    let (example, phrases) = match phrasing(phrasings) {
+        Some((_, example, phrases)) => (*example, *phrases),
+        // A statement from the other dialect
+        None if position == 0 && phrasing(other.0).is_some() => {
+            return match other.1 {
+                Dialect::Modern => format!(
+                    "`{}` is modern English, which needs `{}` as the first line",
+                    words[0].1,
+                    Dialect::Modern.header()
+                ),
+                Dialect::MiddleEnglish => format!(
+                    "`{}` is Middle English, but this file is in modern English",
+                    words[0].1
+                ),
+            };
+        }
+        None => {
+            let starts: Vec<&str> = phrasings
+                .iter()
+                .flat_map(|(starts, ..)| starts.iter().copied())
                 .collect();
-            Some(ASTNode {
-                children: Some(cregs),
-                node_kind: NodeKind::CReg,
-            })
+            return match after
+                .first()
+                .and_then(|word| closest(word, starts.iter().copied()))
+            {
+                Some(start) => format!("did you mean `{}`?", start),
+                None => format!(
+                    "statements start with {}, or {}, but {}",
+                    starts[..starts.len() - 1].join(", "),
+                    starts[starts.len() - 1],
+                    found
+                ),
+            };
         }
-        Rule::CRegSlice => {
-            let mut pair = pair.into_inner();
-            let name = build_node(pair.next()?)?;
-            let mut indices = vec![];
-            let ind1 = build_node(pair.next()?)?;
-            indices.push(ind1);
-            if let Some(next_rule) = pair.next() {
-                indices.push(build_node(next_rule)?);
+    };
+
+    // Modern names rule out modern keywords before trying to match a Name
+    if positives.contains(&Rule::Name) || negatives.contains(&Rule::ModernKeyword) {
+        if let Some(word) = after.first() {
+            if QuillParser::parse(Rule::Keyword, word).is_ok()
+                || (dialect == Dialect::Modern && is_modern_keyword(word))
+            {
+                return format!("`{}` is a keyword, so it can't be a variable's name", word);
             }
-            let indices_node = ASTNode {
-                children: Some(indices),
-                node_kind: NodeKind::Indices,
-            };
-            Some(ASTNode {
-                children: Some(vec![name, indices_node]),
-                node_kind: NodeKind::CRegSlice,
-            })
         }
-        Rule::CBit => {
-            let cbit = (pair.as_str().as_bytes()[1] as char).to_digit(10).unwrap();
-            Some(ASTNode {
-                children: None,
-                node_kind: NodeKind::CBit(cbit.try_into().unwrap()),
-            })
+    }
+    // A keyword phrase that's misspelt, or missing some of its words
+    let written = |count: usize| after[..count.min(after.len())].join(" ");
+    let misspelt = phrases.iter().copied().find_map(|phrase| {
+        let count = phrase.split(' ').count();
+        let distance = (1..=count + 1)
+            .map(|count| distance(&written(count), phrase))
+            .min()?;
+        (distance > 0 && distance <= (phrase.len() / 4).max(1)).then_some(phrase)
+    });
+    let partial = || {
+        phrases.iter().copied().find(|phrase| {
+            let count = phrase.split(' ').count();
+            count > 1
+                && phrase
+                    .split(' ')
+                    .any(|word| after.first() == Some(&word.to_string()))
+                && written(count) != *phrase
+        })
+    };
+    if let Some(phrase) = misspelt.or_else(partial) {
+        return format!("did you mean `{}`?", phrase);
+    }
+    // The first words of a phrase, without the rest of it
+    for phrase in phrases {
+        let phrase: Vec<&str> = phrase.split(' ').collect();
+        for count in (1..phrase.len()).rev() {
+            if before.len() >= count && before[before.len() - count..] == phrase[..count] {
+                return format!(
+                    "expected `{}` after `{}`, but {}",
+                    phrase[count..].join(" "),
+                    phrase[..count].join(" "),
+                    found
+                );
+            }
         }
-        Rule::Index => {
-            let index = pair.as_str().parse::<i32>().ok()?;
-            Some(ASTNode {
-                children: None,
-                node_kind: NodeKind::Index(index),
-            })
+    }
+
+    if positives.contains(&Rule::Type) {
+        if let Some(ty) = after
+            .first()
+            .and_then(|word| closest(word, TYPES.iter().copied()))
+        {
+            return format!("did you mean `{}`?", ty);
         }
-        Rule::Int => {
-            let int_str = pair.as_str();
-            let (sign, val) = match &int_str[..1] {
-                "-" => (-1, &int_str[1..]),
-                _ => (1, int_str),
+    }
+    let mut expected: Vec<String> = vec![];
+    for rule in positives {
+        let quoted;
+        let description = match rule {
+            Rule::Name
+            | Rule::QRegSlice
+            | Rule::CRegSlice
+            | Rule::ControlList
+            | Rule::ModernControlList => "a variable, like q or q[0]",
+            Rule::Gate => "a gate, like h or cnot",
+            Rule::Type => "a type (qubit, qreg, cbit, or creg)",
+            Rule::Qubit
+            | Rule::QReg
+            | Rule::QRegTensor
+            | Rule::CBit
+            | Rule::CReg
+            | Rule::CRegTensor => "a value, like 0, +, 0[3], b0, or b1[2]",
+            Rule::Int => "a whole number",
+            Rule::Float => "a number",
+            Rule::Index => "an index, like 0 or 3",
+            Rule::PI => "pi",
+            Rule::ValList | Rule::ModernValList => "parameters in brackets, like [0.5]",
+            Rule::Comment | Rule::EOI => "the end of the statement",
+            Rule::Equals => "`=`",
+            Rule::Path => "a file in quotes, like \"std/bell.ql\"",
+            _ => match keyword(*rule) {
+                Some(word) => {
+                    quoted = format!("`{}`", word);
+                    &quoted
+                }
+                None => continue,
+            },
+        };
+        if !expected.iter().any(|other| other == description) {
+            expected.push(description.to_string());
+        }
+    }
+    // The end of the statement reads best last
+    expected.sort_by_key(|description| *description == "the end of the statement");
+    match expected.len() {
+        0 => format!("{}, but it should be written like `{}`", found, example),
+        _ => format!("expected {}, but {}", expected.join(" or "), found),
+    }
+}
+
+/// The words of a line (runs of letters and digits), with where they start
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (i, c) in line
+        .char_indices()
+        .chain(std::iter::once((line.len(), ' ')))
+    {
+        match (start, c.is_ascii_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(first), false) => {
+                words.push((first, &line[first..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// The option closest to a misspelt word, if any is close enough to have been
+/// what was meant
+fn closest<'a>(word: &str, options: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    options
+        .map(|option| (distance(word, option), option))
+        .filter(|(distance, option)| *distance > 0 && *distance <= (option.len() / 4).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, option)| option)
+}
+
+/// How many letters have to be added, removed, changed, or swapped with the
+/// next one to turn one word into the other, ignoring case
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    // rows[i][j] is the distance between the first i letters of a and the
+    // first j of b
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let changed = rows[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            rows[i][j] = changed.min(rows[i - 1][j] + 1).min(rows[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Builds an error pointing at the given pair, for problems the grammar itself can't catch
+fn custom_error(pair: &Pair<Rule>, message: String) -> Box<Error<Rule>> {
+    Box::new(Error::new_from_span(
+        ErrorVariant::CustomError { message },
+        pair.as_span(),
+    ))
+}
+
+/// The word a keyword's rule matches, or None if the rule isn't a keyword
+fn keyword(rule: Rule) -> Option<&'static str> {
+    Some(match rule {
+        Rule::KwCreate => "create",
+        Rule::KwOo => "oo",
+        Rule::KwWith => "with",
+        Rule::KwValue => "value",
+        Rule::KwThy => "thy",
+        Rule::KwShalt => "shalt",
+        Rule::KwTarget => "target",
+        Rule::KwAnd => "and",
+        Rule::KwControl => "control",
+        Rule::KwOn => "on",
+        Rule::KwRede => "rede",
+        Rule::KwQuyken => "quyken",
+        Rule::KwReturn => "return",
+        Rule::KwPi => "pi",
+        Rule::KwPlease => "please",
+        Rule::KwLet => "let",
+        Rule::KwApply => "apply",
+        Rule::KwTo => "to",
+        Rule::KwControlled => "controlled",
+        Rule::KwBy => "by",
+        Rule::KwMeasure => "measure",
+        Rule::KwInto => "into",
+        Rule::KwFecche => "fecche",
+        Rule::KwDevyse => "devyse",
+        Rule::KwEnde => "ende",
+        Rule::KwCertes => "certes",
+        Rule::KwImport => "import",
+        Rule::KwGate => "gate",
+        Rule::KwEnd => "end",
+        Rule::KwConst => "const",
+        _ => return None,
+    })
+}
+
+/// The pieces of a statement (or of PI) that say something, leaving out its
+/// keywords
+fn pieces(pair: Pair<Rule>) -> impl Iterator<Item = Pair<Rule>> {
+    pair.into_inner()
+        .filter(|piece| keyword(piece.as_rule()).is_none())
+}
+
+/// Builds a statement, where `defined` holds the names of the gates defined
+/// before it in the same file
+fn build_stmt(pair: Pair<Rule>, defined: &[String]) -> ParseResult<Stmt> {
+    match pair.as_rule() {
+        Rule::AssignStmt => {
+            let mut pair = pieces(pair);
+            // Keywords are case insensitive
+            let respect = match pair.next().unwrap().as_str().to_lowercase().as_str() {
+                "maistow" => RespectExpr::Maistow,
+                "canstow" => RespectExpr::Canstow,
+                _ => unreachable!(),
             };
-            let final_val: i32 = val.parse().unwrap();
-            Some(ASTNode {
-                children: None,
-                node_kind: NodeKind::Int(sign * final_val),
+            let ty = build_type(&pair.next().unwrap());
+            let name = pair.next().unwrap().as_str().to_string();
+            let value = build_value(pair.next().unwrap())?;
+            Ok(Stmt::Assign {
+                respect,
+                ty,
+                name,
+                value,
             })
         }
-        Rule::Float => {
-            let float_str = pair.as_str();
-            let (sign, val) = match &float_str[..1] {
-                "-" => (-1, &float_str[1..]),
-                _ => (1, float_str),
+        Rule::ModernAssignStmt => {
+            let mut pair = pieces(pair);
+            // "please let" is as respectful as Maistow
+            let respect = match pair.next().unwrap().as_str() {
+                "" => RespectExpr::Canstow,
+                _ => RespectExpr::Maistow,
             };
-            let final_val: f64 = val.parse().unwrap();
-            Some(ASTNode {
-                children: None,
-                node_kind: NodeKind::Float((sign as f64) * final_val),
+            let ty = build_type(&pair.next().unwrap());
+            let name = pair.next().unwrap().as_str().to_string();
+            let value = build_value(pair.next().unwrap())?;
+            Ok(Stmt::Assign {
+                respect,
+                ty,
+                name,
+                value,
             })
         }
-        Rule::PI => {
-            let mut pair = pair.into_inner();
-            let mut pi_mult = std::f64::consts::PI;
-            if let Some(next_rule) = pair.next() {
-                pi_mult *= next_rule.as_str().parse::<f64>().ok()?;
-                if let Some(second_rule) = pair.next() {
-                    pi_mult /= second_rule.as_str().parse::<f64>().ok()?;
+        Rule::GateStmt | Rule::ModernGateStmt => {
+            let mut pair = pieces(pair);
+            let gate_rule = pair.next().unwrap();
+            let mut gate = gate_rule.as_str().to_string();
+            let mut targets = vec![];
+            let mut controls = vec![];
+            let mut params = vec![];
+            for next_rule in pair {
+                match next_rule.as_rule() {
+                    // Gates acting on several qubits like swap and rxx name the rest right
+                    // after the first
+                    Rule::Name | Rule::QRegSlice => targets.push(build_target(next_rule)?),
+                    Rule::ControlList | Rule::ModernControlList => {
+                        controls = next_rule
+                            .into_inner()
+                            .map(build_target)
+                            .collect::<ParseResult<_>>()?;
+                    }
+                    Rule::ValList | Rule::ModernValList => {
+                        params = next_rule
+                            .into_inner()
+                            .map(build_param)
+                            .collect::<ParseResult<_>>()?;
+                    }
+                    _ => unreachable!(),
                 }
             }
-            Some(ASTNode {
-                children: None,
-                node_kind: NodeKind::PI(pi_mult),
+            // The grammar accepts any gate name, so names missing from the gate registry are
+            // caught here, unless they're composites, were defined in this file, or come from
+            // another one
+            // Built-in gate names are case insensitive like keywords, and are kept in lower
+            // case so the rest of Quill can match on them
+            if gates::lookup(&gate).is_some() || composites::lookup(&gate).is_some() {
+                gate = gate.to_lowercase();
+            }
+            if let Some(signature) = gates::lookup(&gate) {
+                return Ok(Stmt::Gate {
+                    gate,
+                    kind: signature.kind,
+                    targets,
+                    controls,
+                    params,
+                });
+            }
+            if composites::lookup(&gate).is_some() {
+                if !controls.is_empty() {
+                    return Err(custom_error(
+                        &gate_rule,
+                        format!("{} can't be controlled", gate),
+                    ));
+                }
+                return Ok(Stmt::Call {
+                    gate,
+                    targets,
+                    params,
+                });
+            }
+            if gate.contains('.') || defined.contains(&gate) {
+                if !controls.is_empty() || !params.is_empty() {
+                    return Err(custom_error(
+                        &gate_rule,
+                        format!("{} is defined in Quill, so it only takes targets", gate),
+                    ));
+                }
+                return Ok(Stmt::Call {
+                    gate,
+                    targets,
+                    params,
+                });
+            }
+            let names = gates::GATES
+                .iter()
+                .flat_map(|gate| std::iter::once(gate.name).chain(gate.aliases.iter().copied()))
+                .chain(
+                    composites::COMPOSITES
+                        .iter()
+                        .map(|composite| composite.name),
+                )
+                .chain(defined.iter().map(String::as_str));
+            let message = match closest(&gate, names) {
+                Some(name) => format!("{:?} is not a known gate, did you mean `{}`?", gate, name),
+                None => format!("{:?} is not a known gate", gate),
+            };
+            Err(custom_error(&gate_rule, message))
+        }
+        Rule::MeasureStmt | Rule::ModernMeasureStmt => {
+            let mut pair = pieces(pair);
+            let measured = build_target(pair.next().unwrap())?; // Either Name or QRegSlice
+            let recipient = build_target(pair.next().unwrap())?; // Either Name or CRegSlice
+            Ok(Stmt::Measure {
+                measured,
+                recipient,
             })
         }
-        Rule::Name => Some(ASTNode {
-            children: None,
-            node_kind: NodeKind::Name(pair.as_str().to_string()),
-        }),
-        Rule::ValList => {
-            let val_list = pair
-                .into_inner()
-                .map(|pair| build_node(pair).unwrap())
-                .collect();
-            Some(ASTNode {
-                children: Some(val_list),
-                node_kind: NodeKind::ValList,
+        Rule::ReturnStmt => {
+            let shots = build_int(pieces(pair).next().unwrap())?;
+            Ok(Stmt::Return { shots })
+        }
+        Rule::Comment => {
+            let text = pair.as_str().strip_prefix("//").unwrap();
+            Ok(Stmt::Comment(text.to_string()))
+        }
+        Rule::ImportStmt | Rule::ModernImportStmt => {
+            let path = pieces(pair).next().unwrap().as_str();
+            Ok(Stmt::Import {
+                path: path[1..path.len() - 1].to_string(),
             })
         }
-        Rule::ControlList => {
-            let control_list = pair
-                .into_inner()
-                .map(|pair| build_node(pair).unwrap())
-                .collect();
-            Some(ASTNode {
-                children: Some(control_list),
-                node_kind: NodeKind::ControlList,
+        Rule::GateDefStmt | Rule::ModernGateDefStmt => {
+            let mut names = pieces(pair).map(|name| name.as_str().to_string());
+            // The body is filled in by `parse` from the lines that follow
+            Ok(Stmt::GateDef {
+                name: names.next().unwrap(),
+                qubits: names.collect(),
+                body: vec![],
             })
         }
-        Rule::EOI => Some(ASTNode {
-            children: None,
-            node_kind: NodeKind::EOI,
-        }),
-        Rule::COMMENT => Some(ASTNode {
-            children: None,
-            node_kind: NodeKind::COMMENT,
-        }),
-        _ => unimplemented!(),
+        Rule::EndStmt | Rule::ModernEndStmt => Err(custom_error(
+            &pair,
+            "there's no gate definition here to end".to_string(),
+        )),
+        Rule::ConstantStmt | Rule::ModernConstantStmt => {
+            let mut pair = pieces(pair);
+            let name = pair.next().unwrap().as_str().to_string();
+            let value = build_param(pair.next().unwrap())?;
+            Ok(Stmt::Constant { name, value })
+        }
+        _ => unreachable!(),
     }
 }
 
-// Helper functions for creating all of the different ASTNodes
-fn build_gate_app(
-    gate_rule: pest::iterators::Pair<Rule>,
-    target: ASTNode,
-    controls: Option<ASTNode>,
-    params: Option<ASTNode>,
-) -> Option<ASTNode> {
-    let gate_type = match gate_rule.clone().as_rule() {
-        Rule::Q1Gate => GateExpr::Q1Gate,
-        Rule::Q1ParamGate => GateExpr::Q1ParamGate,
-        Rule::Q2Gate => GateExpr::Q2Gate,
-        Rule::Q2ParamGate => GateExpr::Q2ParamGate,
-        Rule::QMultiGate => GateExpr::QMultiGate,
-        unknown => panic!("Unknown gate type given: {:?}", unknown),
-    };
+// Helper functions for building the pieces of statements
 
-    let gate_type_node = ASTNode {
-        children: None,
-        node_kind: NodeKind::GateType(gate_type),
-    };
+fn build_value(pair: Pair<Rule>) -> ParseResult<Value> {
+    match pair.as_rule() {
+        Rule::Qubit => Ok(Value::Qubit(pair.as_str().to_string())),
+        Rule::CBit => Ok(Value::CBit(build_cbit(&pair))),
+        Rule::QReg => Ok(Value::QReg(vec![build_qreg_segment(pair)?])),
+        Rule::QRegTensor => Ok(Value::QReg(
+            pair.into_inner()
+                .map(build_qreg_segment)
+                .collect::<ParseResult<_>>()?,
+        )),
+        Rule::CReg => Ok(Value::CReg(vec![build_creg_segment(pair)?])),
+        Rule::CRegTensor => Ok(Value::CReg(
+            pair.into_inner()
+                .map(build_creg_segment)
+                .collect::<ParseResult<_>>()?,
+        )),
+        Rule::PI => Err(custom_error(
+            &pair,
+            "PI can only be used as a gate parameter, not as a variable's value".to_string(),
+        )),
+        _ => unreachable!(),
+    }
+}
 
-    let gate = ASTNode {
-        children: None,
-        node_kind: NodeKind::Name(gate_rule.as_str().to_string()),
-    };
+fn build_type(pair: &Pair<Rule>) -> ValueExpr {
+    // Types are case insensitive
+    match pair.as_str().to_lowercase().as_str() {
+        "qreg" => ValueExpr::QReg,
+        "qubit" => ValueExpr::Qubit,
+        "creg" => ValueExpr::CReg,
+        "cbit" => ValueExpr::CBit,
+        _ => unreachable!(),
+    }
+}
+
+fn build_qreg_segment(pair: Pair<Rule>) -> ParseResult<(String, usize)> {
+    let mut pair = pair.into_inner();
+    let qubit = pair.next().unwrap().as_str().to_string();
+    let length = build_index(pair.next().unwrap())?;
+    Ok((qubit, length))
+}
 
-    let mut children = vec![gate, gate_type_node, target];
-    if let Some(cont) = controls {
-        children.push(cont);
+fn build_creg_segment(pair: Pair<Rule>) -> ParseResult<(i32, usize)> {
+    let mut pair = pair.into_inner();
+    let cbit = build_cbit(&pair.next().unwrap());
+    let length = build_index(pair.next().unwrap())?;
+    Ok((cbit, length))
+}
+
+fn build_cbit(pair: &Pair<Rule>) -> i32 {
+    // CBits are written as "b0" or "b1"
+    (pair.as_str().as_bytes()[1] - b'0') as i32
+}
+
+fn build_target(pair: Pair<Rule>) -> ParseResult<Target> {
+    match pair.as_rule() {
+        Rule::Name => Ok(Target::Name(pair.as_str().to_string())),
+        Rule::QRegSlice | Rule::CRegSlice => {
+            let mut pair = pair.into_inner();
+            let name = pair.next().unwrap().as_str().to_string();
+            let start = build_index(pair.next().unwrap())?;
+            let end = match pair.next() {
+                Some(end) => build_index(end)?,
+                None => start,
+            };
+            Ok(Target::Slice { name, start, end })
+        }
+        _ => unreachable!(),
     }
-    if let Some(pars) = params {
-        children.push(pars);
+}
+
+fn build_param(pair: Pair<Rule>) -> ParseResult<Param> {
+    match pair.as_rule() {
+        Rule::Int => Ok(Param::Int(build_int(pair)?)),
+        Rule::Float => match pair.as_str().parse::<f64>() {
+            Ok(val) => Ok(Param::Float(val)),
+            Err(_) => Err(custom_error(&pair, "Invalid float".to_string())),
+        },
+        Rule::PI => {
+            // We support PI, PI[i], and PI[i, j], which mean PI * i and PI * (i / j)
+            let mut fraction = [1, 1];
+            for (i, index) in pieces(pair.clone()).enumerate() {
+                fraction[i] = build_index(index)? as i32;
+            }
+            if fraction[1] == 0 {
+                return Err(custom_error(&pair, "PI cannot be divided by 0".to_string()));
+            }
+            Ok(Param::PI(fraction[0], fraction[1]))
+        }
+        Rule::ConstantName => Ok(Param::Constant(pair.as_str().to_string())),
+        _ => unreachable!(),
     }
+}
 
-    Some(ASTNode {
-        children: Some(children),
-        node_kind: NodeKind::GateApplication,
-    })
+fn build_index(pair: Pair<Rule>) -> ParseResult<usize> {
+    pair.as_str()
+        .parse::<i32>()
+        .map(|index| index as usize)
+        .map_err(|_| custom_error(&pair, format!("{} is too large", pair.as_str())))
+}
+
+fn build_int(pair: Pair<Rule>) -> ParseResult<i32> {
+    pair.as_str()
+        .parse::<i32>()
+        .map_err(|_| custom_error(&pair, format!("{} is too large", pair.as_str())))
 }
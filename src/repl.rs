@@ -0,0 +1,231 @@
+//! An interactive session, which takes a program one statement at a time and
+//! runs each as it comes, keeping the variables and the simulator state around
+//! between them
+use crate::ast::{Program, Statement, Stmt, Value, ValueExpr};
+use crate::circuit::Circuit;
+use crate::modules::{expand_builtin, link};
+use crate::parser::{dialect_header, parse, parse_statement, Dialect};
+use crate::simulator::{self, bits, Rng, StateVector, MAX_QUBITS};
+use crate::type_checker::{check_statement, SymbolTable};
+use std::collections::HashMap;
+
+pub const HELP: &str = "Type a statement to run it, or one of these commands:
+  :state         Print the amplitude of every basis state with any
+  :circuit       Draw the circuit so far
+  :reset         Forget every variable and start over
+  :load <file>   Run every statement of a file
+  :help          Print this message
+  :quit          Leave
+A Return runs the whole session so far that many times and counts the outcomes.
+Typing `// dialect: modern` switches to modern English keywords (and
+`// dialect: middle english` switches back).";
+
+pub struct Session {
+    /// Every statement run so far, numbered in the order they were run
+    program: Program,
+    symbols: SymbolTable,
+    state: StateVector,
+    /// Stores entries of <CBit Name, Its value>
+    cbits: HashMap<String, Vec<bool>>,
+    rng: Rng,
+    /// The keywords statements are typed with
+    dialect: Dialect,
+}
+
+impl Session {
+    pub fn new(seed: u64) -> Session {
+        let program = Program { stmts: vec![] };
+        Session {
+            state: StateVector::new(&program),
+            program,
+            symbols: SymbolTable::default(),
+            cbits: HashMap::new(),
+            rng: Rng::new(seed),
+            dialect: Dialect::default(),
+        }
+    }
+
+    /// Runs a line typed into the session, which is either a statement or a
+    /// command, and returns what to print
+    pub fn eval(&mut self, line: &str) -> Result<String, String> {
+        let line = line.trim();
+        if let Some(command) = line.strip_prefix(':') {
+            return self.command(command);
+        }
+        // A line of just a comment has no statement in it, though it may
+        // switch dialects
+        if let Some(comment) = line.strip_prefix("//") {
+            return match dialect_header(comment) {
+                Some(dialect) => {
+                    self.dialect = dialect?;
+                    Ok(String::new())
+                }
+                None => Ok(String::new()),
+            };
+        }
+        if line.is_empty() {
+            return Ok(String::new());
+        }
+        let line_no = self.program.stmts.len() as i32 + 1;
+        let stmt = parse_statement(line, line_no, self.dialect)
+            .map_err(|error| format!("Can't parse the statement\n{}", error))?;
+        // A composite like qft runs as the gates it expands into, which are all
+        // checked before any of them runs
+        let stmts = expand_builtin(stmt, &self.symbols)?;
+        let mut symbols = self.symbols.clone();
+        for stmt in &stmts {
+            check_statement(stmt, &mut symbols)?;
+        }
+        let mut reply = String::new();
+        for stmt in stmts {
+            reply = self.execute(stmt)?;
+        }
+        Ok(reply)
+    }
+
+    fn command(&mut self, command: &str) -> Result<String, String> {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        match (name, argument) {
+            ("state", "") => Ok(self.draw_state()),
+            ("circuit", "") if self.program.stmts.is_empty() => Ok("(empty circuit)".to_string()),
+            ("circuit", "") => Ok(Circuit::from_program(&self.program)
+                .draw()
+                .trim_end()
+                .to_string()),
+            ("reset", "") => {
+                *self = Session {
+                    rng: self.rng.clone(),
+                    dialect: self.dialect,
+                    ..Session::new(0)
+                };
+                Ok("Reset".to_string())
+            }
+            ("load", "") => Err(":load needs a file!".to_string()),
+            ("load", path) => self.load(path),
+            ("help", "") => Ok(HELP.to_string()),
+            ("state" | "circuit" | "reset" | "help", _) => {
+                Err(format!(":{} doesn't take anything after it!", name))
+            }
+            _ => Err(format!(":{} isn't a command, try :help!", name)),
+        }
+    }
+
+    /// Runs every statement of a file in turn, stopping at the first one that
+    /// fails
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|error| format!("Can't read {}: {}", path, error))?;
+        let program = parse(&source)
+            .map_err(|error| format!("Can't parse the program\n{}", error.with_path(path)))?;
+        let program = link(&program, path)?;
+        let mut output = vec![];
+        for stmt in program.stmts {
+            match self.execute(stmt) {
+                Ok(reply) if reply.is_empty() => {}
+                Ok(reply) => output.push(reply),
+                Err(error) => {
+                    output.push(format!("{}:{}", path, error));
+                    return Err(output.join("\n"));
+                }
+            }
+        }
+        Ok(output.join("\n"))
+    }
+
+    /// Checks and runs a statement, then adds it to the session. Errors are
+    /// reported at the statement's own line number.
+    fn execute(&mut self, mut stmt: Statement) -> Result<String, String> {
+        match &stmt.kind {
+            Stmt::Comment(_) => return Ok(String::new()),
+            Stmt::Return { shots } => return self.sample(*shots),
+            _ => {}
+        }
+        let mut symbols = self.symbols.clone();
+        check_statement(&stmt, &mut symbols)?;
+        if let Stmt::Assign { name, value, .. } = &stmt.kind {
+            let width = match value {
+                Value::Qubit(_) => 1,
+                Value::QReg(_) => symbols.widths[name],
+                Value::CBit(_) | Value::CReg(_) => 0,
+            };
+            let known = self
+                .state
+                .qubits()
+                .iter()
+                .filter(|(n, _)| n == name)
+                .count();
+            if self.state.width() + width.saturating_sub(known) > MAX_QUBITS {
+                return Err(format!(
+                    "{}: only up to {} qubits can be simulated!",
+                    stmt.line_no, MAX_QUBITS
+                ));
+            }
+        }
+        self.symbols = symbols;
+        stmt.line_no = self.program.stmts.len() as i32 + 1;
+        self.state.execute(&stmt, &mut self.cbits, &mut self.rng)?;
+
+        let reply = match &stmt.kind {
+            Stmt::Measure { recipient, .. } => {
+                let name = recipient.name();
+                format!("{}={}", name, bits(&self.cbits[name]))
+            }
+            _ => String::new(),
+        };
+        self.program.stmts.push(stmt);
+        Ok(reply)
+    }
+
+    /// Runs the session so far from the start `shots` times, leaving its
+    /// state alone
+    fn sample(&mut self, shots: i32) -> Result<String, String> {
+        if shots < 1 {
+            return Err("Return needs at least 1 shot!".to_string());
+        }
+        let counts = simulator::run(&self.program, &self.symbols, shots as usize, &mut self.rng)?;
+        match counts.keys().next() {
+            Some(outcome) if outcome.is_empty() => Ok("(no cbits to count)".to_string()),
+            _ => Ok(simulator::histogram(&counts).trim_end().to_string()),
+        }
+    }
+
+    /// Every basis state with an amplitude, along with its probability
+    fn draw_state(&self) -> String {
+        let qubits: Vec<String> = self
+            .state
+            .qubits()
+            .iter()
+            .map(|(name, index)| match self.symbols.types[name] {
+                ValueExpr::QReg => format!("{}[{}]", name, index),
+                _ => name.clone(),
+            })
+            .collect();
+        if qubits.is_empty() {
+            return "(no qubits)".to_string();
+        }
+        let mut lines = vec![format!("|{}>", qubits.join(" "))];
+        let width = qubits.len();
+        for (index, amplitude) in self.state.amplitudes().iter().enumerate() {
+            if amplitude.norm_sqr() < 1e-12 {
+                continue;
+            }
+            let basis: String = (0..width)
+                .map(|bit| match index & (1 << (width - 1 - bit)) {
+                    0 => '0',
+                    _ => '1',
+                })
+                .collect();
+            lines.push(format!(
+                "|{}>  {:+.4}{:+.4}i  (p={:.4})",
+                basis,
+                amplitude.re,
+                amplitude.im,
+                amplitude.norm_sqr()
+            ));
+        }
+        lines.join("\n")
+    }
+}
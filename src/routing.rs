@@ -0,0 +1,661 @@
+//! Routing programs onto devices whose qubits can't all interact with each
+//! other, using the SABRE heuristic (Li, Ding, and Xie, 2019)
+use crate::ast::{Program, RespectExpr, Statement, Stmt, Target, Value, ValueExpr};
+use crate::backends::{elements, initial_values};
+use crate::circuit::{Circuit, Wire};
+use crate::gates;
+use crate::json::Json;
+use crate::optimizations::qubits_of;
+use crate::type_checker::SymbolTable;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// How many two qubit gates past the front layer the heuristic looks ahead at
+const EXTENDED_SIZE: usize = 20;
+
+/// How much the gates it looks ahead at count for, next to the front layer
+const EXTENDED_WEIGHT: f64 = 0.5;
+
+/// How much less attractive a qubit gets each time it's swapped, which keeps
+/// the heuristic from swapping the same pair back and forth
+const DECAY: f64 = 0.001;
+
+/// The connectivity of a device, which is which pairs of physical qubits a two
+/// qubit gate can act on. Edges go both ways, since the direction of a cnot can
+/// always be turned around with single qubit gates.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CouplingMap {
+    neighbours: Vec<Vec<usize>>,
+    /// The length of the shortest path between every two qubits, or
+    /// usize::MAX when there is none
+    distances: Vec<Vec<usize>>,
+}
+
+impl CouplingMap {
+    /// The map of a device with `num_qubits` qubits and the given edges
+    pub fn new(num_qubits: usize, edges: &[(usize, usize)]) -> Result<CouplingMap, String> {
+        let mut neighbours = vec![vec![]; num_qubits];
+        for &(a, b) in edges {
+            if a >= num_qubits || b >= num_qubits {
+                return Err(format!(
+                    "The edge [{}, {}] goes past the {} qubits of the device!",
+                    a, b, num_qubits
+                ));
+            }
+            if a == b {
+                return Err(format!(
+                    "The edge [{}, {}] connects a qubit to itself!",
+                    a, b
+                ));
+            }
+            if !neighbours[a].contains(&b) {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+        for list in &mut neighbours {
+            list.sort();
+        }
+
+        // Breadth first search out of every qubit
+        let distances = (0..num_qubits)
+            .map(|start| {
+                let mut distance = vec![usize::MAX; num_qubits];
+                distance[start] = 0;
+                let mut queue = VecDeque::from([start]);
+                while let Some(qubit) = queue.pop_front() {
+                    for &next in &neighbours[qubit] {
+                        if distance[next] == usize::MAX {
+                            distance[next] = distance[qubit] + 1;
+                            queue.push_back(next);
+                        }
+                    }
+                }
+                distance
+            })
+            .collect();
+        Ok(CouplingMap {
+            neighbours,
+            distances,
+        })
+    }
+
+    /// Reads a coupling map from JSON, which is either a list of edges like
+    /// `[[0, 1], [1, 2]]` or an object like `{"num_qubits": 4, "edges": [[0, 1]]}`.
+    /// Without a number of qubits, the device has just as many as its edges use.
+    pub fn from_json(source: &str) -> Result<CouplingMap, String> {
+        let json =
+            Json::parse(source).map_err(|error| format!("Invalid coupling map! {}", error))?;
+        let (mut num_qubits, mut edges) = (None, None);
+        match &json {
+            Json::Array(_) => edges = Some(build_edges(&json)?),
+            Json::Object(fields) => {
+                for (key, value) in fields {
+                    match key.as_str() {
+                        "num_qubits" => num_qubits = Some(number(value)?),
+                        "edges" => edges = Some(build_edges(value)?),
+                        _ => {
+                            return Err(format!("Unexpected field {:?} in the coupling map!", key))
+                        }
+                    }
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "A coupling map is a list of edges or an object, not {}!",
+                    json
+                ))
+            }
+        }
+        let edges = edges.ok_or_else(|| "The coupling map has no edges!".to_string())?;
+        let needed = edges.iter().map(|&(a, b)| a.max(b) + 1).max().unwrap_or(0);
+        CouplingMap::new(num_qubits.unwrap_or(needed), &edges)
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    pub fn neighbours(&self, qubit: usize) -> &[usize] {
+        &self.neighbours[qubit]
+    }
+
+    pub fn connected(&self, a: usize, b: usize) -> bool {
+        self.neighbours[a].contains(&b)
+    }
+
+    /// The number of edges between two qubits, if there's any path at all
+    pub fn distance(&self, a: usize, b: usize) -> Option<usize> {
+        match self.distances[a][b] {
+            usize::MAX => None,
+            distance => Some(distance),
+        }
+    }
+}
+
+/// Reads a list of edges, each of which is a pair of qubits like [0, 1]
+fn build_edges(json: &Json) -> Result<Vec<(usize, usize)>, String> {
+    let edges = json
+        .as_array()
+        .ok_or_else(|| format!("Expected a list of edges, found {}!", json))?;
+    edges
+        .iter()
+        .map(|edge| match edge.as_array() {
+            Some([a, b]) => Ok((number(a)?, number(b)?)),
+            _ => Err(format!("Expected an edge like [0, 1], found {}!", edge)),
+        })
+        .collect()
+}
+
+fn number(json: &Json) -> Result<usize, String> {
+    match json {
+        Json::Number(number) if *number > u32::MAX as f64 => {
+            Err(format!("{} is too big to be a qubit!", json))
+        }
+        _ => json
+            .as_usize()
+            .ok_or_else(|| format!("Expected a qubit, found {}!", json)),
+    }
+}
+
+/// Where the logical qubits of a routed program are on the device
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Layout {
+    /// Every logical qubit as (variable, index), in the order they were declared
+    pub logical: Vec<(String, usize)>,
+    /// The physical qubit each logical qubit starts out on
+    pub start: Vec<usize>,
+    /// The physical qubit each logical qubit ends up on after all the swaps,
+    /// which is what results read off the whole device have to be un-permuted by
+    pub end: Vec<usize>,
+    /// The number of swaps routing added
+    pub swaps: usize,
+}
+
+impl Layout {
+    /// The physical qubit the given logical qubit ends up on
+    pub fn physical(&self, name: &str, index: usize) -> Option<usize> {
+        self.logical
+            .iter()
+            .position(|(logical, i)| logical == name && *i == index)
+            .map(|id| self.end[id])
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Layout ({} swaps):", self.swaps)?;
+        for (id, (name, index)) in self.logical.iter().enumerate() {
+            writeln!(
+                f,
+                "    {}[{}]: {} -> {}",
+                name, index, self.start[id], self.end[id]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A step of a routed program: either an operation of the original, or a swap
+/// of two physical qubits (made on behalf of the given line)
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Step {
+    Op(usize),
+    Swap(usize, usize, i32),
+}
+
+/// An operation as far as routing cares, which is just the two logical qubits
+/// that have to be next to each other if it's a two qubit gate
+struct Op {
+    line_no: i32,
+    pair: Option<(usize, usize)>,
+}
+
+struct Sabre<'a> {
+    coupling: &'a CouplingMap,
+    ops: &'a [Op],
+    logical: &'a [(String, usize)],
+}
+
+impl Sabre<'_> {
+    /// Routes the operations from the given layout (the physical qubit of every
+    /// logical one), in the order `predecessors` and `successors` allow, and
+    /// returns the steps taken along with the layout at the end
+    fn run(
+        &self,
+        mut place: Vec<usize>,
+        predecessors: &[Vec<usize>],
+        successors: &[Vec<usize>],
+    ) -> Result<(Vec<Step>, Vec<usize>), String> {
+        let coupling = self.coupling;
+        let mut occupant = vec![None; coupling.num_qubits()];
+        for (logical, &physical) in place.iter().enumerate() {
+            occupant[physical] = Some(logical);
+        }
+        let mut waiting: Vec<usize> = predecessors.iter().map(Vec::len).collect();
+        let mut front: Vec<usize> = (0..self.ops.len()).filter(|&i| waiting[i] == 0).collect();
+        let mut decay = vec![1.0; coupling.num_qubits()];
+        let mut steps = vec![];
+        // The number of swaps since the last operation ran
+        let mut stuck = 0;
+
+        while !front.is_empty() {
+            let (ready, blocked): (Vec<usize>, Vec<usize>) =
+                front.iter().partition(|&&i| match self.ops[i].pair {
+                    Some((a, b)) => coupling.connected(place[a], place[b]),
+                    None => true,
+                });
+            if !ready.is_empty() {
+                front = blocked;
+                for i in ready {
+                    steps.push(Step::Op(i));
+                    for &next in &successors[i] {
+                        waiting[next] -= 1;
+                        if waiting[next] == 0 {
+                            front.push(next);
+                        }
+                    }
+                }
+                front.sort();
+                decay.fill(1.0);
+                stuck = 0;
+                continue;
+            }
+
+            for &i in &front {
+                let (a, b) = self.ops[i].pair.unwrap();
+                if coupling.distance(place[a], place[b]).is_none() {
+                    let name =
+                        |id: usize| format!("{}[{}]", self.logical[id].0, self.logical[id].1);
+                    return Err(format!(
+                        "{}: {} and {} are on parts of the device that aren't connected!",
+                        self.ops[i].line_no,
+                        name(a),
+                        name(b)
+                    ));
+                }
+            }
+            let (p, q) = if stuck > 10 * coupling.num_qubits() {
+                // The heuristic is going in circles, so walk the qubits of the
+                // first gate towards each other instead
+                let (a, b) = self.ops[front[0]].pair.unwrap();
+                let closer = coupling
+                    .neighbours(place[a])
+                    .iter()
+                    .min_by_key(|&&next| coupling.distance(next, place[b]).unwrap_or(usize::MAX));
+                (place[a], *closer.unwrap())
+            } else {
+                self.best_swap(&front, &place, &decay, successors)
+            };
+            occupant.swap(p, q);
+            for physical in [p, q] {
+                if let Some(logical) = occupant[physical] {
+                    place[logical] = physical;
+                }
+            }
+            decay[p] += DECAY;
+            decay[q] += DECAY;
+            stuck += 1;
+            steps.push(Step::Swap(p, q, self.ops[front[0]].line_no));
+        }
+        Ok((steps, place))
+    }
+
+    /// The swap, out of those touching a qubit of the front layer, that brings
+    /// the front layer (and to a lesser extent, the gates after it) closest
+    /// together
+    fn best_swap(
+        &self,
+        front: &[usize],
+        place: &[usize],
+        decay: &[f64],
+        successors: &[Vec<usize>],
+    ) -> (usize, usize) {
+        let extended = self.extended_set(front, place, successors);
+        let mut candidates = vec![];
+        for &i in front {
+            let (a, b) = self.ops[i].pair.unwrap();
+            for p in [place[a], place[b]] {
+                for &q in self.coupling.neighbours(p) {
+                    let swap = (p.min(q), p.max(q));
+                    if !candidates.contains(&swap) {
+                        candidates.push(swap);
+                    }
+                }
+            }
+        }
+        candidates.sort();
+
+        let mut best = (f64::INFINITY, candidates[0]);
+        for (p, q) in candidates {
+            let moved = |physical: usize| match physical {
+                _ if physical == p => q,
+                _ if physical == q => p,
+                _ => physical,
+            };
+            // The average distance between the qubits of each gate after the swap
+            let cost = |ops: &[usize]| -> f64 {
+                if ops.is_empty() {
+                    return 0.0;
+                }
+                let total: usize = ops
+                    .iter()
+                    .map(|&i| {
+                        let (a, b) = self.ops[i].pair.unwrap();
+                        self.coupling
+                            .distance(moved(place[a]), moved(place[b]))
+                            .unwrap()
+                    })
+                    .sum();
+                total as f64 / ops.len() as f64
+            };
+            let score = decay[p].max(decay[q]) * (cost(front) + EXTENDED_WEIGHT * cost(&extended));
+            if score < best.0 {
+                best = (score, (p, q));
+            }
+        }
+        best.1
+    }
+
+    /// The first few two qubit gates after the front layer whose qubits can be
+    /// brought together at all
+    fn extended_set(
+        &self,
+        front: &[usize],
+        place: &[usize],
+        successors: &[Vec<usize>],
+    ) -> Vec<usize> {
+        let mut extended = vec![];
+        let mut visited: HashSet<usize> = front.iter().copied().collect();
+        let mut queue: VecDeque<usize> = front.iter().copied().collect();
+        while let Some(i) = queue.pop_front() {
+            for &next in &successors[i] {
+                if extended.len() == EXTENDED_SIZE {
+                    return extended;
+                }
+                if !visited.insert(next) {
+                    continue;
+                }
+                if let Some((a, b)) = self.ops[next].pair {
+                    if self.coupling.distance(place[a], place[b]).is_some() {
+                        extended.push(next);
+                    }
+                }
+                queue.push_back(next);
+            }
+        }
+        extended
+    }
+}
+
+/// Splits single qubit gates over ranges and measurements of whole registers
+/// into one statement per qubit, since each of those qubits may end up anywhere
+fn expand(program: &Program, symbols: &SymbolTable) -> Result<Program, String> {
+    let element = |name: &str, index: usize| match symbols.types[name] {
+        ValueExpr::QReg | ValueExpr::CReg => Target::Slice {
+            name: name.to_string(),
+            start: index,
+            end: index,
+        },
+        _ => Target::Name(name.to_string()),
+    };
+    let mut stmts = vec![];
+    // Widths as of the current statement, since a variable may be redeclared
+    let mut widths: HashMap<String, usize> = HashMap::new();
+    for stmt in &program.stmts {
+        let line_no = stmt.line_no;
+        match &stmt.kind {
+            Stmt::Assign { name, value, .. } => {
+                widths.insert(name.clone(), initial_values(value).len());
+                stmts.push(stmt.clone());
+            }
+            Stmt::Gate {
+                gate,
+                kind,
+                targets,
+                controls,
+                params,
+            } if controls.is_empty() && gates::signature(gate).qubits == 1 => {
+                for (name, i) in elements(&targets[0], &widths, line_no)? {
+                    stmts.push(Statement {
+                        line_no,
+                        kind: Stmt::Gate {
+                            gate: gate.clone(),
+                            kind: *kind,
+                            targets: vec![element(name, i)],
+                            controls: vec![],
+                            params: params.clone(),
+                        },
+                    });
+                }
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let pairs = elements(measured, &widths, line_no)?
+                    .into_iter()
+                    .zip(elements(recipient, &widths, line_no)?);
+                for ((qname, qi), (cname, ci)) in pairs {
+                    stmts.push(Statement {
+                        line_no,
+                        kind: Stmt::Measure {
+                            measured: element(qname, qi),
+                            recipient: element(cname, ci),
+                        },
+                    });
+                }
+            }
+            _ => stmts.push(stmt.clone()),
+        }
+    }
+    Ok(Program { stmts })
+}
+
+/// Maps the qubits of a (type checked) program onto the physical qubits of a
+/// device, adding swaps wherever a two qubit gate acts on qubits that aren't
+/// next to each other. Every physical qubit becomes a plain qubit named after
+/// its number (like `physical3`), assigning a register assigns each of its
+/// qubits wherever they are at the time, and measurements still land in the
+/// same cbits. Multi-controlled gates have to be decomposed first.
+///
+/// The initial layout comes from routing the program forwards from the trivial
+/// layout and then backwards, so that every qubit starts out near where the
+/// start of the program needs it.
+pub fn route(
+    program: &mut Program,
+    symbols: &SymbolTable,
+    coupling: &CouplingMap,
+) -> Result<Layout, String> {
+    let circuit = Circuit::from_program(&expand(program, symbols)?);
+
+    // Number the logical qubits in the order they were declared
+    let mut logical: Vec<(String, usize)> = vec![];
+    let mut ids: HashMap<(String, usize), usize> = HashMap::new();
+    for node in circuit.nodes() {
+        for wire in &node.wires {
+            if let Wire::Qubit(name, i) = wire {
+                let id = logical.len();
+                ids.entry((name.clone(), *i)).or_insert_with(|| {
+                    logical.push((name.clone(), *i));
+                    id
+                });
+            }
+        }
+    }
+    if logical.len() > coupling.num_qubits() {
+        return Err(format!(
+            "The program needs {} qubits, but the device only has {}!",
+            logical.len(),
+            coupling.num_qubits()
+        ));
+    }
+
+    let mut ops = vec![];
+    for node in circuit.nodes() {
+        let qubits: Vec<usize> = node
+            .wires
+            .iter()
+            .filter_map(|wire| match wire {
+                Wire::Qubit(name, i) => Some(ids[&(name.clone(), *i)]),
+                Wire::CBit(..) => None,
+            })
+            .collect();
+        let mut pair = None;
+        if let Stmt::Gate { gate, .. } = &node.stmt.kind {
+            match qubits[..] {
+                [a, b] => pair = Some((a, b)),
+                [_] => {}
+                _ => {
+                    return Err(format!(
+                        "{}: {} acts on more than two qubits, so it has to be decomposed before routing!",
+                        node.stmt.line_no, gate
+                    ))
+                }
+            }
+        }
+        ops.push(Op {
+            line_no: node.stmt.line_no,
+            pair,
+        });
+    }
+    let predecessors: Vec<Vec<usize>> = (0..ops.len()).map(|i| circuit.predecessors(i)).collect();
+    let successors: Vec<Vec<usize>> = (0..ops.len()).map(|i| circuit.successors(i)).collect();
+
+    let sabre = Sabre {
+        coupling,
+        ops: &ops,
+        logical: &logical,
+    };
+    let trivial: Vec<usize> = (0..logical.len()).collect();
+    let (_, forward) = sabre.run(trivial, &predecessors, &successors)?;
+    let (_, start) = sabre.run(forward, &successors, &predecessors)?;
+    let (steps, end) = sabre.run(start.clone(), &predecessors, &successors)?;
+
+    // Name the physical qubits so they can't clash with any variable
+    let prefix = (0..)
+        .map(|i| format!("physical{}", "q".repeat(i)))
+        .find(|prefix| {
+            !symbols
+                .types
+                .keys()
+                .any(|name| name.starts_with(prefix.as_str()))
+        })
+        .unwrap();
+    let physical = |qubit: usize| format!("{}{}", prefix, qubit);
+    let declaration = |qubit: usize, respect: RespectExpr, value: String, line_no: i32| Statement {
+        line_no,
+        kind: Stmt::Assign {
+            respect,
+            ty: ValueExpr::Qubit,
+            name: physical(qubit),
+            value: Value::Qubit(value),
+        },
+    };
+
+    let mut place = start.clone();
+    let mut occupant = vec![None; coupling.num_qubits()];
+    for (id, &qubit) in place.iter().enumerate() {
+        occupant[qubit] = Some(id);
+    }
+    let mut declared = vec![false; coupling.num_qubits()];
+    let mut stmts = vec![];
+    let mut swaps = 0;
+    for step in steps {
+        let i = match step {
+            Step::Op(i) => i,
+            Step::Swap(p, q, line_no) => {
+                // Qubits nothing was assigned to yet are still 0
+                for qubit in [p, q] {
+                    if !declared[qubit] {
+                        declared[qubit] = true;
+                        stmts.push(declaration(
+                            qubit,
+                            RespectExpr::Maistow,
+                            "0".to_string(),
+                            line_no,
+                        ));
+                    }
+                }
+                stmts.push(Statement {
+                    line_no,
+                    kind: Stmt::Gate {
+                        gate: "swap".to_string(),
+                        kind: gates::signature("swap").kind,
+                        targets: vec![Target::Name(physical(p)), Target::Name(physical(q))],
+                        controls: vec![],
+                        params: vec![],
+                    },
+                });
+                occupant.swap(p, q);
+                for qubit in [p, q] {
+                    if let Some(id) = occupant[qubit] {
+                        place[id] = qubit;
+                    }
+                }
+                swaps += 1;
+                continue;
+            }
+        };
+
+        let stmt = &circuit.nodes()[i].stmt;
+        let line_no = stmt.line_no;
+        // Expanding left every operand a single qubit
+        let on_device = |target: &Target| -> Vec<Target> {
+            qubits_of(target)
+                .into_iter()
+                .map(|(name, index)| Target::Name(physical(place[ids[&(name.to_string(), index)]])))
+                .collect()
+        };
+        match &stmt.kind {
+            Stmt::Assign {
+                respect,
+                ty: ValueExpr::Qubit | ValueExpr::QReg,
+                name,
+                value,
+            } => {
+                for (index, value) in initial_values(value).into_iter().enumerate() {
+                    let qubit = place[ids[&(name.clone(), index)]];
+                    declared[qubit] = true;
+                    stmts.push(declaration(qubit, *respect, value, line_no));
+                }
+            }
+            Stmt::Gate {
+                gate,
+                kind,
+                targets,
+                controls,
+                params,
+            } => stmts.push(Statement {
+                line_no,
+                kind: Stmt::Gate {
+                    gate: gate.clone(),
+                    kind: *kind,
+                    targets: targets.iter().flat_map(on_device).collect(),
+                    controls: controls.iter().flat_map(on_device).collect(),
+                    params: params.clone(),
+                },
+            }),
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => stmts.push(Statement {
+                line_no,
+                kind: Stmt::Measure {
+                    measured: on_device(measured).remove(0),
+                    recipient: recipient.clone(),
+                },
+            }),
+            _ => stmts.push(stmt.clone()),
+        }
+    }
+    stmts.extend(circuit.ret.clone());
+    program.stmts = stmts;
+
+    Ok(Layout {
+        logical,
+        start,
+        end,
+        swaps,
+    })
+}
@@ -0,0 +1,367 @@
+//! A statevector simulator for Quill programs, built on the matrices in the
+//! gate registry
+use crate::ast::{Program, Statement, Stmt, Target, Value, ValueExpr};
+use crate::backends::{elements, initial_values};
+use crate::gates::{self, Matrix};
+use crate::type_checker::SymbolTable;
+use num_complex::Complex64;
+use std::collections::{BTreeMap, HashMap};
+
+/// The state of a set of qubits, as one amplitude per basis state. Qubits are
+/// numbered in the order they were declared in, and the first one is the most
+/// significant bit of a basis state's index (the same order the gate registry
+/// uses within a matrix).
+#[derive(PartialEq, Debug, Clone)]
+pub struct StateVector {
+    /// Stores entries of <Qubit, Its position in the state>
+    qubits: HashMap<(String, usize), usize>,
+    amplitudes: Vec<Complex64>,
+    /// Stores entries of <Variable Name, Width>, as of the last statement
+    /// executed, since a variable may be redeclared with a new width
+    widths: HashMap<String, usize>,
+}
+
+impl StateVector {
+    /// Every qubit the program declares, all starting out as 0
+    pub fn new(program: &Program) -> StateVector {
+        let mut qubits = HashMap::new();
+        for stmt in &program.stmts {
+            if let Stmt::Assign { name, value, .. } = &stmt.kind {
+                let width = match value {
+                    Value::Qubit(_) => 1,
+                    Value::QReg(segments) => segments.iter().map(|(_, length)| length).sum(),
+                    Value::CBit(_) | Value::CReg(_) => 0,
+                };
+                for i in 0..width {
+                    let position = qubits.len();
+                    qubits.entry((name.clone(), i)).or_insert(position);
+                }
+            }
+        }
+        let mut amplitudes = vec![Complex64::new(0.0, 0.0); 1 << qubits.len()];
+        amplitudes[0] = Complex64::new(1.0, 0.0);
+        StateVector {
+            qubits,
+            amplitudes,
+            widths: HashMap::new(),
+        }
+    }
+
+    /// The number of qubits the state is over
+    pub fn width(&self) -> usize {
+        self.qubits.len()
+    }
+
+    pub fn amplitudes(&self) -> &[Complex64] {
+        &self.amplitudes
+    }
+
+    /// Every qubit the state is over as (variable, index), from the most
+    /// significant bit of a basis state's index down
+    pub fn qubits(&self) -> Vec<(String, usize)> {
+        let mut qubits: Vec<(&(String, usize), &usize)> = self.qubits.iter().collect();
+        qubits.sort_by_key(|(_, position)| **position);
+        qubits.into_iter().map(|(qubit, _)| qubit.clone()).collect()
+    }
+
+    /// Adds a qubit starting out as 0, below every qubit already in the state
+    pub fn add_qubit(&mut self, name: &str, index: usize) {
+        let position = self.qubits.len();
+        self.qubits.insert((name.to_string(), index), position);
+        self.amplitudes = self
+            .amplitudes
+            .iter()
+            .flat_map(|&amplitude| [amplitude, Complex64::new(0.0, 0.0)])
+            .collect();
+    }
+
+    /// Puts the qubits into the given basis state
+    pub fn set_basis_state(&mut self, index: usize) {
+        self.amplitudes.fill(Complex64::new(0.0, 0.0));
+        self.amplitudes[index] = Complex64::new(1.0, 0.0);
+    }
+
+    /// The bit of a basis state's index that holds the given qubit
+    fn mask(&self, name: &str, index: usize) -> usize {
+        1 << (self.width() - 1 - self.qubits[&(name.to_string(), index)])
+    }
+
+    /// The bits of every qubit a target covers. Bare names in gate applications
+    /// are always single qubits.
+    fn masks(&self, target: &Target) -> Vec<usize> {
+        match target {
+            Target::Name(name) => vec![self.mask(name, 0)],
+            Target::Slice { name, start, end } => {
+                (*start..=*end).map(|i| self.mask(name, i)).collect()
+            }
+        }
+    }
+
+    /// Applies a matrix to the target qubits (the first being the most
+    /// significant bit of the matrix's index), in the basis states where every
+    /// control qubit is 1
+    pub fn apply(&mut self, matrix: &Matrix, targets: &[usize], controls: &[usize]) {
+        let controlled: usize = controls.iter().sum();
+        let touched: usize = targets.iter().sum::<usize>() | controlled;
+        let offsets: Vec<usize> = (0..matrix.len())
+            .map(|k| {
+                targets
+                    .iter()
+                    .enumerate()
+                    .filter(|(bit, _)| k & (1 << (targets.len() - 1 - bit)) != 0)
+                    .map(|(_, mask)| mask)
+                    .sum()
+            })
+            .collect();
+        for base in 0..self.amplitudes.len() {
+            // Visit each group of basis states once, through its member with every target at 0
+            if base & touched != controlled {
+                continue;
+            }
+            let old: Vec<Complex64> = offsets.iter().map(|o| self.amplitudes[base | o]).collect();
+            for (row, offset) in offsets.iter().enumerate() {
+                self.amplitudes[base | offset] =
+                    (0..old.len()).map(|col| matrix[row][col] * old[col]).sum();
+            }
+        }
+    }
+
+    /// Applies every gate of a program in order, skipping anything that isn't
+    /// a gate application
+    pub fn apply_gates(&mut self, program: &Program) {
+        for stmt in &program.stmts {
+            self.apply_gate(&stmt.kind);
+        }
+    }
+
+    fn apply_gate(&mut self, stmt: &Stmt) {
+        if let Stmt::Gate {
+            gate,
+            targets,
+            controls,
+            params,
+            ..
+        } = stmt
+        {
+            let signature = gates::signature(gate);
+            let params: Vec<f64> = params.iter().map(|param| param.value()).collect();
+            let matrix = (signature.matrix)(&params);
+            let controls: Vec<usize> = controls.iter().flat_map(|c| self.masks(c)).collect();
+            if targets.len() == 1 && signature.qubits == 1 {
+                // A single qubit gate given a range applies to every qubit in it
+                for target in self.masks(&targets[0]) {
+                    self.apply(&matrix, &[target], &controls);
+                }
+            } else {
+                let targets: Vec<usize> = targets.iter().flat_map(|t| self.masks(t)).collect();
+                self.apply(&matrix, &targets, &controls);
+            }
+        }
+    }
+
+    /// Measures a single qubit, collapsing the state onto the outcome
+    pub fn measure(&mut self, name: &str, index: usize, rng: &mut Rng) -> bool {
+        let mask = self.mask(name, index);
+        let one: f64 = (0..self.amplitudes.len())
+            .filter(|basis| basis & mask != 0)
+            .map(|basis| self.amplitudes[basis].norm_sqr())
+            .sum();
+        let outcome = rng.next_f64() < one;
+        let norm = if outcome { one } else { 1.0 - one }.sqrt();
+        for (basis, amplitude) in self.amplitudes.iter_mut().enumerate() {
+            if (basis & mask != 0) == outcome {
+                *amplitude /= norm;
+            } else {
+                *amplitude = Complex64::new(0.0, 0.0);
+            }
+        }
+        outcome
+    }
+
+    /// Puts a single qubit into one of the values a qubit can be created with,
+    /// whatever state it was in before
+    fn prepare(&mut self, name: &str, index: usize, value: &str, rng: &mut Rng) {
+        let mask = self.mask(name, index);
+        let x = (gates::signature("x").matrix)(&[]);
+        let h = (gates::signature("h").matrix)(&[]);
+        if self.measure(name, index, rng) {
+            self.apply(&x, &[mask], &[]);
+        }
+        if value == "1" || value == "-" {
+            self.apply(&x, &[mask], &[]);
+        }
+        if value == "+" || value == "-" {
+            self.apply(&h, &[mask], &[]);
+        }
+    }
+
+    /// Runs a single (type checked) statement, measuring into and assigning
+    /// the cbits in `cbits`. Qubits assigned for the first time are added to
+    /// the state.
+    pub fn execute(
+        &mut self,
+        stmt: &Statement,
+        cbits: &mut HashMap<String, Vec<bool>>,
+        rng: &mut Rng,
+    ) -> Result<(), String> {
+        let line_no = stmt.line_no;
+        match &stmt.kind {
+            Stmt::Assign {
+                ty, name, value, ..
+            } => {
+                let elements = initial_values(value);
+                self.widths.insert(name.clone(), elements.len());
+                if !matches!(ty, ValueExpr::Qubit | ValueExpr::QReg) {
+                    cbits.insert(
+                        name.clone(),
+                        elements.iter().map(|bit| bit == "1").collect(),
+                    );
+                    return Ok(());
+                }
+                for (i, element) in elements.iter().enumerate() {
+                    if !self.qubits.contains_key(&(name.clone(), i)) {
+                        self.add_qubit(name, i);
+                    }
+                    self.prepare(name, i, element, rng);
+                }
+            }
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => {
+                let pairs = elements(measured, &self.widths, line_no)?
+                    .into_iter()
+                    .zip(elements(recipient, &self.widths, line_no)?);
+                for ((qname, qi), (cname, ci)) in pairs {
+                    let outcome = self.measure(qname, qi, rng);
+                    cbits.get_mut(cname).unwrap()[ci] = outcome;
+                }
+            }
+            kind => self.apply_gate(kind),
+        }
+        Ok(())
+    }
+}
+
+/// The most qubits `run` will simulate, past which the state gets too big
+pub const MAX_QUBITS: usize = 24;
+
+/// A small xorshift generator, which is all sampling measurements needs
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Spread the seed out with splitmix64, since xorshift can't start at 0
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Rng((z ^ (z >> 31)).max(1))
+    }
+
+    /// A number in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Runs a (type checked) program `shots` times, and counts how often each
+/// outcome came up. An outcome is the final value of every cbit, written as
+/// `name=bits` per variable in the order they were declared, with index 0
+/// first (like `c1=0 cr=10`).
+pub fn run(
+    program: &Program,
+    symbols: &SymbolTable,
+    shots: usize,
+    rng: &mut Rng,
+) -> Result<BTreeMap<String, usize>, String> {
+    let mut cbits: Vec<&str> = vec![];
+    for stmt in &program.stmts {
+        if let Stmt::Assign {
+            ty: ValueExpr::CBit | ValueExpr::CReg,
+            name,
+            ..
+        } = &stmt.kind
+        {
+            if !cbits.contains(&name.as_str()) {
+                cbits.push(name);
+            }
+        }
+    }
+    // The state holds 2^n amplitudes, so the qubits are counted before making it
+    let width: usize = symbols
+        .sizes
+        .iter()
+        .filter(|(name, _)| matches!(symbols.types[*name], ValueExpr::Qubit | ValueExpr::QReg))
+        .map(|(_, size)| size)
+        .sum();
+    if width > MAX_QUBITS {
+        return Err(format!(
+            "The program has {} qubits, but only up to {} can be simulated!",
+            width, MAX_QUBITS
+        ));
+    }
+    let initial = StateVector::new(program);
+
+    let mut counts = BTreeMap::new();
+    for _ in 0..shots {
+        let mut state = initial.clone();
+        let mut values = HashMap::new();
+        for stmt in &program.stmts {
+            state.execute(stmt, &mut values, rng)?;
+        }
+        let outcome: Vec<String> = cbits
+            .iter()
+            .map(|name| format!("{}={}", name, bits(&values[*name])))
+            .collect();
+        *counts.entry(outcome.join(" ")).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+/// Draws the counts from `run` as one line per outcome, with a bar scaled to
+/// the most common one
+pub fn histogram(counts: &BTreeMap<String, usize>) -> String {
+    let width = counts.keys().map(String::len).max().unwrap_or(0);
+    let most = counts.values().copied().max().unwrap_or(0);
+    let mut lines = String::new();
+    for (outcome, count) in counts {
+        let bar = "#".repeat((count * 40).div_ceil(most));
+        lines.push_str(&format!(
+            "{:width$}  {:>6}  {}\n",
+            outcome,
+            count,
+            bar,
+            width = width
+        ));
+    }
+    lines
+}
+
+/// Writes cbit values out with index 0 first
+pub fn bits(values: &[bool]) -> String {
+    values
+        .iter()
+        .map(|&bit| if bit { '1' } else { '0' })
+        .collect()
+}
+
+/// The unitary the gates of a program multiply out to, over every qubit it
+/// declares (in the order of `StateVector`). Initial values and measurements
+/// are ignored.
+pub fn unitary(program: &Program) -> Matrix {
+    let mut state = StateVector::new(program);
+    let size = state.amplitudes.len();
+    let mut columns = vec![];
+    for column in 0..size {
+        state.set_basis_state(column);
+        state.apply_gates(program);
+        columns.push(state.amplitudes.clone());
+    }
+    (0..size)
+        .map(|row| (0..size).map(|column| columns[column][row]).collect())
+        .collect()
+}
@@ -1,372 +1,363 @@
 //! Basic type checker for verifying validity of Quill programs
-use crate::ast::{ASTNode, GateExpr, NodeKind, ValueExpr};
+use crate::ast::{Param, Program, Statement, Stmt, Target, Value, ValueExpr};
+use crate::gates::{self, Controls, GateSignature};
 use std::collections::{HashMap, HashSet};
 
-/// This function is for type checking the AST, making sure that
-/// all of the statements are valid, typing wise
-pub fn type_check(ast: &ASTNode) {
-    // Stores entries of <Variable Name, Type>
-    let mut ctx: HashMap<String, ValueExpr> = HashMap::new();
+/// The variables declared by a program, along with the type and width
+/// (number of qubits / cbits) of each, as collected by the type checker
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    pub types: HashMap<String, ValueExpr>,
+    /// The width of each variable as of the last statement checked
+    pub widths: HashMap<String, usize>,
+    /// The largest width each variable is ever declared with, which is how
+    /// much room it needs in a backend's registers
+    pub sizes: HashMap<String, usize>,
+}
 
-    // Check to see if Return is the second last child
-    let children = ast.children.as_ref().unwrap();
-    for i in 0..(children.len() - 1) {
-        if children[i].node_kind == NodeKind::Return && i != (children.len() - 2) {
-            panic!(
+/// This function is for type checking the AST, making sure that
+/// all of the statements are valid, typing wise. The symbol table it
+/// builds along the way is returned for later analyses to use, and the
+/// first problem found is returned as an error instead.
+pub fn type_check(program: &Program) -> Result<SymbolTable, String> {
+    // Check to see if Return is the last statement (comments may follow it)
+    let stmts = &program.stmts;
+    let last = stmts
+        .iter()
+        .rposition(|stmt| !matches!(stmt.kind, Stmt::Comment(_)));
+    for (i, stmt) in stmts.iter().enumerate() {
+        if matches!(stmt.kind, Stmt::Return { .. }) && Some(i) != last {
+            return Err(format!(
                 "Return expected on line {}, found at line {} instead!",
-                children.len() + 1,
-                i + 2
-            );
+                stmts[last.unwrap()].line_no,
+                stmt.line_no
+            ));
         }
     }
 
-    // TODO: (This section of the code will be done at a later date)
-    // What I need to check for:
-    // - If gate expressions apply a gate to a qreg, make sure the slice exists
-    //   (This can include a single index -> if out of bounds, bad, or it can
-    //   also include having a range and the range not being valid)
-    // - If gate expressions apply to a given qreg, make sure the slices do not overlap at all
-    // - For multicontrol gates, make sure the slices are limited to one single instance of the
-    //   qreg and not multiple qubits across the qreg
-    // - For the measurement expressions, check that the number of qubits measured and number of
-    // cbits measured matches up
-    let mut line_no = 0;
-    for node in ast.children.as_ref().unwrap() {
-        match &node.node_kind {
-            NodeKind::Assignment => {
-                line_no += 1;
-                let children = node.children.as_ref().unwrap();
-                assert_eq!(children.len(), 4, "{}: Your assignment node somehow didn't have the requisite number of elements!\nShame on thee!", line_no);
-                let val_type = &children[1];
-                let name = match &children[2].node_kind {
-                    NodeKind::Name(nam) => nam,
-                    _ => unreachable!(),
-                };
-                let value = &children[3];
-
-                let val_expr = match &val_type.node_kind {
-                    NodeKind::ValueType(typ) => assignment_helper(typ, &value),
-                    _ => panic!(
-                        "{}: The ValueType node should have AST NodeKind ValueType!",
-                        line_no
-                    ),
-                };
-                // Use the return value of insert to check and see if there was a previous entry
-                // with the same name, and then verify types!
-                let old_val = ctx.insert((*name.clone()).to_string(), val_expr.clone());
-                if let Some(prev) = old_val {
-                    if prev != val_expr {
-                        panic!(
-                            "{}: {:?} was originally of type {:?}, but now given type {:?}!",
-                            line_no,
-                            *name,
-                            prev,
-                            val_expr.clone()
-                        );
-                    }
-                }
-            }
-            NodeKind::GateApplication => {
-                line_no += 1;
-                let children = node.children.as_ref().unwrap();
-                // [gate, gate_type_node, target, controls, params] (Always controls first)
-                // controls and params are optional
-
-                // Check name of target, verify that it's qubit or single qreg slice
-                // OR, if is multi qreg slice, then the gate is a single qubit gate of some form
-                verify_target(&children[2], &ctx, line_no);
-                // match &children[2].node_kind {
-                //     NodeKind::Name(nam) => {
-                //         // Qubit Case, verify name is a qubit
-                //         if let Some(val) = ctx.get(nam) {
-                //             match *val {
-                //                 ValueExpr::Qubit => {}
-                //                 _ => panic!("Qubit expected, {:?} given!", val.clone()),
-                //             }
-                //         } else {
-                //             panic!("Unknown variable {:?} given, not a qubit!", nam.clone());
-                //         }
-                //     }
-                //     NodeKind::QRegSlice => {
-                //         let qreg_children = &children[2].children.as_ref().unwrap();
-                //         match &qreg_children[0].node_kind {
-                //             /*TODO: Should probably be an if let*/
-                //             NodeKind::Name(nam) => {
-                //                 // QReg Case, verify name is a QReg
-                //                 if let Some(val) = ctx.get(nam) {
-                //                     match *val {
-                //                         ValueExpr::QReg => {
-                //                             // TODO: Check index validity
-                //                         }
-                //                         _ => panic!("QReg expected, {:?} given!", val.clone()),
-                //                     }
-                //                 } else {
-                //                     panic!("Unknown variable {:?} given, not a qreg!", nam.clone());
-                //                 }
-                //             }
-                //             _ => unreachable!(),
-                //         }
-                //     }
-                //     _ => unreachable!(),
-                // }
-
-                // actually do the type checking for the
-                // [gate, gate_type_node, target, controls, params] (Always controls first) (match against gate_type_node for what to expect)
-                // NOTE: this if let is kind of redundant, but "easy" for now
-                // note that gates, as talked about below, will always be vacuously
-                // correct. Below, we destructure the vector manually because it
-                // isn't cool like tuples.
-                let gate_node_kind = &children[1].node_kind;
-                // let controls = &children[3]; // Option
-                // let params = &children[4]; // Option
-                if let NodeKind::GateType(gate_expr) = gate_node_kind {
-                    match gate_expr {
-                        GateExpr::Q1Gate => {
-                            /*children.len() = 3, no cont, params
-                             * we have already checked for target's validity
-                             * and because of pest parsing, the gate name will
-                             * be a correct subset of the gate_type_node's
-                             * category (e.g. 'h' will necessarily be of
-                             * type Q1Gate. Hence, we do nothing here!*/
-                        }
-                        GateExpr::Q1ParamGate => {
-                            /*Requires params list, so check for params.len() == 1*/
-                            if let Some(pars) = &children[3].children {
-                                assert!(
-                                    pars.len() == 1,
-                                    "{}", format!("{}: More than one parameter for single qubit parameterized gate!", line_no)
-                                );
-                                // DONE: Continue checks by making sure the parameter is defined
-                                // correctly (Has to be PI, Float, Int for params, make this check
-                                // a function?)
-                                match &pars[0].node_kind {
-                                    NodeKind::PI(_) | NodeKind::Float(_) | NodeKind::Int(_) => {},
-                                    other => panic!("{}: Parameters should be of type PI, Float, or Int, found {:?} instead!", line_no, other),
-                                }
-                            } else {
-                                panic!("{}: No parameters for Q1 Param Gate!", line_no);
-                            }
-                        }
-                        GateExpr::Q2Gate => {
-                            /*Q2 gates are cx, cz for now, so they require controls, not params*/
-                            if let Some(controls) = &children[3].children {
-                                assert!(
-                                    controls.len() == 1,
-                                    "{}", format!("{}: More than one controlled qubit for a double qubit control gate!", line_no)
-                                );
-                                // TODO: Check that control is a defined qubit and not a duplicate
-                                // (make function)
-                                verify_target(&controls[0], &ctx, line_no); /* Can repeat for QMultiGate */
-                                control_validity(&children[2], controls, line_no);
-                            } else {
-                                panic!("{}: Q2 gates require controls list, but no list of controlled qubits was found!", line_no);
-                            }
-                        }
-                        GateExpr::Q2ParamGate => {
-                            /*Requires params list, so check for params.len() == 1*/
-                            if let Some(pars) = &children[3].children {
-                                assert!(
-                                    pars.len() == 1,
-                                    "{}", format!("{}: More than one parameter for double qubit parameterized gate!", line_no)
-                                );
-                                // DONE: Continue checks by making sure the parameter is defined
-                                // correctly (Has to be PI, Float, Int for params, make this check
-                                // a function?)
-                                match &pars[0].node_kind {
-                                    NodeKind::PI(_) | NodeKind::Float(_) | NodeKind::Int(_) => {},
-                                    other => panic!("{}: Parameters should be of type PI, Float, or Int, found {:?} instead!", line_no, other),
-                                }
-                            } else {
-                                panic!("{}: No parameters for Q2 Param Gate!", line_no);
-                            }
-                        }
-                        GateExpr::QMultiGate => {
-                            /*Q2 gates are cx, cz for now, so they require controls, not params*/
-                            if let Some(controls) = &children[3].children {
-                                // TODO: Check that control is a defined qubit and not a duplicate
-                                // (make function)
-                                // NOTE: REPEAT FOR QMULTI
-                                for control in controls {
-                                    verify_target(control, &ctx, line_no);
-                                }
-                                control_validity(&children[2], controls, line_no);
-                            } else {
-                                panic!("{}: QMulti gates require controls list, but no list of controlled qubits was found!", line_no);
-                            }
-                        }
-                        _ => unreachable!(),
-                    }
-                }
-            }
-            NodeKind::Measurement => {
-                line_no += 1;
-                // [measured (Name/QRegSlice), recipient (Name/CRegSlice)]
-                let children = node.children.as_ref().unwrap();
-                // Measured Qubit / QRegSlice
-                verify_target(&children[0], &ctx, line_no);
+    let mut symbols = SymbolTable::default();
+    for stmt in stmts {
+        check_statement(stmt, &mut symbols)?;
+    }
+    Ok(symbols)
+}
 
-                // Recipient CBit / CRegSlice
-                match &children[1].node_kind {
-                    NodeKind::Name(nam) => {
-                        // CBit case, verify name is a cbit
-                        if let Some(val) = ctx.get(nam) {
-                            match *val {
-                                ValueExpr::CBit => {}
-                                _ => panic!("CBit expected, {:?} given!", val.clone()),
-                            }
-                        } else {
-                            panic!("Unknown variable {:?} given, not a CBit!", nam.clone());
-                        }
-                    }
-                    NodeKind::CRegSlice => {
-                        let creg_children = &children[0].children.as_ref().unwrap();
-                        match &creg_children[0].node_kind {
-                            NodeKind::Name(nam) => {
-                                // QReg Case, verify name is a QReg
-                                if let Some(val) = ctx.get(nam) {
-                                    match *val {
-                                        ValueExpr::CReg => {
-                                            // TODO: Check index validity
-                                        }
-                                        _ => panic!("CReg expected, {:?} given!", val.clone()),
-                                    }
-                                } else {
-                                    panic!(
-                                        "Unknown variable {:?} given, not a qubit!",
-                                        nam.clone()
-                                    );
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                    _ => {}
+/// Type checks a single statement against the variables declared before it,
+/// adding whatever it assigns to the symbol table. The table is left alone
+/// when the statement doesn't check.
+pub fn check_statement(stmt: &Statement, symbols: &mut SymbolTable) -> Result<(), String> {
+    // Stores entries of <Variable Name, Type>
+    let ctx = &mut symbols.types;
+    // Stores entries of <Variable Name, Width>, so that slices can be checked against the
+    // number of qubits / cbits their register was created with
+    let widths = &mut symbols.widths;
+    let line_no = stmt.line_no;
+    match &stmt.kind {
+        Stmt::Assign {
+            ty, name, value, ..
+        } => {
+            let val_expr = assignment_helper(ty, value, line_no)?;
+            let width = checked_width(value, line_no)?;
+            // Verify that a variable being reassigned keeps its type
+            if let Some(prev) = ctx.get(name) {
+                if *prev != val_expr {
+                    return Err(format!(
+                        "{}: {:?} was originally of type {:?}, but now given type {:?}!",
+                        line_no, name, prev, val_expr
+                    ));
                 }
             }
-            NodeKind::Return => {
-                line_no += 1;
-                // [shots]
-                // Verify if integer is non-negative
-                let children = node.children.as_ref().unwrap();
-                match &children[0].node_kind {
-                    NodeKind::Int(val) => {
-                        if *val < 1 {
-                            panic!(
-                                "Non-negative number of shots required, {} shots received instead!",
-                                val
-                            );
-                        }
-                    }
-                    _ => panic!("Node is not of type int, unexpected in return statement!"),
-                }
+            ctx.insert(name.clone(), val_expr);
+            widths.insert(name.clone(), width);
+            let size = symbols.sizes.entry(name.clone()).or_insert(0);
+            *size = width.max(*size);
+        }
+        Stmt::Gate {
+            gate,
+            targets,
+            controls,
+            params,
+            ..
+        } => {
+            let signature = gates::signature(gate);
+            verify_gate(signature, targets, controls, params, ctx, widths, line_no)?;
+        }
+        Stmt::Measure {
+            measured,
+            recipient,
+        } => verify_measurement(measured, recipient, ctx, widths, line_no)?,
+        Stmt::Return { shots } => {
+            // Verify if integer is non-negative
+            if *shots < 1 {
+                return Err(format!(
+                    "Non-negative number of shots required, {} shots received instead!",
+                    shots
+                ));
             }
-            NodeKind::COMMENT => line_no += 1,
-            NodeKind::EOI => {} // Intentionally do nothing here, nothing to handle
-            _ => unreachable!(),
+        }
+        Stmt::Comment(_) => {} // Intentionally do nothing here, nothing to handle
+        Stmt::Import { .. } | Stmt::GateDef { .. } | Stmt::Constant { .. } | Stmt::Call { .. } => {
+            // Linking replaces these before a program is checked
+            return Err(format!(
+                "{}: imports, gate definitions, and constants only work in files, once they're linked!",
+                line_no
+            ));
         }
     }
+    Ok(())
 }
 
 /// A helper function for the assignment portion of the type checker
 /// which allows us to compare the type we've annotated and
 /// the actual type of the variable!
-fn assignment_helper(typ: &ValueExpr, value: &ASTNode) -> ValueExpr {
-    let value_typ = match &value.node_kind {
-        NodeKind::QReg => ValueExpr::QReg,
-        NodeKind::CReg => ValueExpr::CReg,
-        NodeKind::Qubit(_) => ValueExpr::Qubit,
-        NodeKind::CBit(_) => ValueExpr::CBit,
-        unknown => panic!("{:?} is not supported in assignment expressions!", unknown),
+fn assignment_helper(typ: &ValueExpr, value: &Value, line_no: i32) -> Result<ValueExpr, String> {
+    let value_typ = match value {
+        Value::QReg(_) => ValueExpr::QReg,
+        Value::CReg(_) => ValueExpr::CReg,
+        Value::Qubit(_) => ValueExpr::Qubit,
+        Value::CBit(_) => ValueExpr::CBit,
     };
 
-    assert_eq!(*typ, value_typ);
-    value_typ
+    if *typ != value_typ {
+        return Err(format!(
+            "{}: A {:?} was declared, but given a {:?} value!",
+            line_no, typ, value_typ
+        ));
+    }
+    Ok(value_typ)
 }
 
-// Goal of this function is to make sure that the target node is a valid Qubit or QReg
-fn verify_target(target: &ASTNode, ctx: &HashMap<String, ValueExpr>, line_no: i32) {
-    match &target.node_kind {
-        NodeKind::Name(nam) => {
+// Goal of this function is to make sure that the target is a valid Qubit or QReg
+fn verify_target(
+    target: &Target,
+    ctx: &HashMap<String, ValueExpr>,
+    line_no: i32,
+) -> Result<(), String> {
+    match target {
+        Target::Name(nam) => {
             // Qubit Case, verify name is a qubit
             if let Some(val) = ctx.get(nam) {
                 match *val {
                     ValueExpr::Qubit => {}
-                    _ => panic!("{}: Qubit expected, {:?} given!", line_no, val.clone()),
+                    _ => return Err(format!("{}: Qubit expected, {:?} given!", line_no, val)),
                 }
             } else {
-                panic!(
+                return Err(format!(
                     "{}: Unknown variable {:?} given, not a qubit!",
-                    line_no,
-                    nam.clone()
-                );
+                    line_no, nam
+                ));
             }
         }
-        NodeKind::QRegSlice => {
-            let qreg_children = target.children.as_ref().unwrap();
-            match &qreg_children[0].node_kind {
-                NodeKind::Name(nam) => {
-                    // QReg Case, verify name is a QReg
-                    if let Some(val) = ctx.get(nam) {
-                        match *val {
-                            ValueExpr::QReg => {
-                                // TODO: Check index validity (need to reimplement hashing, add
-                                // separate hash for storing indices for QRegs during assignment,
-                                // to cross reference during gate application)
-                                // let indices = &qreg_children[1].children.as_ref().unwrap();
-                            }
-                            _ => panic!("{}: QReg expected, {:?} given!", line_no, val.clone()),
-                        }
-                    } else {
-                        panic!(
-                            "{}: Unknown variable {:?} given, not a qreg!",
-                            line_no,
-                            nam.clone()
-                        );
+        Target::Slice { name, .. } => {
+            // QReg Case, verify name is a QReg
+            if let Some(val) = ctx.get(name) {
+                match *val {
+                    ValueExpr::QReg => {
+                        // Index validity is checked separately by checked_range, using the
+                        // widths recorded during assignment
                     }
+                    _ => return Err(format!("{}: QReg expected, {:?} given!", line_no, val)),
                 }
-                _ => unreachable!(),
+            } else {
+                return Err(format!(
+                    "{}: Unknown variable {:?} given, not a qreg!",
+                    line_no, name
+                ));
             }
         }
-        _ => unreachable!(),
     }
+    Ok(())
+}
+
+/// Verifies a gate application against the gate's signature in the registry:
+/// the number and types of its parameters, the number of qubits and controls
+/// it was given, and that every qubit it touches exists and is distinct.
+/// Only single qubit gates without controls may target a range of qubits, in
+/// which case the gate is applied to every qubit in the range.
+fn verify_gate(
+    signature: &GateSignature,
+    targets: &[Target],
+    controls: &[Target],
+    params: &[Param],
+    ctx: &HashMap<String, ValueExpr>,
+    widths: &HashMap<String, usize>,
+    line_no: i32,
+) -> Result<(), String> {
+    let gate = signature.name;
+    if params.len() != signature.params {
+        return Err(format!(
+            "{}: {} takes {} parameter(s), but {} were given!",
+            line_no,
+            gate,
+            signature.params,
+            params.len()
+        ));
+    }
+    if targets.len() != signature.qubits {
+        return Err(format!(
+            "{}: {} acts on {} qubit(s), but {} were given!",
+            line_no,
+            gate,
+            signature.qubits,
+            targets.len()
+        ));
+    }
+    if !signature.controls.accepts(controls.len()) {
+        let expected = match signature.controls {
+            Controls::None => {
+                return Err(format!(
+                    "{}: {} does not take any control qubits!",
+                    line_no, gate
+                ))
+            }
+            Controls::Exactly(n) => format!("exactly {}", n),
+            Controls::AtLeast(n) => format!("at least {}", n),
+        };
+        return Err(format!(
+            "{}: {} needs {} control qubit(s), but {} were given!",
+            line_no,
+            gate,
+            expected,
+            controls.len()
+        ));
+    }
+
+    let operands: Vec<&Target> = targets.iter().chain(controls).collect();
+    for operand in &operands {
+        verify_target(operand, ctx, line_no)?;
+    }
+    if operands.len() == 1 {
+        // A range given to a single qubit gate applies it to every qubit in the range, and
+        // checked_range takes care of checking the bounds
+        checked_range(operands[0], widths, line_no)?;
+        return Ok(());
+    }
+    distinct_qubits(&operands, widths, line_no)
 }
 
-// Goal of this function is to make sure there are no duplicates amongst the target and all the
-// controls.
-fn control_validity(target: &ASTNode, controls: &Vec<ASTNode>, line_no: i32) {
-    let target_name = get_name_from_node(target);
-    let mut control_ids: Vec<&str> = vec![];
-    for qubit in controls {
-        let qubit_name = get_name_from_node(qubit);
-        control_ids.push(qubit_name);
-        if target_name == qubit_name {
-            panic!("{}: Controlled gates cannot control on the same qubit, but target equalled controlled!", line_no);
+// Goal of this function is to make sure there are no duplicates amongst the targets and all the
+// controls of a multi-qubit gate, and that every one of them is a single qubit.
+fn distinct_qubits(
+    operands: &[&Target],
+    widths: &HashMap<String, usize>,
+    line_no: i32,
+) -> Result<(), String> {
+    let mut qubit_ids: Vec<(&str, usize)> = vec![];
+    for qubit in operands {
+        let (qubit_name, start, end) = checked_range(qubit, widths, line_no)?;
+        if start != end {
+            return Err(format!(
+                "{}: Multi-qubit gates act on single qubits, but the range {}[{}..{}] was given!",
+                line_no, qubit_name, start, end
+            ));
         }
+        qubit_ids.push((qubit_name, start));
+    }
+    if qubit_ids.len() != HashSet::<(&str, usize)>::from_iter(qubit_ids.clone()).len() {
+        return Err(format!(
+            "{}: A gate cannot act on the same qubit twice, but there was a duplicate amongst: {:?}",
+            line_no, qubit_ids
+        ));
+    }
+    Ok(())
+}
+
+/// Computes how many qubits or cbits an assignment value holds, so that
+/// slices and measurements can be checked against it later on
+pub(crate) fn checked_width(value: &Value, line_no: i32) -> Result<usize, String> {
+    let lengths: Vec<usize> = match value {
+        Value::Qubit(_) | Value::CBit(_) => vec![1],
+        Value::QReg(segments) => segments.iter().map(|(_, length)| *length).collect(),
+        Value::CReg(segments) => segments.iter().map(|(_, length)| *length).collect(),
+    };
+    if lengths.contains(&0) {
+        return Err(format!(
+            "{}: Register lengths must be positive integers, found 0 instead!",
+            line_no
+        ));
     }
-    assert_eq!(
-        control_ids.len(),
-        HashSet::<&str>::from_iter(control_ids.clone()).len(),
-        "{}",
-        format!(
-            "{}: There was a duplicate amongst the control qubits: {:?}",
-            line_no, control_ids
-        ),
-    );
+    Ok(lengths.iter().sum())
 }
 
-fn get_name_from_node(node: &ASTNode) -> &str {
-    match &node.node_kind {
-        NodeKind::Name(nam) => nam,
-        NodeKind::QRegSlice => {
-            let qreg_children = node.children.as_ref().unwrap();
-            match &qreg_children[0].node_kind {
-                NodeKind::Name(nam) => nam,
-                _ => unreachable!(),
+/// Resolves a target (a bare variable or a register slice) to the name of the
+/// variable it refers to and the inclusive range of indices it covers. A bare
+/// variable covers every index of its register, and the indices of a slice are
+/// checked against the width the register has at that point, which is up to
+/// the caller to keep track of as variables get redeclared.
+pub(crate) fn checked_range<'a>(
+    target: &'a Target,
+    widths: &HashMap<String, usize>,
+    line_no: i32,
+) -> Result<(&'a str, usize, usize), String> {
+    let name = target.name();
+    let width = widths[name];
+    match *target {
+        Target::Name(_) => Ok((name, 0, width - 1)),
+        Target::Slice { start, end, .. } => {
+            if start > end {
+                return Err(format!(
+                    "{}: The range {}[{}..{}] is backwards, the start index must not exceed the end!",
+                    line_no, name, start, end
+                ));
             }
+            if end >= width {
+                return Err(format!(
+                    "{}: Index {} is out of bounds for {:?}, which only has {} elements!",
+                    line_no, end, name, width
+                ));
+            }
+            Ok((name, start, end))
         }
-        _ => unreachable!(),
     }
 }
 
-// TODO: This
-// fn check_index_validity()
+/// Checks that a variable exists and has one of the allowed types
+fn verify_type(
+    name: &str,
+    allowed: &[ValueExpr],
+    ctx: &HashMap<String, ValueExpr>,
+    line_no: i32,
+) -> Result<(), String> {
+    match ctx.get(name) {
+        Some(val) if allowed.contains(val) => Ok(()),
+        Some(val) => Err(format!(
+            "{}: {:?} expected for {:?}, {:?} given!",
+            line_no, allowed, name, val
+        )),
+        None => Err(format!("{}: Unknown variable {:?} given!", line_no, name)),
+    }
+}
+
+/// Verifies a measurement statement, "Rede measured and quyken recipient".
+/// The measured side must be a qubit, a qreg, or a slice of a qreg, and the
+/// recipient must be a cbit, a creg, or a slice of a creg covering exactly as
+/// many elements. Ranges map element-wise and in order, so
+/// `Rede qr[0..2] and quyken cr[3..5]` stores qr[0] in cr[3], qr[1] in cr[4],
+/// and qr[2] in cr[5].
+fn verify_measurement(
+    measured: &Target,
+    recipient: &Target,
+    ctx: &HashMap<String, ValueExpr>,
+    widths: &HashMap<String, usize>,
+    line_no: i32,
+) -> Result<(), String> {
+    match measured {
+        Target::Name(nam) => verify_type(nam, &[ValueExpr::Qubit, ValueExpr::QReg], ctx, line_no)?,
+        Target::Slice { name, .. } => verify_type(name, &[ValueExpr::QReg], ctx, line_no)?,
+    }
+    match recipient {
+        Target::Name(nam) => verify_type(nam, &[ValueExpr::CBit, ValueExpr::CReg], ctx, line_no)?,
+        Target::Slice { name, .. } => verify_type(name, &[ValueExpr::CReg], ctx, line_no)?,
+    }
+
+    let (qname, qstart, qend) = checked_range(measured, widths, line_no)?;
+    let (cname, cstart, cend) = checked_range(recipient, widths, line_no)?;
+    let (measured_width, recipient_width) = (qend - qstart + 1, cend - cstart + 1);
+    if measured_width != recipient_width {
+        return Err(format!(
+            "{}: Cannot measure {} qubit(s) of {:?} into {} cbit(s) of {:?}, the widths must match!",
+            line_no, measured_width, qname, recipient_width, cname
+        ));
+    }
+    Ok(())
+}
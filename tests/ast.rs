@@ -0,0 +1,149 @@
+//! Checks the typed AST the parser builds, that writing it back out gives
+//! source that parses to the same AST, and the node tree kept for debugging
+use quill::ast::{
+    ASTNode, GateExpr, NodeKind, Param, Program, RespectExpr, Statement, Stmt, Target, Value,
+    ValueExpr,
+};
+use quill::parser::parse;
+
+/// Every kind of statement, written the way `Display` writes it back out
+const EVERYTHING: &str = "// Every kind of statement
+Fecche \"std/qft.ql\"
+Certes quarter with value PI[1, 2]
+Devyse pair on a, b
+    Thy h shalt target a
+    Thy cnot shalt target b and control on a
+Ende
+Maistow create oo qreg q with value 0[2] + 1[1] + +[2]
+Canstow create oo qubit r with value -
+Maistow create oo creg c with value b0[3] + b1[2]
+Canstow create oo cbit d with value b1
+Thy h shalt target q[0..4]
+Thy u3 shalt target r with [0.5, -2, PI[3, 4]]
+Thy rz shalt target q[1] with [quarter]
+Thy swap shalt target q[0] and q[4]
+Thy mcz shalt target q[3] and control on q[0], q[1], q[2]
+Thy pair shalt target q[0] and r
+Thy qft shalt target q[0..2]
+Rede q[0..4] and quyken c
+Rede r and quyken d
+Return 100
+";
+
+#[test]
+fn programs_round_trip_through_display() {
+    let program = parse(EVERYTHING).unwrap();
+    assert_eq!(program.to_string(), EVERYTHING);
+    assert_eq!(parse(&program.to_string()).unwrap(), program);
+}
+
+#[test]
+fn statements_build_typed_nodes() {
+    let program = parse(
+        "Maistow create oo qreg q with value 0[1] + 1[1]
+Thy cx shalt target q[1] and control on q[0]
+Thy rx shalt target q[0..1] with [PI]
+Rede q[1] and quyken c // c isn't declared, but that's for the type checker
+",
+    )
+    .unwrap();
+    let slice = |start, end| Target::Slice {
+        name: "q".to_string(),
+        start,
+        end,
+    };
+    assert_eq!(
+        program.stmts,
+        [
+            Statement {
+                line_no: 1,
+                kind: Stmt::Assign {
+                    respect: RespectExpr::Maistow,
+                    ty: ValueExpr::QReg,
+                    name: "q".to_string(),
+                    value: Value::QReg(vec![("0".to_string(), 1), ("1".to_string(), 1)]),
+                },
+            },
+            // Aliases are kept as written
+            Statement {
+                line_no: 2,
+                kind: Stmt::Gate {
+                    gate: "cx".to_string(),
+                    kind: GateExpr::Q2Gate,
+                    targets: vec![slice(1, 1)],
+                    controls: vec![slice(0, 0)],
+                    params: vec![],
+                },
+            },
+            Statement {
+                line_no: 3,
+                kind: Stmt::Gate {
+                    gate: "rx".to_string(),
+                    kind: GateExpr::Q1ParamGate,
+                    targets: vec![slice(0, 1)],
+                    controls: vec![],
+                    params: vec![Param::PI(1, 1)],
+                },
+            },
+            Statement {
+                line_no: 4,
+                kind: Stmt::Measure {
+                    measured: slice(1, 1),
+                    recipient: Target::Name("c".to_string()),
+                },
+            },
+            Statement {
+                line_no: 4,
+                kind: Stmt::Comment(
+                    " c isn't declared, but that's for the type checker".to_string()
+                ),
+            },
+        ]
+    );
+}
+
+#[test]
+fn the_debug_tree_mirrors_the_statements() {
+    let program =
+        parse("Canstow create oo qubit q with value 1\nThy rz shalt target q with [2]\n").unwrap();
+    let leaf = |node_kind| ASTNode::new(None, node_kind);
+    assert_eq!(
+        program.to_tree(),
+        ASTNode::new(
+            Some(vec![
+                ASTNode::new(
+                    Some(vec![
+                        leaf(NodeKind::RespectType(RespectExpr::Canstow)),
+                        leaf(NodeKind::ValueType(ValueExpr::Qubit)),
+                        leaf(NodeKind::Name("q".to_string())),
+                        leaf(NodeKind::Qubit("1".to_string())),
+                    ]),
+                    NodeKind::Assignment,
+                ),
+                ASTNode::new(
+                    Some(vec![
+                        leaf(NodeKind::Name("rz".to_string())),
+                        leaf(NodeKind::GateType(GateExpr::Q1ParamGate)),
+                        leaf(NodeKind::Name("q".to_string())),
+                        ASTNode::new(Some(vec![leaf(NodeKind::Int(2))]), NodeKind::ValList),
+                    ]),
+                    NodeKind::GateApplication,
+                ),
+                leaf(NodeKind::EOI),
+            ]),
+            NodeKind::Program,
+        )
+    );
+}
+
+#[test]
+fn empty_programs_are_just_the_end() {
+    assert_eq!(parse("").unwrap(), Program { stmts: vec![] });
+    assert_eq!(
+        parse("").unwrap().to_tree(),
+        ASTNode::new(
+            Some(vec![ASTNode::new(None, NodeKind::EOI)]),
+            NodeKind::Program
+        )
+    );
+}
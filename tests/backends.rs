@@ -0,0 +1,97 @@
+//! Checks the registers every backend sets aside for variables that are
+//! declared more than once, possibly with different widths
+use quill::backends::{qasm, qasm3, qir, qiskit};
+use quill::parser::parse;
+use quill::type_checker::{type_check, SymbolTable};
+
+/// q and c grow when they're redeclared, and r comes between the two q's
+const GROWING: &str = "Maistow create oo qreg q with value 0[2]
+Canstow create oo qubit r with value 1
+Canstow create oo creg c with value b0[2]
+Thy h shalt target q[1]
+Maistow create oo qreg q with value 1[4]
+Maistow create oo creg c with value b1[3]
+Thy x shalt target q[3]
+Thy cnot shalt target r and control on q[3]
+Rede q[2..3] and quyken c[0..1]
+Return 10
+";
+
+fn checked(source: &str) -> (quill::ast::Program, SymbolTable) {
+    let program = parse(source).unwrap();
+    let symbols = type_check(&program).unwrap();
+    (program, symbols)
+}
+
+/// Whether every line appears in the source, in order
+fn contains_in_order(source: &str, lines: &[&str]) -> bool {
+    let mut rest = source.lines();
+    lines.iter().all(|line| rest.any(|actual| actual == *line))
+}
+
+#[test]
+fn registers_are_as_wide_as_their_widest_declaration() {
+    let (program, symbols) = checked(GROWING);
+    assert_eq!(symbols.sizes["q"], 4);
+    assert_eq!(symbols.widths["q"], 4);
+
+    let source = qasm::emit(&program, &symbols).unwrap();
+    assert!(
+        contains_in_order(
+            &source,
+            &[
+                "qreg q[4];",
+                "qreg r[1];",
+                "creg c[3];",
+                "h q[1];",
+                "reset q[3];",
+                "x q[3];",
+                "measure q[3] -> c[1];",
+            ]
+        ),
+        "{}",
+        source
+    );
+    assert!(!source.contains("qreg q[2];"));
+
+    let source = qasm3::emit(&program, &symbols).unwrap();
+    assert!(
+        contains_in_order(
+            &source,
+            &[
+                "qubit[4] q;",
+                "bit[3] c = \"000\";",
+                "reset q;",
+                "c = \"111\";",
+            ]
+        ),
+        "{}",
+        source
+    );
+
+    let source = qiskit::emit(&program, &symbols).unwrap();
+    assert!(source.contains("q = QuantumRegister(4, \"q\")"));
+    assert!(source.contains("c = ClassicalRegister(3, \"c\")"));
+
+    // r comes after all four of q's qubits, rather than overlapping the last two
+    let source = qir::emit(&program, &symbols).unwrap();
+    assert!(source.contains("\"required_num_qubits\"=\"5\" \"required_num_results\"=\"3\""));
+    assert!(source.contains(
+        "call void @__quantum__qis__cnot__body(%Qubit* inttoptr (i64 3 to %Qubit*), %Qubit* inttoptr (i64 4 to %Qubit*))"
+    ));
+}
+
+#[test]
+fn shrinking_bits_only_assigns_the_ones_in_use() {
+    let (program, symbols) = checked(
+        "Maistow create oo creg c with value b0[3]
+Maistow create oo creg c with value b1[2]
+",
+    );
+    let source = qasm3::emit(&program, &symbols).unwrap();
+    assert!(
+        contains_in_order(&source, &["bit[3] c = \"000\";", "c[0] = 1;", "c[1] = 1;"]),
+        "{}",
+        source
+    );
+}
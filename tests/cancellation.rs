@@ -0,0 +1,94 @@
+//! Checks that cancelling inverse pairs only removes gates that really undo
+//! each other
+mod common;
+
+use common::{gate_names, run_pass};
+use quill::optimizations::cancellation::CancelInverses;
+
+/// The gates left over from cancelling over three qubits q[0..2]
+fn cancel(gates: &str) -> Vec<String> {
+    gate_names(&run_pass(&CancelInverses, 3, gates))
+}
+
+#[test]
+fn adjacent_pairs_cancel() {
+    for gates in [
+        "Thy h shalt target q[0]\nThy h shalt target q[0]\n",
+        "Thy s shalt target q[1]\nThy sadj shalt target q[1]\n",
+        "Thy tadj shalt target q[2]\nThy t shalt target q[2]\n",
+        "Thy sx shalt target q[0]\nThy sxadj shalt target q[0]\n",
+        "Thy swap shalt target q[0] and q[2]\nThy swap shalt target q[0] and q[2]\n",
+    ] {
+        assert!(cancel(gates).is_empty(), "{}", gates);
+    }
+    // Removing one pair leaves the next one adjacent
+    let nested = "Thy h shalt target q[0]
+Thy x shalt target q[0]
+Thy x shalt target q[0]
+Thy h shalt target q[0]
+";
+    assert!(cancel(nested).is_empty());
+}
+
+#[test]
+fn pairs_cancel_through_commuting_gates() {
+    // rz is diagonal like s, x shares the cnot's target basis, and y doesn't
+    // touch q[0] at all
+    for (gates, left) in [
+        (
+            "Thy s shalt target q[0]\nThy rz shalt target q[0] with [0.3]\nThy sadj shalt target q[0]\n",
+            "rz",
+        ),
+        (
+            "Thy cnot shalt target q[1] and control on q[0]\nThy x shalt target q[1]\nThy cnot shalt target q[1] and control on q[0]\n",
+            "x",
+        ),
+        (
+            "Thy cnot shalt target q[1] and control on q[0]\nThy t shalt target q[0]\nThy cnot shalt target q[1] and control on q[0]\n",
+            "t",
+        ),
+        (
+            "Thy h shalt target q[0]\nThy y shalt target q[1]\nThy h shalt target q[0]\n",
+            "y",
+        ),
+    ] {
+        assert_eq!(cancel(gates), [left], "{}", gates);
+    }
+}
+
+#[test]
+fn pairs_stay_around_gates_that_dont_commute() {
+    for gates in [
+        "Thy h shalt target q[0]\nThy s shalt target q[0]\nThy h shalt target q[0]\n",
+        "Thy s shalt target q[0]\nThy x shalt target q[0]\nThy sadj shalt target q[0]\n",
+        // z on the target, and x on the control, both get in the cnots' way
+        "Thy cnot shalt target q[1] and control on q[0]\nThy z shalt target q[1]\nThy cnot shalt target q[1] and control on q[0]\n",
+        "Thy cnot shalt target q[1] and control on q[0]\nThy x shalt target q[0]\nThy cnot shalt target q[1] and control on q[0]\n",
+    ] {
+        assert_eq!(cancel(gates).len(), 3, "{}", gates);
+    }
+}
+
+#[test]
+fn controlled_pairs_need_the_same_controls() {
+    for gates in [
+        "Thy cz shalt target q[1] and control on q[0]\nThy cz shalt target q[1] and control on q[0]\n",
+        // The order controls are listed in doesn't matter
+        "Thy toffoli shalt target q[2] and control on q[0], q[1]\nThy toffoli shalt target q[2] and control on q[1], q[0]\n",
+        "Thy mcz shalt target q[0] and control on q[1], q[2]\nThy s shalt target q[1]\nThy mcz shalt target q[0] and control on q[2], q[1]\n",
+    ] {
+        assert!(
+            !cancel(gates).iter().any(|gate| gate != "s"),
+            "{}",
+            gates
+        );
+    }
+    for gates in [
+        "Thy cnot shalt target q[1] and control on q[0]\nThy cnot shalt target q[1] and control on q[2]\n",
+        "Thy cnot shalt target q[1] and control on q[0]\nThy cnot shalt target q[0] and control on q[1]\n",
+        "Thy mcx shalt target q[2] and control on q[0], q[1]\nThy mcx shalt target q[2] and control on q[0]\n",
+        "Thy cnot shalt target q[1] and control on q[0]\nThy x shalt target q[1]\n",
+    ] {
+        assert_eq!(cancel(gates).len(), 2, "{}", gates);
+    }
+}
@@ -0,0 +1,149 @@
+//! Checks that programs survive the trip through the circuit DAG, and that
+//! its layers (which the optimizer measures depth with) put every operation
+//! right after the ones it depends on
+use quill::ast::Stmt;
+use quill::circuit::{Circuit, Wire};
+use quill::optimizations::depth;
+use quill::parser::parse;
+
+const BELL: &str = "Maistow create oo qreg q with value 0[2]
+Canstow create oo creg c with value b0[2]
+Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Rede q and quyken c
+Return 200
+";
+
+#[test]
+fn programs_round_trip() {
+    for source in [
+        BELL,
+        "Maistow create oo qreg q with value 0[3]
+// Comments stay where they are
+Canstow create oo qubit r with value 1
+Canstow create oo creg c with value b0[3]
+Thy h shalt target q[0..2]
+Thy x shalt target r
+Thy toffoli shalt target r and control on q[0], q[2]
+Thy rzz shalt target q[1] and q[2] with [PI[1, 4]]
+Rede q[1..2] and quyken c[0..1]
+Maistow create oo qreg q with value 1[2]
+Thy swap shalt target q[0] and q[1]
+Rede q[0] and quyken c[2]
+",
+    ] {
+        let program = parse(source).unwrap();
+        let circuit = Circuit::from_program(&program);
+        assert_eq!(circuit.to_program(), program);
+        assert_eq!(circuit.to_source(), program.to_string());
+        assert_eq!(parse(&circuit.to_source()).unwrap(), program);
+    }
+}
+
+#[test]
+fn comments_stay_with_their_operations() {
+    let source = "// A lone qubit
+Maistow create oo qubit q with value 0 // starts at 0
+// Flip it
+Thy x shalt target q
+// Done
+Return 10 // shots
+// really done
+";
+    let program = parse(source).unwrap();
+    let circuit = Circuit::from_program(&program);
+    // Comments aren't operations
+    assert_eq!(circuit.nodes().len(), 2);
+    assert!(matches!(
+        circuit.ret.as_ref().unwrap().kind,
+        Stmt::Return { shots: 10 }
+    ));
+    assert_eq!(circuit.to_program(), program);
+    // An operation added later goes after the others, but before the comments at the end
+    let mut circuit = circuit;
+    circuit.push(parse("Thy h shalt target q").unwrap().stmts.remove(0));
+    assert_eq!(
+        circuit.to_source(),
+        "// A lone qubit
+Maistow create oo qubit q with value 0
+// starts at 0
+// Flip it
+Thy x shalt target q
+Thy h shalt target q
+// Done
+Return 10
+// shots
+// really done
+"
+    );
+}
+
+#[test]
+fn operations_connect_through_shared_wires() {
+    let circuit = Circuit::from_program(&parse(BELL).unwrap());
+    assert_eq!(
+        circuit.nodes()[4].wires,
+        [
+            Wire::Qubit("q".to_string(), 0),
+            Wire::Qubit("q".to_string(), 1),
+            Wire::CBit("c".to_string(), 0),
+            Wire::CBit("c".to_string(), 1),
+        ]
+    );
+    assert_eq!(circuit.predecessors(2), [0]);
+    assert_eq!(circuit.successors(2), [3]);
+    // The cnot and the measurement share two wires, but that's one dependency
+    assert_eq!(circuit.successors(3), [4]);
+    assert_eq!(circuit.predecessors(4), [1, 3]);
+    assert_eq!(circuit.edges().len(), 7);
+}
+
+#[test]
+fn layers_hold_operations_that_can_happen_at_once() {
+    let program = parse(
+        "Maistow create oo qreg q with value 0[3]
+Canstow create oo creg c with value b0[1]
+Thy h shalt target q[0]
+Thy x shalt target q[1]
+Thy cnot shalt target q[2] and control on q[0]
+Thy z shalt target q[1]
+Thy cz shalt target q[1] and control on q[2]
+Rede q[0] and quyken c
+",
+    )
+    .unwrap();
+    let circuit = Circuit::from_program(&program);
+    assert_eq!(
+        circuit.layers(),
+        [vec![0, 1], vec![2, 3], vec![4, 5], vec![6, 7]]
+    );
+    // Only gates count towards depth, so the declarations and the measurement
+    // don't add layers of their own
+    assert_eq!(depth(&program), 3);
+    // Every layer's operations act on disjoint wires
+    for layer in circuit.layers() {
+        let mut wires: Vec<&Wire> = layer
+            .iter()
+            .flat_map(|index| &circuit.nodes()[*index].wires)
+            .collect();
+        let count = wires.len();
+        wires.sort_by_key(|wire| format!("{:?}", wire));
+        wires.dedup();
+        assert_eq!(wires.len(), count, "{:?}", layer);
+    }
+}
+
+#[test]
+fn depth_counts_each_qubit_of_a_range() {
+    let program = parse(
+        "Maistow create oo qreg q with value 0[2]
+Thy x shalt target q[0]
+Thy h shalt target q[0..1]
+Thy x shalt target q[1]
+",
+    )
+    .unwrap();
+    // The h on q[1] doesn't wait for the x on q[0]
+    assert_eq!(depth(&program), 2);
+    assert_eq!(depth(&parse(BELL).unwrap()), 2);
+}
@@ -0,0 +1,244 @@
+//! Runs the quill binary the way a user would, checking its output and exit
+//! codes, and that bad input never makes it panic
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Runs quill with the given arguments, feeding `stdin` to it
+fn quill(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_quill"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Quill may exit before reading any of it, which breaks the pipe
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("panicked"), "quill panicked: {}", stderr);
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+const BELL: &str = "Maistow create oo qreg q with value 0[2]
+Canstow create oo creg c with value b0[2]
+Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Rede q and quyken c
+Return 200
+";
+
+#[test]
+fn run_prints_a_histogram() {
+    let output = quill(&["run", "--seed", "7"], BELL);
+    assert!(output.status.success());
+    let lines: Vec<String> = stdout(&output).lines().map(String::from).collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("c=00"));
+    assert!(lines[1].starts_with("c=11"));
+    let total: usize = lines
+        .iter()
+        .map(|line| {
+            line.split_whitespace()
+                .nth(1)
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+        })
+        .sum();
+    assert_eq!(total, 200);
+
+    let flipped = "Maistow create oo qubit q with value 1
+Maistow create oo cbit c with value b0
+Rede q and quyken c
+";
+    let output = quill(&["run", "--shots", "10", "-"], flipped);
+    assert!(stdout(&output).starts_with("c=1      10"));
+}
+
+#[test]
+fn build_emits_every_target() {
+    for (target, start) in [
+        ("qasm", "OPENQASM 2.0;"),
+        ("qasm3", "OPENQASM 3.0;"),
+        ("qir", "; ModuleID = 'quill'"),
+        ("qiskit", "from math import pi"),
+    ] {
+        let output = quill(&["build", "--target", target], BELL);
+        assert!(output.status.success(), "{} failed", target);
+        assert!(stdout(&output).starts_with(start), "{}", stdout(&output));
+        // What the optimizer did goes to stderr, out of the way of the output
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with("Optimization level O"), "{}", stderr);
+    }
+}
+
+#[test]
+fn fmt_and_ast_print_the_program() {
+    let output = quill(&["fmt"], BELL);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), BELL);
+    let output = quill(&["ast"], BELL);
+    assert!(stdout(&output).starts_with("Program\n"));
+}
+
+#[test]
+fn bad_programs_exit_with_1() {
+    let unparsable = "Maistow crate oo qubit q with value 0\n";
+    let ill_typed = "Maistow create oo qubit q with value 0\nThy h shalt target r\n";
+    for args in [&["check"][..], &["run"], &["build", "--target", "qasm"]] {
+        for source in [unparsable, ill_typed] {
+            let output = quill(args, source);
+            assert_eq!(output.status.code(), Some(1), "{:?} on {:?}", args, source);
+        }
+    }
+    let output = quill(&["check"], ill_typed);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: <stdin>:2: "), "{}", stderr);
+
+    // Too many qubits to simulate is reported before any memory is set aside for them
+    let output = quill(
+        &["run"],
+        "Canstow create oo qreg q with value 0[30]\nReturn 5\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("The program has 30 qubits, but only up to 24 can be simulated!"));
+}
+
+#[test]
+fn bad_command_lines_exit_with_2() {
+    for args in [
+        &["frobnicate"][..],
+        &["run", "--shots", "many"],
+        &["run", "--target", "qasm"],
+        &["build"],
+        &["build", "--target", "cirq"],
+        &["check", "/no/such/file.ql"],
+    ] {
+        assert_eq!(quill(args, BELL).status.code(), Some(2), "{:?}", args);
+    }
+    assert!(quill(&["help"], "").status.success());
+}
+
+#[test]
+fn repl_reads_statements_until_quit() {
+    let output = quill(
+        &["repl", "--seed", "1"],
+        "Maistow create oo qubit a with value 1
+Thy h shalt target b
+:state
+:quit
+Thy h shalt target a
+",
+    );
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "|a>\n|1>  +1.0000+0.0000i  (p=1.0000)\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: 2: "), "{}", stderr);
+    assert_eq!(quill(&["repl", "file.ql"], "").status.code(), Some(2));
+}
+
+#[test]
+fn fmt_check_fails_on_unformatted_programs() {
+    let output = quill(&["fmt", "--check"], BELL);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "");
+
+    let aliased = BELL.replace("cnot", "cx");
+    let output = quill(&["fmt", "--check"], &aliased);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: <stdin>:4: "), "{}", stderr);
+    assert!(quill(&["fmt", "--check", "--prefer", "cx"], &aliased)
+        .status
+        .success());
+    assert_eq!(
+        quill(&["fmt", "--prefer", "frob"], BELL).status.code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn translate_rewrites_between_dialects() {
+    let output = quill(&["translate", "--dialect", "modern"], BELL);
+    assert!(output.status.success());
+    let modern = stdout(&output);
+    assert!(modern.starts_with("// dialect: modern\nplease let qreg q = 0[2]\n"));
+    assert!(modern.contains("apply cnot to q[1] controlled by q[0]\n"));
+    assert!(quill(&["fmt", "--check"], &modern).status.success());
+    assert!(quill(&["run", "--seed", "7"], &modern).status.success());
+
+    let output = quill(&["translate", "--dialect", "middle-english"], &modern);
+    assert_eq!(
+        stdout(&output),
+        format!("// dialect: middle english\n{}", BELL)
+    );
+    // to is a name in Middle English, but a keyword in modern English
+    let output = quill(
+        &["translate", "--dialect", "modern"],
+        "Maistow create oo qubit to with value 0\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`to` is a keyword"), "{}", stderr);
+    assert_eq!(quill(&["translate"], BELL).status.code(), Some(2));
+    assert_eq!(
+        quill(&["translate", "--dialect", "klingon"], BELL)
+            .status
+            .code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn redeclared_registers_run_and_build() {
+    // q shrinks after a gate on q[3], so later statements only see two qubits
+    let source = "Maistow create oo qreg q with value 0[4]
+Canstow create oo creg c with value b0[2]
+Thy x shalt target q[3]
+Maistow create oo qreg q with value 1[2]
+Rede q and quyken c
+Return 5
+";
+    let output = quill(&["run"], source);
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("c=11"), "{}", stdout(&output));
+    for target in ["qasm", "qasm3", "qir", "qiskit"] {
+        let output = quill(&["build", "--target", target], source);
+        assert!(output.status.success(), "{} failed", target);
+    }
+}
+
+#[test]
+fn the_readme_example_runs_and_builds() {
+    let readme = include_str!("../README.md");
+    let example = readme
+        .split("## Examples")
+        .nth(1)
+        .and_then(|section| section.split("```").nth(1))
+        .unwrap();
+    let output = quill(&["run", "--seed", "7"], example);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let total: usize = stdout(&output)
+        .lines()
+        .map(|line| {
+            line.split_whitespace()
+                .nth(2)
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+        })
+        .sum();
+    assert_eq!(total, 1024);
+    let output = quill(&["build", "--target", "qir"], example);
+    assert!(output.status.success());
+}
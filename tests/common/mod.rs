@@ -0,0 +1,66 @@
+// Every test file compiles its own copy of this module, using only some of it
+#![allow(dead_code)]
+
+use num_complex::Complex64;
+use quill::ast::{Program, Stmt};
+use quill::gates::Matrix;
+use quill::optimizations::Pass;
+use quill::parser::parse;
+use quill::simulator::unitary;
+use quill::type_checker::type_check;
+
+/// The statements of a program, without their line numbers
+pub fn kinds(program: &Program) -> Vec<Stmt> {
+    program.stmts.iter().map(|stmt| stmt.kind.clone()).collect()
+}
+
+/// Whether the top left block of `actual` (the part where every ancilla, which
+/// are declared first, starts and ends as 0) equals `expected` up to a global phase
+pub fn equal_up_to_phase(expected: &Matrix, actual: &Matrix) -> bool {
+    let size = expected.len();
+    let (row, col) = (0..size * size)
+        .map(|i| (i / size, i % size))
+        .find(|&(row, col)| expected[row][col].norm() > 1e-6)
+        .unwrap();
+    let phase: Complex64 = actual[row][col] / expected[row][col];
+    (0..size).all(|row| {
+        (0..size).all(|col| (expected[row][col] * phase - actual[row][col]).norm() < 1e-6)
+    })
+}
+
+/// Runs `pass` over a register q of `qubits` qubits that has gone through the
+/// given gates, checking the unitary it applies stays the same (up to global
+/// phase). Returns the gates left over.
+pub fn run_pass(pass: &dyn Pass, qubits: usize, gates: &str) -> Vec<Stmt> {
+    let source = format!(
+        "Maistow create oo qreg q with value 0[{}]\n{}",
+        qubits, gates
+    );
+    let mut program = parse(&source).unwrap();
+    let symbols = type_check(&program).unwrap();
+    let expected = unitary(&program);
+    pass.run(&mut program, &symbols).unwrap();
+    assert!(
+        equal_up_to_phase(&expected, &unitary(&program)),
+        "{} changed what {} does",
+        pass.name(),
+        gates
+    );
+    program
+        .stmts
+        .into_iter()
+        .map(|stmt| stmt.kind)
+        .filter(|kind| matches!(kind, Stmt::Gate { .. }))
+        .collect()
+}
+
+/// The names of the gates `run_pass` left over
+pub fn gate_names(gates: &[Stmt]) -> Vec<String> {
+    gates
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Gate { gate, .. } => Some(gate.clone()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,151 @@
+//! Checks the rules deciding which statements commute, and the dependency DAG
+//! they build over a program
+use quill::ast::Program;
+use quill::optimizations::commutation::{commutes, CommutationDag};
+use quill::parser::parse;
+
+/// Parses the statements after declaring three qubits q[0..2] and two bits c
+fn program(stmts: &str) -> Program {
+    let source = format!(
+        "Maistow create oo qreg q with value 0[3]\nCanstow create oo creg c with value b0[2]\n{}",
+        stmts
+    );
+    parse(&source).unwrap()
+}
+
+/// Whether the two statements commute, checking it doesn't matter which comes first
+fn pair_commutes(first: &str, second: &str) -> bool {
+    let program = program(&format!("{}\n{}\n", first, second));
+    let (first, second) = (&program.stmts[2].kind, &program.stmts[3].kind);
+    assert_eq!(commutes(first, second), commutes(second, first));
+    commutes(first, second)
+}
+
+#[test]
+fn diagonal_gates_commute_through_controls() {
+    let cnot = "Thy cnot shalt target q[1] and control on q[0]";
+    for gate in [
+        "Thy z shalt target q[0]",
+        "Thy s shalt target q[0]",
+        "Thy tadj shalt target q[0]",
+        "Thy rz shalt target q[0] with [0.3]",
+        "Thy cz shalt target q[2] and control on q[0]",
+        "Thy mcz shalt target q[0] and control on q[2]",
+    ] {
+        assert!(pair_commutes(cnot, gate), "{}", gate);
+    }
+    // cz is diagonal on its target as well, so z commutes through both ends
+    assert!(pair_commutes(
+        "Thy cz shalt target q[1] and control on q[0]",
+        "Thy z shalt target q[1]"
+    ));
+    for gate in [
+        "Thy x shalt target q[0]",
+        "Thy h shalt target q[0]",
+        "Thy ry shalt target q[0] with [0.3]",
+        "Thy cnot shalt target q[0] and control on q[2]",
+    ] {
+        assert!(!pair_commutes(cnot, gate), "{}", gate);
+    }
+}
+
+#[test]
+fn x_type_gates_commute_through_targets() {
+    let cnot = "Thy cnot shalt target q[1] and control on q[0]";
+    for gate in [
+        "Thy x shalt target q[1]",
+        "Thy sx shalt target q[1]",
+        "Thy rx shalt target q[1] with [0.3]",
+        "Thy cnot shalt target q[1] and control on q[2]",
+        "Thy rxx shalt target q[1] and q[2] with [0.3]",
+        "Thy toffoli shalt target q[1] and control on q[0], q[2]",
+    ] {
+        assert!(pair_commutes(cnot, gate), "{}", gate);
+    }
+    for gate in [
+        "Thy z shalt target q[1]",
+        "Thy y shalt target q[1]",
+        "Thy h shalt target q[1]",
+        "Thy cz shalt target q[2] and control on q[1]",
+        "Thy toffoli shalt target q[2] and control on q[0], q[1]",
+        // swap and u3 aren't diagonal in any basis, so they commute with nothing they touch
+        "Thy swap shalt target q[1] and q[2]",
+        "Thy u3 shalt target q[1] with [0.4, 1.2, 0.5]",
+    ] {
+        assert!(!pair_commutes(cnot, gate), "{}", gate);
+    }
+    assert!(!pair_commutes(
+        "Thy swap shalt target q[1] and q[2]",
+        "Thy swap shalt target q[1] and q[2]"
+    ));
+}
+
+#[test]
+fn gates_on_disjoint_qubits_commute() {
+    for (first, second) in [
+        ("Thy h shalt target q[0]", "Thy h shalt target q[1]"),
+        (
+            "Thy swap shalt target q[0] and q[1]",
+            "Thy u3 shalt target q[2] with [0.4, 1.2, 0.5]",
+        ),
+        (
+            "Thy cnot shalt target q[1] and control on q[0]",
+            "Thy y shalt target q[2]",
+        ),
+        // Ranges only overlap when their indices do
+        ("Thy h shalt target q[0..1]", "Thy x shalt target q[2]"),
+    ] {
+        assert!(pair_commutes(first, second), "{} and {}", first, second);
+    }
+    assert!(!pair_commutes(
+        "Thy h shalt target q[0..1]",
+        "Thy x shalt target q[1]"
+    ));
+}
+
+#[test]
+fn other_statements_only_commute_with_what_they_dont_mention() {
+    assert!(!pair_commutes(
+        "Thy h shalt target q[0]",
+        "Rede q and quyken c"
+    ));
+    assert!(!pair_commutes(
+        "Rede q[0] and quyken c[0]",
+        "Rede q[1] and quyken c[1]"
+    ));
+    assert!(!pair_commutes("Thy h shalt target q[0]", "Return 10"));
+    assert!(pair_commutes(
+        "Thy h shalt target q[0]",
+        "// gates and comments are independent"
+    ));
+}
+
+#[test]
+fn dag_edges_follow_the_rules() {
+    let program = program(
+        "Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Thy rz shalt target q[0] with [0.3]
+Thy x shalt target q[1]
+Thy h shalt target q[2]
+Thy cz shalt target q[1] and control on q[2]
+",
+    );
+    let dag = CommutationDag::new(&program);
+    assert_eq!(dag.len(), program.stmts.len());
+    // The declarations of q come before every gate on it
+    for gate in 2..8 {
+        assert!(dag.predecessors(gate).contains(&0), "{}", gate);
+        assert!(!dag.predecessors(gate).contains(&1), "{}", gate);
+    }
+    // h q[0] is behind the cnot's control and the rz, but the rz commutes
+    // through the control, and the x through the target
+    assert_eq!(dag.successors(2), [3, 4]);
+    assert_eq!(dag.successors(3), [7]);
+    assert_eq!(dag.predecessors(4), [0, 2]);
+    assert_eq!(dag.predecessors(5), [0]);
+    assert_eq!(dag.predecessors(7), [0, 3, 5, 6]);
+    // h q[0] can move up to the cnot, which goes on past the x and the rz to the cz
+    assert_eq!(dag.partners(&program, 2), [3]);
+    assert_eq!(dag.partners(&program, 3), [4, 5, 7]);
+}
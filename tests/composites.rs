@@ -0,0 +1,112 @@
+//! Checks that the built-in composites do what they say, by simulating the
+//! gates they expand into
+use num_complex::Complex64;
+use quill::modules::link;
+use quill::parser::parse;
+use quill::simulator::StateVector;
+use quill::type_checker::type_check;
+
+/// The state a program leaves its qubits in, once linked and checked
+fn state(source: &str) -> StateVector {
+    let program = link(&parse(source).unwrap(), "<stdin>").unwrap();
+    type_check(&program).unwrap();
+    let mut state = StateVector::new(&program);
+    state.apply_gates(&program);
+    state
+}
+
+/// The basis state a program ends up in for certain
+fn outcome(source: &str) -> usize {
+    let state = state(source);
+    let amplitudes = state.amplitudes();
+    let index = (0..amplitudes.len())
+        .find(|&i| amplitudes[i].norm_sqr() > 0.5)
+        .unwrap();
+    assert!((amplitudes[index].norm_sqr() - 1.0).abs() < 1e-9);
+    index
+}
+
+fn error(source: &str) -> String {
+    link(&parse(source).unwrap(), "<stdin>").unwrap_err()
+}
+
+#[test]
+fn qft_and_iqft_are_the_fourier_transform_and_back() {
+    let source = "Canstow create oo qreg q with value 0[4]
+Thy x shalt target q[3]
+Thy qft shalt target q[1..3]
+";
+    // q[0] isn't part of it, and |1> goes to the sum of e^(2 PI i k / 8) |k> / sqrt(8)
+    let state = state(source);
+    let amplitudes = state.amplitudes();
+    let phase = amplitudes[0] * 8f64.sqrt();
+    for (k, amplitude) in amplitudes.iter().enumerate() {
+        let expected = match k < 8 {
+            true => {
+                Complex64::from_polar(
+                    1.0 / 8f64.sqrt(),
+                    2.0 * std::f64::consts::PI * k as f64 / 8.0,
+                ) * phase
+            }
+            false => Complex64::new(0.0, 0.0),
+        };
+        assert!((amplitude - expected).norm() < 1e-9, "{}: {}", k, amplitude);
+    }
+    assert_eq!(
+        outcome(&format!("{}Thy iqft shalt target q[1..3]\n", source)),
+        0b0001
+    );
+}
+
+#[test]
+fn ghz_grover_and_phase_estimation() {
+    let ghz = state("Canstow create oo qreg q with value 0[3]\nThy ghz shalt target q\n");
+    let half = std::f64::consts::FRAC_1_SQRT_2;
+    assert!((ghz.amplitudes()[0] - half).norm() < 1e-9);
+    assert!((ghz.amplitudes()[7] - half).norm() < 1e-9);
+
+    // One round of Grover's search over two qubits finds the marked |11>
+    let grover = "Canstow create oo qreg q with value 0[2]
+Thy h shalt target q[0..1]
+Thy cz shalt target q[1] and control on q[0]
+Thy diffuse shalt target q
+";
+    assert_eq!(outcome(grover), 0b11);
+
+    // A phase of 2 PI * 3 / 8 reads as 3 out of 8
+    let qpe = "Canstow create oo qreg c with value 0[3]
+Canstow create oo qubit e with value 0
+Thy x shalt target e
+Thy qpe shalt target c and e with [PI[3, 4]]
+";
+    assert_eq!(outcome(qpe) >> 1, 3);
+}
+
+#[test]
+fn adders_add_and_subtract_registers() {
+    // a = 3 and b = 6, declared in that order, so a is the top three bits
+    let setup = "Canstow create oo qreg a with value 0[3]
+Canstow create oo qreg b with value 0[3]
+Thy x shalt target a[1..2]
+Thy x shalt target b[0..1]
+";
+    assert_eq!(
+        outcome(&format!("{}Thy add shalt target a and b\n", setup)),
+        3 << 3 | 1
+    );
+    assert_eq!(
+        outcome(&format!("{}Thy sub shalt target a and b\n", setup)),
+        3 << 3 | 3
+    );
+
+    assert!(
+        error(&format!("{}Thy add shalt target a and b[0..1]\n", setup))
+            .ends_with("5: registers of the same size can be added, but they have 3 and 2 qubits!")
+    );
+    assert!(error(&format!("{}Thy add shalt target a\n", setup))
+        .ends_with("5: add takes 2 target(s), but 1 were given!"));
+    assert!(error(
+        "Canstow create oo qreg q with value 0[2]\nThy qpe shalt target q[0] and q[1]\n"
+    )
+    .ends_with("2: qpe takes 1 parameter(s), but 0 were given!"));
+}
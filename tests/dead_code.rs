@@ -0,0 +1,82 @@
+//! Checks that dead code elimination removes exactly the gates (and unused
+//! declarations) that can't affect a measurement
+use quill::optimizations::dead_code::EliminateDeadCode;
+use quill::optimizations::Pass;
+use quill::parser::parse;
+use quill::type_checker::type_check;
+
+/// The statements left after the pass, and the notes it made
+fn eliminate(source: &str, remove_declarations: bool) -> (Vec<String>, Vec<String>) {
+    let mut program = parse(source).unwrap();
+    let symbols = type_check(&program).unwrap();
+    let notes = EliminateDeadCode {
+        remove_declarations,
+    }
+    .run(&mut program, &symbols)
+    .unwrap();
+    let stmts = program
+        .stmts
+        .iter()
+        .map(|stmt| stmt.kind.to_string())
+        .collect();
+    (stmts, notes)
+}
+
+const SOURCE: &str = "Maistow create oo qreg q with value 0[3]
+Canstow create oo qubit spare with value 0
+Canstow create oo creg c with value b0[2]
+Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Thy x shalt target q[2]
+Thy h shalt target spare
+Rede q[0..1] and quyken c
+Return 10
+";
+
+#[test]
+fn gates_that_reach_no_measurement_are_removed() {
+    let (stmts, notes) = eliminate(SOURCE, true);
+    // q[0] reaches the measurement through the cnot, but q[2] and spare don't
+    assert!(stmts
+        .iter()
+        .any(|stmt| stmt.contains("Thy h shalt target q[0]")));
+    assert!(stmts.iter().any(|stmt| stmt.contains("Thy cnot")));
+    assert!(!stmts.iter().any(|stmt| stmt.contains("target q[2]")));
+    assert!(!stmts.iter().any(|stmt| stmt.contains("spare")));
+    assert_eq!(
+        notes,
+        [
+            "2: removed \"Canstow create oo qubit spare with value 0\"",
+            "6: removed \"Thy x shalt target q[2]\"",
+            "7: removed \"Thy h shalt target spare\"",
+        ]
+    );
+    // q is still used, so its declaration stays even though q[2] isn't
+    assert!(stmts.iter().any(|stmt| stmt.contains("qreg q")));
+}
+
+#[test]
+fn declarations_can_be_kept() {
+    let (stmts, notes) = eliminate(SOURCE, false);
+    assert!(stmts.iter().any(|stmt| stmt.contains("qubit spare")));
+    assert_eq!(notes.len(), 2);
+}
+
+#[test]
+fn redeclared_registers_use_the_width_of_each_declaration() {
+    // The gate on q[3] comes before q shrinks to two qubits, and measuring
+    // all of q afterwards only reads the two
+    let source = "Maistow create oo qreg q with value 0[4]
+Canstow create oo creg c with value b0[2]
+Thy x shalt target q[3]
+Maistow create oo qreg q with value 0[2]
+Thy h shalt target q[1]
+Rede q and quyken c
+Return 10
+";
+    let (stmts, notes) = eliminate(source, true);
+    assert_eq!(notes, ["3: removed \"Thy x shalt target q[3]\""]);
+    assert!(stmts
+        .iter()
+        .any(|stmt| stmt.contains("Thy h shalt target q[1]")));
+}
@@ -0,0 +1,162 @@
+//! Checks that decomposing multi-controlled gates preserves the unitary the
+//! program applies, as computed by the simulator
+mod common;
+
+use common::equal_up_to_phase;
+use quill::ast::{RespectExpr, Stmt};
+use quill::formatter::{format, FormatConfig};
+use quill::optimizations::decomposition::{BasisSet, DecomposeMultiControlled};
+use quill::optimizations::{earned_level, OptLevel, Pass};
+use quill::parser::{parse, Dialect};
+use quill::simulator::unitary;
+use quill::type_checker::type_check;
+
+fn check(gate: &str, controls: usize, basis: BasisSet, ancillas: bool) {
+    let control_list: Vec<String> = (0..controls).map(|i| format!("q[{}]", i)).collect();
+    let source = format!(
+        "Maistow create oo qreg q with value 0[{}]\n\
+         Thy h shalt target q[0..{}]\n\
+         Thy {} shalt target q[{}] and control on {}\n\
+         Thy h shalt target q[{}]\n",
+        controls + 1,
+        controls,
+        gate,
+        controls,
+        control_list.join(", "),
+        controls
+    );
+    let mut program = parse(&source).unwrap();
+    let symbols = type_check(&program).unwrap();
+    let expected = unitary(&program);
+
+    let pass = DecomposeMultiControlled { basis, ancillas };
+    pass.run(&mut program, &symbols).unwrap();
+    for stmt in &program.stmts {
+        if let Stmt::Gate { gate, .. } = &stmt.kind {
+            assert!(
+                gate == "h" || basis.gates().contains(&gate.as_str()),
+                "{} is not in {:?}",
+                gate,
+                basis
+            );
+        }
+    }
+    assert!(
+        equal_up_to_phase(&expected, &unitary(&program)),
+        "{} with {} controls changed in {:?} (ancillas: {})",
+        gate,
+        controls,
+        basis,
+        ancillas
+    );
+}
+
+#[test]
+fn toffoli() {
+    for basis in [BasisSet::U3Cnot, BasisSet::CliffordT] {
+        check("toffoli", 2, basis, false);
+        check("ccx", 2, basis, true);
+    }
+}
+
+#[test]
+fn multi_controlled_without_ancillas() {
+    for gate in ["mcx", "mcy", "mcz"] {
+        for controls in 1..=4 {
+            check(gate, controls, BasisSet::U3Cnot, false);
+        }
+    }
+}
+
+#[test]
+fn multi_controlled_with_ancillas() {
+    for gate in ["mcx", "mcy", "mcz"] {
+        for controls in 1..=4 {
+            check(gate, controls, BasisSet::U3Cnot, true);
+            check(gate, controls, BasisSet::CliffordT, true);
+        }
+    }
+}
+
+#[test]
+fn clifford_t_falls_back_to_ancillas() {
+    let mut program = parse(
+        "Maistow create oo qreg q with value 0[4]\n\
+         Thy mcx shalt target q[3] and control on q[0], q[1], q[2]\n",
+    )
+    .unwrap();
+    let symbols = type_check(&program).unwrap();
+    let pass = DecomposeMultiControlled {
+        basis: BasisSet::CliffordT,
+        ancillas: false,
+    };
+    let notes = pass.run(&mut program, &symbols).unwrap();
+    assert_eq!(notes.len(), 1);
+    assert!(matches!(&program.stmts[0].kind, Stmt::Assign { name, .. } if name == "ancilla"));
+}
+
+#[test]
+fn ancillas_are_declared_on_the_line_that_needs_them() {
+    let mut program = parse(
+        "Maistow create oo qreg q with value 0[4]
+Canstow create oo qreg r with value 0[5]
+Thy toffoli shalt target q[2] and control on q[0], q[1]
+Thy mcz shalt target q[3] and control on q[0], q[1], q[2]
+Thy mcx shalt target r[4] and control on r[0], r[1], r[2], r[3]
+",
+    )
+    .unwrap();
+    let symbols = type_check(&program).unwrap();
+    // Half the declarations are Maistow, which is just enough for O1
+    assert_eq!(earned_level(&program), OptLevel::O1);
+    let pass = DecomposeMultiControlled {
+        basis: BasisSet::U3Cnot,
+        ancillas: true,
+    };
+    pass.run(&mut program, &symbols).unwrap();
+    let ancilla = &program.stmts[0];
+    assert_eq!(ancilla.line_no, 4);
+    assert!(matches!(
+        &ancilla.kind,
+        Stmt::Assign { respect: RespectExpr::Maistow, name, .. } if name == "ancilla"
+    ));
+    assert!(program.is_generated(0));
+    assert_eq!(program.stmts[1].line_no, 4);
+    // It doesn't make the program any more respectful
+    assert_eq!(earned_level(&program), OptLevel::O1);
+}
+
+#[test]
+fn ancillas_stay_generated_when_written_out() {
+    let mut program = parse(
+        "Maistow create oo qreg q with value 0[4]
+Canstow create oo qubit r with value 0
+Thy mcx shalt target q[3] and control on q[0], q[1], q[2]
+",
+    )
+    .unwrap();
+    let symbols = type_check(&program).unwrap();
+    let pass = DecomposeMultiControlled {
+        basis: BasisSet::CliffordT,
+        ancillas: true,
+    };
+    pass.run(&mut program, &symbols).unwrap();
+    let mut modern = FormatConfig::default();
+    modern.dialect = Dialect::Modern;
+    for source in [
+        program.to_string(),
+        format(&program, &FormatConfig::default()),
+        format(&program, &modern),
+    ] {
+        let reparsed = parse(&source).unwrap();
+        // The modern dialect's header comes before it
+        let ancilla = reparsed
+            .stmts
+            .iter()
+            .position(|stmt| matches!(&stmt.kind, Stmt::Assign { name, .. } if name == "ancilla"))
+            .unwrap();
+        assert!(reparsed.is_generated(ancilla), "{}", source);
+        // Counting the ancilla would make it two Maistows out of three, for O2
+        assert_eq!(earned_level(&reparsed), OptLevel::O1, "{}", source);
+    }
+}
@@ -0,0 +1,135 @@
+//! Checks that programs in modern English mean the same as in Middle English,
+//! and that the formatter translates between them
+mod common;
+
+use common::kinds;
+use quill::ast::{RespectExpr, Stmt};
+use quill::formatter::{format, FormatConfig};
+use quill::parser::{dialect_of, parse, parse_statement, Dialect};
+
+const MIDDLE_ENGLISH: &str = "Maistow create oo qreg q with value 0[2] + 1[1]
+Canstow create oo creg c with value b0[3]
+Thy h shalt target q[0] // superpose
+Thy cnot shalt target q[1] and control on q[0]
+Thy toffoli shalt target q[2] and control on q[0], q[1]
+
+Thy rx shalt target q[1..2] with [-0.5, PI[1, 2]]
+Thy swap shalt target q[0] and q[2]
+Rede q and quyken c
+Return 10
+";
+
+const MODERN: &str = "// dialect: modern
+please let qreg q = 0[2] + 1[1]
+let creg c = b0[3]
+apply h to q[0] // superpose
+apply cnot to q[1] controlled by q[0]
+apply toffoli to q[2] controlled by q[0], q[1]
+
+apply rx to q[1..2] with [-0.5, PI[1, 2]]
+apply swap to q[0] and q[2]
+measure q into c
+return 10
+";
+
+fn config(dialect: Dialect) -> FormatConfig {
+    let mut config = FormatConfig::default();
+    config.dialect = dialect;
+    config
+}
+
+#[test]
+fn both_dialects_mean_the_same() {
+    assert_eq!(dialect_of(MODERN), Dialect::Modern);
+    assert_eq!(dialect_of(MIDDLE_ENGLISH), Dialect::MiddleEnglish);
+    let modern = parse(MODERN).unwrap();
+    // Past the header, which is a comment like any other
+    assert_eq!(kinds(&modern)[1..], kinds(&parse(MIDDLE_ENGLISH).unwrap()));
+
+    // "please" is what makes a declaration respectful
+    let respect = |line: &str| match parse_statement(line, 1, Dialect::Modern).unwrap().kind {
+        Stmt::Assign { respect, .. } => respect,
+        kind => panic!("{:?} isn't an assignment", kind),
+    };
+    assert_eq!(respect("Please LET qubit q = +"), RespectExpr::Maistow);
+    assert_eq!(respect("let qubit q=+"), RespectExpr::Canstow);
+}
+
+#[test]
+fn the_formatter_translates_between_dialects() {
+    let middle = parse(MIDDLE_ENGLISH).unwrap();
+    assert_eq!(format(&middle, &config(Dialect::Modern)), MODERN);
+    // Going back rewrites the header rather than dropping it
+    let translated = format(&parse(MODERN).unwrap(), &config(Dialect::MiddleEnglish));
+    assert_eq!(
+        translated,
+        format!("// dialect: middle english\n{}", MIDDLE_ENGLISH)
+    );
+    assert_eq!(kinds(&parse(&translated).unwrap())[1..], kinds(&middle));
+}
+
+#[test]
+fn each_file_speaks_one_dialect() {
+    let message = |source: &str| {
+        parse(source).unwrap_err().errors[0]
+            .variant
+            .message()
+            .to_string()
+    };
+    assert_eq!(
+        message("apply h to q\n"),
+        "`apply` is modern English, which needs `// dialect: modern` as the first line"
+    );
+    assert_eq!(
+        message("// dialect: modern\nThy h shalt target q\n"),
+        "`Thy` is Middle English, but this file is in modern English"
+    );
+    assert_eq!(
+        message("// dialect: klingon\n"),
+        "\"klingon\" isn't a dialect, try `modern` or `middle english`"
+    );
+    assert_eq!(
+        message("// dialect: modern\napply cnot to q controled by r\n"),
+        "did you mean `controlled by`?"
+    );
+    // Only the first line can be a header
+    assert!(parse("\n// dialect: modern\nlet qubit q = 0\n").is_err());
+    // Middle English keywords are reserved in modern English too
+    assert_eq!(
+        message("// dialect: modern\nlet qubit rede = 0\n"),
+        "`rede` is a keyword, so it can't be a variable's name"
+    );
+    for line in [
+        "let qubit to = 0",
+        "apply h to to",
+        "apply cnot to q controlled by into",
+        "measure q into by",
+        "apply rx to q with [let]",
+        "const end = 0.5",
+        "gate gate on a",
+    ] {
+        assert!(
+            message(&format!("// dialect: modern\n{}\n", line)).contains("is a keyword"),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn modern_keywords_are_only_reserved_in_modern_english() {
+    let program = parse(
+        "Maistow create oo qreg to with value 0[2]
+Canstow create oo creg value2 with value b0[2]
+Maistow create oo qubit into with value 1
+Certes by with value PI[1, 2]
+Thy rx shalt target to[0] with [by]
+Thy cnot shalt target to[1] and control on into
+Rede to and quyken value2
+",
+    )
+    .unwrap();
+    assert_eq!(program.stmts.len(), 7);
+    // value is a Middle English keyword, so it's still reserved
+    assert!(parse("Maistow create oo qubit value with value 0\n").is_err());
+}
@@ -0,0 +1,87 @@
+//! Checks that formatting keeps every statement and comment, lays them out
+//! the same way every time, and writes gates under the configured names
+use quill::ast::Stmt;
+use quill::formatter::{first_difference, format, FormatConfig};
+use quill::parser::parse;
+
+const MESSY: &str = "// Bell pair, the old way   
+
+
+Maistow create oo qreg q with value 0[2]
+Canstow create oo creg c with value b0[2]
+//   entangle
+Thy h shalt target q[0]
+Thy cx shalt target q[1] and control on q[0]
+
+
+Thy ccx shalt target q[0] and control on q[1], q[0]
+Thy rx shalt target q[1] with [3.0]
+Rede q and quyken c
+Return 10
+";
+
+const CANONICAL: &str = "// Bell pair, the old way
+
+Maistow create oo qreg q with value 0[2]
+Canstow create oo creg c with value b0[2]
+//   entangle
+Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+
+Thy toffoli shalt target q[0] and control on q[1], q[0]
+Thy rx shalt target q[1] with [3.0]
+Rede q and quyken c
+Return 10
+";
+
+#[test]
+fn comments_keep_their_text() {
+    let program = parse(MESSY).unwrap();
+    assert_eq!(
+        program.stmts[0].kind,
+        Stmt::Comment(" Bell pair, the old way   ".to_string())
+    );
+    assert_eq!(
+        program.stmts[3].kind,
+        Stmt::Comment("   entangle".to_string())
+    );
+}
+
+#[test]
+fn formats_canonically_and_idempotently() {
+    let config = FormatConfig::default();
+    let formatted = format(&parse(MESSY).unwrap(), &config);
+    assert_eq!(formatted, CANONICAL);
+    assert_eq!(format(&parse(CANONICAL).unwrap(), &config), CANONICAL);
+    assert_eq!(first_difference(CANONICAL, &formatted), None);
+    assert_eq!(
+        first_difference(MESSY, &formatted),
+        Some((1, Some("// Bell pair, the old way".to_string())))
+    );
+    // A missing last line is reported past the end of the source
+    let (head, _) = CANONICAL.rsplit_once("Return").unwrap();
+    assert_eq!(
+        first_difference(head, CANONICAL),
+        Some((12, Some("Return 10".to_string())))
+    );
+}
+
+#[test]
+fn aliases_follow_the_config() {
+    let config = FormatConfig::new(&["cx", "ccx"]).unwrap();
+    let formatted = format(&parse(MESSY).unwrap(), &config);
+    assert!(formatted.contains("Thy cx shalt target q[1]"));
+    assert!(formatted.contains("Thy ccx shalt target q[0]"));
+    assert_eq!(
+        formatted
+            .replace(" cx ", " cnot ")
+            .replace(" ccx ", " toffoli "),
+        CANONICAL
+    );
+    // Preferring a registry name is the same as the default
+    let config = FormatConfig::new(&["cnot"]).unwrap();
+    assert_eq!(format(&parse(MESSY).unwrap(), &config), CANONICAL);
+
+    assert!(FormatConfig::new(&["cx", "cnot"]).is_err());
+    assert!(FormatConfig::new(&["frob"]).is_err());
+}
@@ -0,0 +1,160 @@
+//! Checks that the grammar doesn't care about spacing or the case of keywords,
+//! while still keeping keywords and numbers whole
+mod common;
+
+use common::kinds;
+use quill::ast::{GateExpr, Param, Stmt, Target};
+use quill::formatter::{format, FormatConfig};
+use quill::parser::parse;
+use quill::type_checker::type_check;
+
+const CANONICAL: &str = "Maistow create oo qreg q with value 0[2] + 1[1]
+Canstow create oo creg c with value b0[3]
+Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Thy toffoli shalt target q[2] and control on q[0], q[1]
+Thy rx shalt target q[1..2] with [-0.5, PI[1, 2]]
+Thy swap shalt target q[0] and q[2]
+Rede q and quyken c
+Return 10
+";
+
+#[test]
+fn spacing_and_case_dont_matter() {
+    let messy = "  MAISTOW   create\too qreg  q with VALUE 0 [2]+1[ 1 ]
+canstow create oo CREG c with value b0[3]\t
+\tthy H SHALT target q [0]
+Thy CNOT shalt target q[1]   and control On q[0]
+Thy Toffoli shalt target q[2] and control on q[0],q[1]
+Thy rx shalt target q[1 .. 2] with [ -0.5 , pi[1,2] ]
+Thy swap shalt target q[0] AND q[2]
+rede q and QUYKEN c
+RETURN 10";
+    let expected = parse(CANONICAL).unwrap();
+    let program = parse(messy).unwrap();
+    assert_eq!(kinds(&program), kinds(&expected));
+    assert_eq!(format(&program, &FormatConfig::default()), CANONICAL);
+}
+
+#[test]
+fn gate_names_are_case_insensitive_like_keywords() {
+    let source = "Maistow create oo qubit q with value 0
+Maistow create oo qreg r with value 0[3]
+Thy H shalt target q
+Thy CX shalt target r[1] and control on r[0]
+";
+    type_check(&parse(source).unwrap()).unwrap();
+    // They're kept in lower case, aliases and composites included
+    let program = parse(&format!("{}Thy QFT shalt target r", source)).unwrap();
+    let gates: Vec<&str> = program.stmts[2..]
+        .iter()
+        .map(|stmt| match &stmt.kind {
+            Stmt::Gate { gate, .. } | Stmt::Call { gate, .. } => gate.as_str(),
+            other => panic!("{:?} isn't a gate", other),
+        })
+        .collect();
+    assert_eq!(gates, ["h", "cx", "qft"]);
+    assert!(matches!(program.stmts[4].kind, Stmt::Call { .. }));
+}
+
+#[test]
+fn blank_lines_and_comments_go_anywhere() {
+    let source = "\n  \n// Setup\nMaistow create oo qubit q with value 0 // starts at 0\n\t\n\nThy h shalt target q//no space\r\nReturn 5 // the end\n// really\n";
+    let program = parse(source).unwrap();
+    let lines: Vec<(i32, &Stmt)> = program
+        .stmts
+        .iter()
+        .map(|stmt| (stmt.line_no, &stmt.kind))
+        .collect();
+    assert_eq!(lines.len(), 8);
+    assert_eq!(lines[2], (4, &Stmt::Comment(" starts at 0".to_string())));
+    assert_eq!(lines[4], (7, &Stmt::Comment("no space".to_string())));
+    assert_eq!(lines[7], (9, &Stmt::Comment(" really".to_string())));
+    // Comments after the Return are fine
+    type_check(&program).unwrap();
+    assert_eq!(
+        format(&program, &FormatConfig::default()),
+        "// Setup
+Maistow create oo qubit q with value 0 // starts at 0
+
+Thy h shalt target q //no space
+Return 5 // the end
+// really
+"
+    );
+    assert!(parse("").unwrap().stmts.is_empty());
+    assert!(parse("// just a comment").unwrap().stmts.len() == 1);
+}
+
+#[test]
+fn keywords_and_numbers_stay_whole() {
+    let declared = "Maistow create oo qreg q with value 0[2]\n";
+    for bad in [
+        "Thyh shalt target q[0]",
+        "Thy h shalttarget q[0]",
+        "Thy h shalt target q[0] andcontrol on q[1]",
+        "Rede q and quyken c Return 5",
+        "Return 1 0",
+        "Thy rx shalt target q[0] with [1 .5]",
+        "Thy h shalt target q[0]\nThy h shalt target q[1] Thy h shalt target q[0]",
+    ] {
+        assert!(
+            parse(&format!("{}{}", declared, bad)).is_err(),
+            "{:?} parsed",
+            bad
+        );
+    }
+    // Variables can't be keywords, but can start with one
+    assert!(parse("Maistow create oo qubit with with value 0").is_err());
+    assert!(parse("Maistow create oo qubit On with value 0").is_err());
+    let program = parse(
+        "Maistow create oo qubit ontology with value 0\nMaistow create oo qubit thyme with value 1",
+    )
+    .unwrap();
+    assert_eq!(program.stmts.len(), 2);
+}
+
+#[test]
+fn two_qubit_gates_name_both_targets() {
+    let qubit = |index| Target::Slice {
+        name: "q".to_string(),
+        start: index,
+        end: index,
+    };
+    let program = parse(
+        "Maistow create oo qreg q with value 0[2]
+Thy swap shalt target q[0] and q[1]
+Thy swap shalt target q[1] and q[0]
+Thy rxx shalt target q[0] and q[1] with [PI[1, 2]]
+",
+    )
+    .unwrap();
+    assert_eq!(
+        kinds(&program)[1..],
+        [
+            Stmt::Gate {
+                gate: "swap".to_string(),
+                kind: GateExpr::Q2Gate,
+                targets: vec![qubit(0), qubit(1)],
+                controls: vec![],
+                params: vec![],
+            },
+            // swap is symmetric, but the targets stay in the order they were written
+            Stmt::Gate {
+                gate: "swap".to_string(),
+                kind: GateExpr::Q2Gate,
+                targets: vec![qubit(1), qubit(0)],
+                controls: vec![],
+                params: vec![],
+            },
+            Stmt::Gate {
+                gate: "rxx".to_string(),
+                kind: GateExpr::Q2ParamGate,
+                targets: vec![qubit(0), qubit(1)],
+                controls: vec![],
+                params: vec![Param::PI(1, 2)],
+            },
+        ]
+    );
+    type_check(&program).unwrap();
+}
@@ -0,0 +1,90 @@
+//! Checks the warnings linearity analysis gives for qubits used after they
+//! were measured, thrown away, or never touched at all
+use quill::linearity::check_linearity;
+use quill::parser::parse;
+use quill::type_checker::type_check;
+
+/// The warnings for the given program, as they're printed
+fn warnings(source: &str) -> Vec<String> {
+    let program = parse(source).unwrap();
+    let symbols = type_check(&program).unwrap();
+    check_linearity(&program, &symbols)
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[test]
+fn careful_programs_have_no_warnings() {
+    assert!(warnings(
+        "Maistow create oo qreg q with value 0[2]
+Canstow create oo creg c with value b0[2]
+Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Rede q and quyken c
+// Reinitializing a measured qubit makes it fresh again
+Maistow create oo qreg q with value 0[2]
+Thy x shalt target q[0..1]
+Rede q and quyken c
+"
+    )
+    .is_empty());
+}
+
+#[test]
+fn gates_after_measurement_warn() {
+    assert_eq!(
+        warnings(
+            "Maistow create oo qreg q with value 0[2]
+Canstow create oo creg c with value b0[2]
+Rede q and quyken c
+Thy h shalt target q[1]
+Thy cnot shalt target q[1] and control on q[0]
+"
+        ),
+        // The h already put q[1] back in use, so the cnot only warns about q[0]
+        [
+            "4: warning: h is applied to q[1] after it was measured, without reinitializing it first",
+            "5: warning: cnot is applied to q[0] after it was measured, without reinitializing it first",
+        ]
+    );
+}
+
+#[test]
+fn reinitializing_qubits_in_use_warns() {
+    assert_eq!(
+        warnings(
+            "Maistow create oo qreg q with value 0[3]
+Canstow create oo creg c with value b0[3]
+Thy h shalt target q[0]
+Thy x shalt target q[2]
+Maistow create oo qreg q with value 0[3]
+Thy h shalt target q[0..2]
+Rede q and quyken c
+"
+        ),
+        ["5: warning: q[0, 2] is reinitialized while they are still in use, so their state is discarded without ever being measured"]
+    );
+}
+
+#[test]
+fn unused_qubits_and_unwritten_bits_warn() {
+    assert_eq!(
+        warnings(
+            "Maistow create oo qubit a with value 0
+Canstow create oo qreg q with value 0[3]
+Maistow create oo creg c with value b0[2]
+Canstow create oo cbit b with value b0
+Thy h shalt target q[1]
+Rede q[1] and quyken c[0]
+"
+        ),
+        [
+            "1: warning: a is never used by any gate or measurement",
+            "2: warning: q[0, 2] is never used by any gate or measurement",
+            "3: warning: c[1] is never written to by a measurement",
+            "4: warning: b is never written to by a measurement",
+        ]
+    );
+}
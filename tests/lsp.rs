@@ -0,0 +1,226 @@
+//! Checks what the language server answers, both through the analysis it's
+//! built on and over the wire
+use quill::json::Json;
+use quill::lsp::analysis::*;
+use quill::lsp::serve;
+
+const SOURCE: &str = "Maistow create oo qreg q with value 0[3]
+Canstow create oo qubit anc with value +
+Canstow create oo creg c with value b0[3]
+// Entangle them
+Thy h shalt target q[0]
+Thy cnot shalt target anc and control on q[0]
+Thy rz shalt target q[1] with [-0.5]
+Rede q and quyken c
+";
+
+#[test]
+fn diagnostics_point_at_the_problem() {
+    let lines: Vec<&str> = SOURCE.lines().collect();
+    let ill_typed = format!("{}Thy h shalt target r\n", lines[..3].join("\n") + "\n");
+    let found = diagnostics(&ill_typed);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].severity, Severity::Error);
+    assert_eq!(
+        found[0].span,
+        Span {
+            line: 3,
+            start: 0,
+            end: 20
+        }
+    );
+    assert!(found[0].message.contains("\"r\""), "{}", found[0].message);
+
+    let found = diagnostics("Maistow create oo qubit q with value 0\nThy frob shalt target q\n");
+    assert_eq!(
+        found[0].span,
+        Span {
+            line: 1,
+            start: 4,
+            end: 8
+        }
+    );
+    assert!(found[0].message.contains("frob"));
+
+    assert!(diagnostics(SOURCE).is_empty());
+    // A qubit that's never used is only a warning
+    let unused = format!("{}Canstow create oo qubit spare with value 0\n", SOURCE);
+    let found = diagnostics(&unused);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].severity, Severity::Warning);
+    assert_eq!(found[0].span.line, 8);
+}
+
+#[test]
+fn hover_and_definition() {
+    let hovered = hover(SOURCE, 5, 23).unwrap();
+    assert_eq!(hovered, "`anc`: qubit (declared on line 2 with Canstow)");
+    assert!(hover(SOURCE, 7, 5)
+        .unwrap()
+        .starts_with("`q`: qreg of 3 qubits"));
+    assert!(hover(SOURCE, 5, 5).unwrap().contains("1 control"));
+    assert!(hover(SOURCE, 0, 2).unwrap().contains("may thou"));
+    assert_eq!(hover(SOURCE, 3, 5), None);
+
+    assert_eq!(
+        definition(SOURCE, 7, 18),
+        Some(Span {
+            line: 2,
+            start: 23,
+            end: 24
+        })
+    );
+    // Redeclaring a variable moves its definition
+    let redeclared = format!(
+        "{}Maistow create oo qubit anc with value 0\nThy x shalt target anc\n",
+        SOURCE
+    );
+    assert_eq!(definition(&redeclared, 9, 19).unwrap().line, 8);
+    assert_eq!(definition(&redeclared, 5, 22).unwrap().line, 1);
+    assert_eq!(definition(SOURCE, 4, 1), None);
+}
+
+#[test]
+fn completions_depend_on_where_they_are() {
+    let labels = |line: &str, character: usize| -> Vec<String> {
+        completions(line, 0, character)
+            .into_iter()
+            .map(|completion| completion.label)
+            .collect()
+    };
+    assert!(labels("Ma", 2).contains(&"Maistow".to_string()));
+    let gates = labels("Thy c", 5);
+    assert!(gates.contains(&"cnot".to_string()));
+    assert!(gates.contains(&"toffoli".to_string()));
+    assert_eq!(labels("Canstow create oo ", 18), TYPES);
+    let variables = completions(SOURCE, 7, 5);
+    assert_eq!(variables.len(), 3);
+    assert!(variables
+        .iter()
+        .all(|completion| completion.kind == CompletionKind::Variable));
+}
+
+#[test]
+fn semantic_tokens_classify_every_word() {
+    let kinds = |line: &str| -> Vec<(usize, usize, TokenKind)> {
+        semantic_tokens(line)
+            .into_iter()
+            .map(|token| (token.span.start, token.span.end, token.kind))
+            .collect()
+    };
+    use TokenKind::*;
+    assert_eq!(
+        kinds("Thy rz shalt target q[1] with [-0.5] // turn"),
+        [
+            (0, 3, Keyword),
+            (4, 6, Gate),
+            (7, 12, Keyword),
+            (13, 19, Keyword),
+            (20, 21, Variable),
+            (22, 23, Number),
+            (25, 29, Keyword),
+            (31, 35, Number),
+            (37, 44, Comment),
+        ]
+    );
+    assert_eq!(
+        kinds("Canstow create oo creg c with value b0[3]")[3..],
+        [
+            (18, 22, Type),
+            (23, 24, Variable),
+            (25, 29, Keyword),
+            (30, 35, Keyword),
+            (36, 38, Number),
+            (39, 40, Number),
+        ]
+    );
+    // Modern keywords are only keywords in modern English
+    assert_eq!(
+        kinds("Canstow create oo qubit to with value 0")[4],
+        (24, 26, Variable)
+    );
+    assert_eq!(
+        kinds("// dialect: modern\napply h to q")[3],
+        (8, 10, Keyword)
+    );
+    assert_eq!(
+        hover("Maistow create oo qubit to with value 0\n", 0, 25).unwrap(),
+        "`to`: qubit (declared on line 1 with Maistow)"
+    );
+}
+
+/// Frames messages the way a client would
+fn framed(messages: &[&str]) -> Vec<u8> {
+    messages
+        .iter()
+        .flat_map(|message| {
+            format!("Content-Length: {}\r\n\r\n{}", message.len(), message).into_bytes()
+        })
+        .collect()
+}
+
+#[test]
+fn serves_a_session() {
+    let open = format!(
+        r#"{{"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {{"textDocument": {{"uri": "file:///bell.ql", "languageId": "quill", "version": 1, "text": {}}}}}}}"#,
+        Json::from("Maistow create oo qubit q with value 0\nThy h shalt target r\n")
+    );
+    let input = framed(&[
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}"#,
+        r#"{"jsonrpc": "2.0", "method": "initialized", "params": {}}"#,
+        &open,
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {"textDocument": {"uri": "file:///bell.ql"}, "position": {"line": 1, "character": 4}}}"#,
+        r#"{"jsonrpc": "2.0", "id": "three", "method": "textDocument/rename", "params": {}}"#,
+        r#"{"jsonrpc": "2.0", "id": 4, "method": "textDocument/hover", "params": {"textDocument": {"uri": "file:///other.ql"}, "position": {"line": 0, "character": 0}}}"#,
+        "{not json",
+        r#"{"jsonrpc": "2.0", "id": 5, "method": "shutdown"}"#,
+        r#"{"jsonrpc": "2.0", "method": "exit"}"#,
+    ]);
+    let mut output = vec![];
+    assert!(serve(&input[..], &mut output).unwrap());
+
+    let output = String::from_utf8(output).unwrap();
+    let replies: Vec<Json> = output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| Json::parse(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    assert_eq!(replies.len(), 7);
+    assert_eq!(
+        replies[0].at(&["result", "capabilities", "textDocumentSync"]),
+        Some(&Json::Number(1.0))
+    );
+    let published = replies[1].at(&["params", "diagnostics"]).unwrap();
+    assert_eq!(published.as_array().unwrap().len(), 1);
+    assert_eq!(
+        replies[2]
+            .at(&["result", "contents", "value"])
+            .and_then(Json::as_str),
+        Some("`h`: single qubit gate (1 target)")
+    );
+    for (reply, code) in replies[3..6].iter().zip([-32601.0, -32602.0, -32700.0]) {
+        assert_eq!(reply.at(&["error", "code"]), Some(&Json::Number(code)));
+    }
+    assert_eq!(replies[3].get("id"), Some(&Json::from("three")));
+    assert_eq!(replies[6].get("result"), Some(&Json::Null));
+}
+
+#[test]
+fn json_round_trips() {
+    let source =
+        r#"{"a": [1, -2.5, 3e2, true, null], "b\n\"c\"": "\u00e9\ud83d\ude00\t", "d": {}}"#;
+    let json = Json::parse(source).unwrap();
+    assert_eq!(
+        json.at(&["a"]).unwrap().as_array().unwrap()[2],
+        Json::Number(300.0)
+    );
+    assert_eq!(json.get("b\n\"c\"").and_then(Json::as_str), Some("é😀\t"));
+    assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+    assert_eq!(
+        json.to_string(),
+        r#"{"a":[1,-2.5,300,true,null],"b\n\"c\"":"é😀\t","d":{}}"#
+    );
+    for bad in ["[1, 2", "{\"a\" 1}", "01", "\"\\x\"", "[1,]"] {
+        assert!(Json::parse(bad).is_err(), "{}", bad);
+    }
+}
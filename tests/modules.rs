@@ -0,0 +1,199 @@
+//! Checks that programs can use the gates and constants of the files they
+//! import, including the ones bundled with Quill, and that mistakes in or
+//! between modules are reported where they are
+use num_complex::Complex64;
+use quill::ast::{Param, Stmt, Target};
+use quill::modules::link;
+use quill::parser::parse;
+use quill::simulator::StateVector;
+use quill::type_checker::type_check;
+use std::path::{Path, PathBuf};
+
+/// A fresh directory holding the given files
+fn files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("quill-modules-{}-{}", std::process::id(), test));
+    for (name, source) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+/// Parses and links the file `name` in `dir`
+fn linked(dir: &Path, name: &str) -> Result<Vec<Stmt>, String> {
+    let path = dir.join(name).display().to_string();
+    let program = parse(&std::fs::read_to_string(&path).unwrap()).map_err(|e| e.to_string())?;
+    let program = link(&program, &path)?;
+    Ok(program.stmts.into_iter().map(|stmt| stmt.kind).collect())
+}
+
+/// A gate application as (gate, targets, controls, parameters)
+type Applied = (String, Vec<Target>, Vec<Target>, Vec<Param>);
+
+fn qubit(i: usize) -> Target {
+    Target::Slice {
+        name: "q".to_string(),
+        start: i,
+        end: i,
+    }
+}
+
+#[test]
+fn calls_expand_into_the_gates_of_their_definitions() {
+    let dir = files(
+        "expand",
+        &[
+            (
+                "main.ql",
+                "Fecche \"lib/turns.ql\"
+Fecche \"lib/pairs.ql\"
+Canstow create oo qreg q with value 0[4]
+Thy turns.spin shalt target q[3] // one qubit
+Thy pairs.both shalt target q[0..1] and q[2]
+Thy rz shalt target q[3] with [turns.quarter]
+Return 10
+",
+            ),
+            (
+                "lib/turns.ql",
+                "Certes quarter with value PI[1, 2]
+Devyse spin on a
+    Thy rx shalt target a with [quarter]
+Ende
+",
+            ),
+            // Both import turns, which is only loaded once
+            (
+                "lib/pairs.ql",
+                "Fecche \"turns.ql\"
+Devyse pair on a, b
+    Thy h shalt target a
+    Thy cnot shalt target b and control on a
+Ende
+Devyse both on a, b, c
+    Thy pair shalt target a and b
+    Thy turns.spin shalt target c
+Ende
+",
+            ),
+        ],
+    );
+    let stmts = linked(&dir, "main.ql").unwrap();
+    let gates: Vec<Applied> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Gate {
+                gate,
+                targets,
+                controls,
+                params,
+                ..
+            } => Some((
+                gate.clone(),
+                targets.clone(),
+                controls.clone(),
+                params.clone(),
+            )),
+            _ => None,
+        })
+        .collect();
+    let quarter = vec![Param::PI(1, 2)];
+    assert_eq!(
+        gates,
+        vec![
+            ("rx".to_string(), vec![qubit(3)], vec![], quarter.clone()),
+            ("h".to_string(), vec![qubit(0)], vec![], vec![]),
+            ("cnot".to_string(), vec![qubit(1)], vec![qubit(0)], vec![]),
+            ("rx".to_string(), vec![qubit(2)], vec![], quarter.clone()),
+            ("rz".to_string(), vec![qubit(3)], vec![], quarter),
+        ]
+    );
+    // The comment stays after the gates its line turned into
+    assert!(stmts.contains(&Stmt::Comment(" one qubit".to_string())));
+}
+
+#[test]
+fn the_standard_qft_is_the_fourier_transform() {
+    let source = "Fecche \"std/qft.ql\"
+Canstow create oo qreg q with value 0[3]
+Thy x shalt target q[2]
+Thy qft.qft3 shalt target q
+Return 1
+";
+    let program = link(&parse(source).unwrap(), "<stdin>").unwrap();
+    type_check(&program).unwrap();
+    let mut state = StateVector::new(&program);
+    state.apply_gates(&program);
+    // |1> goes to the sum of e^(2 PI i k / 8) |k> / sqrt(8), up to a global phase
+    let amplitudes = state.amplitudes();
+    let phase = amplitudes[0] * 8f64.sqrt();
+    for (k, amplitude) in amplitudes.iter().enumerate() {
+        let angle = 2.0 * std::f64::consts::PI * k as f64 / 8.0;
+        let expected = Complex64::from_polar(1.0 / 8f64.sqrt(), angle) * phase;
+        assert!((amplitude - expected).norm() < 1e-9, "{}: {}", k, amplitude);
+    }
+}
+
+#[test]
+fn mistakes_are_reported_in_the_file_they_are_in() {
+    let dir = files(
+        "mistakes",
+        &[
+            ("a.ql", "Fecche \"b.ql\"\n"),
+            ("b.ql", "\nFecche \"a.ql\"\n"),
+            (
+                "bad.ql",
+                "Devyse twice on a\n    Thy cnot shalt target a and control on a\nEnde\n",
+            ),
+            ("uses_bad.ql", "Fecche \"bad.ql\"\nReturn 1\n"),
+            (
+                "arity.ql",
+                "Fecche \"std/bell.ql\"
+Canstow create oo qreg q with value 0[3]
+Thy bell.pair shalt target q
+",
+            ),
+            ("unknown.ql", "Thy grover.diffuse2 shalt target q and r\n"),
+        ],
+    );
+    let error = |name: &str| linked(&dir, name).unwrap_err();
+    assert!(error("a.ql").contains("b.ql:2: imports go in a circle: a.ql -> b.ql -> a.ql!"));
+    let bad = error("uses_bad.ql");
+    assert!(
+        bad.contains("bad.ql:2: A gate cannot act on the same qubit twice"),
+        "{}",
+        bad
+    );
+    assert!(
+        error("arity.ql").ends_with("arity.ql:3: bell.pair acts on 2 qubit(s), but 3 were given!")
+    );
+    assert!(
+        error("unknown.ql").ends_with("unknown.ql:1: no module named grover has been imported!")
+    );
+
+    // Definitions have to be closed, can't be nested, and can't reuse names
+    let message = |source: &str| {
+        parse(source).unwrap_err().errors[0]
+            .variant
+            .message()
+            .to_string()
+    };
+    assert_eq!(
+        message("Devyse f on a\n"),
+        "the definition of f needs an `Ende`"
+    );
+    assert_eq!(message("Ende\n"), "there's no gate definition here to end");
+    assert_eq!(
+        message("Devyse f on a\nDevyse g on b\nEnde\n"),
+        "gates can't be defined inside another gate's definition"
+    );
+    assert_eq!(
+        message("Devyse h on a\nEnde\n"),
+        "h is already a gate, so it can't be defined again"
+    );
+    assert_eq!(
+        message("Devyse f on a\nRede a and quyken c\nEnde\n"),
+        "only gate applications can go in a gate's definition"
+    );
+}
@@ -0,0 +1,183 @@
+//! Checks which passes the pass manager runs at each level, and what each of
+//! them did to the program's gate count and depth
+use quill::ast::Program;
+use quill::optimizations::decomposition::{BasisSet, DecomposeMultiControlled};
+use quill::optimizations::{depth, earned_level, gate_count, OptLevel, Pass, PassManager};
+use quill::parser::parse;
+use quill::type_checker::{type_check, SymbolTable};
+
+/// Each level gets further than the last: O1 merges the rotations away, O2
+/// fuses the h's that leaves and drops spare, and only O3 goes back around to
+/// cancel the cnots that are left next to each other
+const LAYERED: &str = "Maistow create oo qreg q with value 0[2]
+Canstow create oo qubit spare with value 0
+Canstow create oo creg c with value b0[2]
+Thy cnot shalt target q[1] and control on q[0]
+Thy h shalt target q[1]
+Thy rz shalt target q[1] with [PI[1, 1]]
+Thy rz shalt target q[1] with [PI[1, 1]]
+Thy h shalt target q[1]
+Thy cnot shalt target q[1] and control on q[0]
+Thy x shalt target spare
+Rede q and quyken c
+Return 10
+";
+
+#[test]
+fn each_level_runs_the_passes_below_it() {
+    let expected: [(OptLevel, &[&str]); 4] = [
+        (OptLevel::O0, &[]),
+        (OptLevel::O1, &["cancel-inverses", "merge-rotations"]),
+        (
+            OptLevel::O2,
+            &[
+                "cancel-inverses",
+                "merge-rotations",
+                "resynthesize-1q",
+                "eliminate-dead-code",
+            ],
+        ),
+        (
+            OptLevel::O3,
+            &[
+                "cancel-inverses",
+                "merge-rotations",
+                "resynthesize-1q",
+                "eliminate-dead-code",
+                "repeat-until-fixed",
+            ],
+        ),
+    ];
+    for (level, names) in expected {
+        let mut program = parse(LAYERED).unwrap();
+        let mut symbols = type_check(&program).unwrap();
+        let report = PassManager::new()
+            .run(&mut program, &mut symbols, level)
+            .unwrap();
+        assert_eq!(report.level, level);
+        let ran: Vec<&str> = report.results.iter().map(|result| result.name).collect();
+        assert_eq!(ran, names, "{:?}", level);
+    }
+}
+
+#[test]
+fn higher_levels_leave_fewer_gates() {
+    for (level, gates, layers) in [
+        (OptLevel::O0, 7, 6),
+        (OptLevel::O1, 5, 4),
+        (OptLevel::O2, 2, 2),
+        (OptLevel::O3, 0, 0),
+    ] {
+        let mut program = parse(LAYERED).unwrap();
+        let mut symbols = type_check(&program).unwrap();
+        PassManager::new()
+            .run(&mut program, &mut symbols, level)
+            .unwrap();
+        assert_eq!(gate_count(&program), gates, "{:?}", level);
+        assert_eq!(depth(&program), layers, "{:?}", level);
+    }
+}
+
+#[test]
+fn results_count_gates_and_depth_around_each_pass() {
+    let mut program = parse(LAYERED).unwrap();
+    let mut symbols = type_check(&program).unwrap();
+    let report = PassManager::new()
+        .run(&mut program, &mut symbols, OptLevel::O3)
+        .unwrap();
+    let counts: Vec<(usize, usize, usize, usize)> = report
+        .results
+        .iter()
+        .map(|result| {
+            (
+                result.gates_before,
+                result.gates_after,
+                result.depth_before,
+                result.depth_after,
+            )
+        })
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            // Nothing cancels yet, since the h's are apart
+            (7, 7, 6, 6),
+            (7, 5, 6, 4),
+            (5, 3, 4, 2),
+            (3, 2, 2, 2),
+            (2, 0, 2, 0),
+        ]
+    );
+    // Each pass starts from where the last one left off
+    for pair in report.results.windows(2) {
+        assert_eq!(pair[0].gates_after, pair[1].gates_before);
+        assert_eq!(pair[0].depth_after, pair[1].depth_before);
+    }
+}
+
+/// Notes down the variables it's told the program has
+struct Variables;
+
+impl Pass for Variables {
+    fn name(&self) -> &'static str {
+        "variables"
+    }
+
+    fn run(&self, _: &mut Program, symbols: &SymbolTable) -> Result<Vec<String>, String> {
+        let mut names: Vec<String> = symbols.types.keys().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[test]
+fn passes_see_the_variables_earlier_passes_declared() {
+    let mut program = parse(
+        "Maistow create oo qreg q with value 0[4]
+Thy mcx shalt target q[3] and control on q[0], q[1], q[2]
+",
+    )
+    .unwrap();
+    let mut symbols = type_check(&program).unwrap();
+    let mut manager = PassManager::new();
+    manager.register(
+        OptLevel::O0,
+        Box::new(DecomposeMultiControlled {
+            basis: BasisSet::U3Cnot,
+            ancillas: true,
+        }),
+    );
+    manager.register(OptLevel::O0, Box::new(Variables));
+    let report = manager
+        .run(&mut program, &mut symbols, OptLevel::O0)
+        .unwrap();
+    assert_eq!(report.results[1].notes, ["ancilla", "q"]);
+    // The caller's table is kept up to date too
+    assert_eq!(symbols.widths["ancilla"], 1);
+}
+
+#[test]
+fn respect_earns_levels() {
+    // The share of assignments that use Maistow, out of ten
+    let program = |maistows: usize| {
+        let source: String = (0..10)
+            .map(|i| {
+                let respect = if i < maistows { "Maistow" } else { "Canstow" };
+                format!("{} create oo qubit q{} with value 0\n", respect, i)
+            })
+            .collect();
+        parse(&source).unwrap()
+    };
+    for (maistows, level) in [
+        (0, OptLevel::O0),
+        (4, OptLevel::O0),
+        (5, OptLevel::O1),
+        (6, OptLevel::O1),
+        (7, OptLevel::O2),
+        (8, OptLevel::O3),
+        (9, OptLevel::O3),
+        (10, OptLevel::O0),
+    ] {
+        assert_eq!(earned_level(&program(maistows)), level, "{}", maistows);
+    }
+}
@@ -0,0 +1,97 @@
+//! Checks that the parser reports every malformed line of a program at once,
+//! and says what was probably meant
+use pest::error::LineColLocation;
+use quill::lsp::analysis::{diagnostics, Severity};
+use quill::parser::{parse, parse_statement, Dialect};
+
+const BROKEN: &str = "Maistow create oo qubit q with value 0
+Thy h shal target q
+Canstow create oo qbit r with value +
+Thy cnot shalt target r and control on q
+Rede q and c
+Retrun 100
+";
+
+/// The line and message of every error in a program that doesn't parse
+fn errors(source: &str) -> Vec<(usize, String)> {
+    parse(source)
+        .unwrap_err()
+        .errors
+        .into_iter()
+        .map(|error| {
+            let line = match error.line_col {
+                LineColLocation::Pos((line, _)) | LineColLocation::Span((line, _), _) => line,
+            };
+            (line, error.variant.message().to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn every_malformed_line_is_reported() {
+    let found = errors(BROKEN);
+    let lines: Vec<usize> = found.iter().map(|(line, _)| *line).collect();
+    assert_eq!(lines, [2, 3, 5, 6]);
+    assert_eq!(found[0].1, "did you mean `shalt target`?");
+    assert_eq!(found[1].1, "did you mean `qubit`?");
+    assert_eq!(found[2].1, "expected `quyken` after `and`, but found `c`");
+    assert_eq!(found[3].1, "did you mean `Return`?");
+
+    // Gates that aren't in the registry are reported alongside syntax errors
+    let found = errors("Thy cnto shalt target q\nThy h target q\n");
+    assert_eq!(
+        found[0].1,
+        "\"cnto\" is not a known gate, did you mean `cnot`?"
+    );
+    assert_eq!(found[1].1, "did you mean `shalt target`?");
+    // Printing every error keeps the whole report together
+    let report = parse(BROKEN)
+        .unwrap_err()
+        .with_path("broken.ql")
+        .to_string();
+    assert_eq!(report.matches("--> broken.ql:").count(), 4);
+}
+
+#[test]
+fn messages_say_what_was_expected() {
+    let message = |line: &str| {
+        parse_statement(line, 1, Dialect::MiddleEnglish)
+            .unwrap_err()
+            .variant
+            .message()
+            .to_string()
+    };
+    assert_eq!(
+        message("Thy x shalt target q control on r"),
+        "did you mean `and control on`?"
+    );
+    assert_eq!(
+        message("Maistow create oo qubit r with 0"),
+        "expected `value` after `with`, but found `0`"
+    );
+    assert_eq!(
+        message("Maistow create oo qubit with with value 0"),
+        "`with` is a keyword, so it can't be a variable's name"
+    );
+    assert_eq!(
+        message("Return many"),
+        "expected a whole number, but found `many`"
+    );
+    assert_eq!(
+        message("Thy h shalt target"),
+        "expected a variable, like q or q[0], but the line ends there"
+    );
+    assert!(message("Declare q").starts_with("statements start with Maistow"));
+}
+
+#[test]
+fn the_language_server_shows_every_error() {
+    let found = diagnostics(BROKEN);
+    assert_eq!(found.len(), 4);
+    assert!(found
+        .iter()
+        .all(|diagnostic| diagnostic.severity == Severity::Error));
+    assert_eq!(found[0].span.line, 1);
+    assert_eq!((found[0].span.start, found[0].span.end), (6, 10));
+    assert_eq!(found[3].span.line, 5);
+}
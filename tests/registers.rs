@@ -0,0 +1,114 @@
+//! Checks that registers can start out holding different values, like
+//! `0[2] + +[1] + 1[2]` or `b0[2] + b1[3]`, from parsing through to the
+//! state the simulator and backends prepare
+use num_complex::Complex64;
+use quill::ast::{Stmt, Value};
+use quill::backends::{qasm3, qiskit};
+use quill::parser::parse;
+use quill::simulator::{run, Rng, StateVector};
+use quill::type_checker::type_check;
+use std::collections::HashMap;
+
+const MIXED: &str = "Canstow create oo qreg q with value 0[2] + +[1] + 1[2]
+Canstow create oo creg c with value b0[2] + b1[3]
+Rede q[3..4] and quyken c[0..1]
+Return 10
+";
+
+#[test]
+fn mixed_values_parse_into_segments_with_their_total_width() {
+    let program = parse(MIXED).unwrap();
+    let values: Vec<&Value> = program
+        .stmts
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            Stmt::Assign { value, .. } => Some(value),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        values,
+        [
+            &Value::QReg(vec![
+                ("0".to_string(), 2),
+                ("+".to_string(), 1),
+                ("1".to_string(), 2)
+            ]),
+            &Value::CReg(vec![(0, 2), (1, 3)]),
+        ]
+    );
+    let symbols = type_check(&program).unwrap();
+    assert_eq!(symbols.widths["q"], 5);
+    assert_eq!(symbols.widths["c"], 5);
+
+    let error = |source: &str| type_check(&parse(source).unwrap()).unwrap_err();
+    assert_eq!(
+        error("Canstow create oo qreg q with value 0[2] + 1[1]\nThy x shalt target q[3]\n"),
+        "2: Index 3 is out of bounds for \"q\", which only has 3 elements!"
+    );
+    assert_eq!(
+        error("Canstow create oo qreg q with value b0[2] + b1[1]\n"),
+        "1: A QReg was declared, but given a CReg value!"
+    );
+    assert_eq!(
+        error("Canstow create oo creg c with value b0[2] + b1[0]\n"),
+        "1: Register lengths must be positive integers, found 0 instead!"
+    );
+    // Lengths are indices, never variables
+    assert!(parse("Canstow create oo creg c with value b0[n]\n").is_err());
+}
+
+#[test]
+fn the_simulator_prepares_each_segment() {
+    let program = parse(MIXED).unwrap();
+    let symbols = type_check(&program).unwrap();
+    // Before the measurement, q is 00+11, so 00011 and 00111 equally
+    let mut state = StateVector::new(&program);
+    let (mut cbits, mut rng) = (HashMap::new(), Rng::new(0));
+    for stmt in &program.stmts[..2] {
+        state.execute(stmt, &mut cbits, &mut rng).unwrap();
+    }
+    let half = Complex64::new(std::f64::consts::FRAC_1_SQRT_2, 0.0);
+    for (i, amplitude) in state.amplitudes().iter().enumerate() {
+        let expected = match i {
+            0b00011 | 0b00111 => half,
+            _ => Complex64::new(0.0, 0.0),
+        };
+        assert!((amplitude - expected).norm() < 1e-9, "{}: {}", i, amplitude);
+    }
+    assert_eq!(cbits["c"], [false, false, true, true, true]);
+
+    // The measured 1s land in the b0 half of c
+    let counts = run(&program, &symbols, 10, &mut rng).unwrap();
+    assert_eq!(
+        counts.into_iter().collect::<Vec<_>>(),
+        [("c=11111".to_string(), 10)]
+    );
+}
+
+#[test]
+fn backends_prepare_each_segment() {
+    let program = parse(MIXED).unwrap();
+    let symbols = type_check(&program).unwrap();
+    let qasm = qasm3::emit(&program, &symbols).unwrap();
+    for line in [
+        "qubit[5] q;",
+        "h q[2];",
+        "x q[3];",
+        "x q[4];",
+        "bit[5] c = \"11100\";",
+    ] {
+        assert!(qasm.contains(line), "{} isn't in:\n{}", line, qasm);
+    }
+    assert!(!qasm.contains("q[0];") && !qasm.contains("q[1];"));
+
+    let python = qiskit::emit(&program, &symbols).unwrap();
+    for line in [
+        "q = QuantumRegister(5, \"q\")",
+        "circuit.h(q[2])",
+        "circuit.x(q[4])",
+        "# c[4] starts as 1, which Qiskit can't express",
+    ] {
+        assert!(python.contains(line), "{} isn't in:\n{}", line, python);
+    }
+}
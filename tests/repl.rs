@@ -0,0 +1,112 @@
+//! Drives a REPL session the way someone typing into it would, checking that
+//! variables and the state carry over from one line to the next
+use quill::repl::Session;
+
+fn eval(session: &mut Session, line: &str) -> String {
+    session
+        .eval(line)
+        .unwrap_or_else(|error| panic!("{:?} failed: {}", line, error))
+}
+
+#[test]
+fn state_carries_over_between_statements() {
+    let mut session = Session::new(1);
+    eval(&mut session, "Maistow create oo qreg q with value 0[2]");
+    eval(&mut session, "Thy h shalt target q[0]");
+    eval(
+        &mut session,
+        "Thy cnot shalt target q[1] and control on q[0]",
+    );
+    let state = eval(&mut session, ":state");
+    let lines: Vec<&str> = state.lines().collect();
+    assert_eq!(lines[0], "|q[0] q[1]>");
+    assert!(lines[1].starts_with("|00>  +0.7071"), "{}", state);
+    assert!(lines[2].starts_with("|11>  +0.7071"), "{}", state);
+    assert_eq!(lines.len(), 3);
+
+    // Qubits declared later go below the ones already there
+    eval(&mut session, "Maistow create oo qubit r with value 1");
+    eval(&mut session, "Canstow create oo creg c with value b0[2]");
+    let measured = eval(&mut session, "Rede q and quyken c");
+    assert!(measured == "c=00" || measured == "c=11", "{}", measured);
+    let state = eval(&mut session, ":state");
+    assert_eq!(state.lines().count(), 2);
+    assert!(state.starts_with("|q[0] q[1] r>"));
+    assert!(state
+        .lines()
+        .nth(1)
+        .unwrap()
+        .ends_with("1>  +1.0000+0.0000i  (p=1.0000)"));
+}
+
+#[test]
+fn bad_statements_leave_the_session_alone() {
+    let mut session = Session::new(1);
+    eval(&mut session, "Maistow create oo qubit a with value 0");
+    assert!(session.eval("Thy h shalt target b").is_err());
+    assert!(session.eval("Thy h shalt taget a").is_err());
+    assert!(session
+        .eval("Maistow create oo cbit a with value b0")
+        .is_err());
+    assert!(session.eval(":frobnicate").is_err());
+    assert!(session.eval(":load /no/such/file.ql").is_err());
+    eval(&mut session, "Thy x shalt target a");
+    assert_eq!(
+        eval(&mut session, ":state"),
+        "|a>\n|1>  +1.0000+0.0000i  (p=1.0000)"
+    );
+    // Errors point at the line the statement would have been
+    let error = session.eval("Thy h shalt target b").unwrap_err();
+    assert!(error.starts_with("3: "), "{}", error);
+}
+
+#[test]
+fn commands() {
+    let mut session = Session::new(1);
+    assert_eq!(eval(&mut session, ":circuit"), "(empty circuit)");
+    assert_eq!(eval(&mut session, "// nothing to run"), "");
+    assert_eq!(eval(&mut session, ""), "");
+
+    let path = std::env::temp_dir().join(format!("quill-repl-{}.ql", std::process::id()));
+    std::fs::write(
+        &path,
+        "Maistow create oo qreg q with value 0[2]
+Canstow create oo cbit c with value b0
+// Entangle them
+Thy h shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Rede q[1] and quyken c
+Return 50
+",
+    )
+    .unwrap();
+    let output = eval(&mut session, &format!(":load {}", path.display()));
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0] == "c=0" || lines[0] == "c=1");
+    assert!(lines[1..].iter().all(|line| line.starts_with("c=")));
+
+    assert_eq!(
+        eval(&mut session, ":circuit"),
+        "q[0] -|0>-h--*-----
+q[1] -|0>---cnot-M-
+c    ==0=========v="
+    );
+    assert_eq!(eval(&mut session, ":reset"), "Reset");
+    assert_eq!(eval(&mut session, ":state"), "(no qubits)");
+    assert!(session.eval("Thy h shalt target q[0]").is_err());
+}
+
+#[test]
+fn a_header_switches_dialects() {
+    let mut session = Session::new(3);
+    eval(&mut session, "// dialect: modern");
+    eval(&mut session, "let qubit q = 1");
+    eval(&mut session, "apply x to q");
+    assert!(session.eval("Thy x shalt target q").is_err());
+    eval(&mut session, "// dialect: middle english");
+    eval(&mut session, "Canstow create oo cbit c with value b0");
+    eval(&mut session, "Rede q and quyken c");
+    assert!(eval(&mut session, "Return 4").starts_with("c=0"));
+    assert!(session.eval("// dialect: klingon").is_err());
+}
@@ -0,0 +1,89 @@
+//! Checks that fusing runs of single qubit gates into u3's only fuses what it
+//! should, and drops what multiplies out to the identity
+mod common;
+
+use common::{gate_names, run_pass};
+use quill::optimizations::resynthesis::ResynthesizeSingleQubit;
+
+/// The gates left over from resynthesizing over two qubits q[0..1]
+fn resynthesize(gates: &str) -> Vec<String> {
+    gate_names(&run_pass(&ResynthesizeSingleQubit, 2, gates))
+}
+
+#[test]
+fn runs_become_one_u3() {
+    for gates in [
+        "Thy h shalt target q[0]\nThy t shalt target q[0]\n",
+        "Thy h shalt target q[0]
+Thy t shalt target q[0]
+Thy h shalt target q[0]
+Thy s shalt target q[0]
+Thy sx shalt target q[0]
+",
+        "Thy rx shalt target q[1] with [0.3]
+Thy ry shalt target q[1] with [PI[2, 3]]
+Thy rz shalt target q[1] with [1.7]
+Thy u3 shalt target q[1] with [0.4, 1.2, PI[3, 4]]
+",
+    ] {
+        assert_eq!(resynthesize(gates), ["u3"], "{}", gates);
+    }
+}
+
+#[test]
+fn runs_that_multiply_out_to_identity_are_dropped() {
+    for gates in [
+        "Thy h shalt target q[0]\nThy h shalt target q[0]\n",
+        "Thy s shalt target q[0]\nThy s shalt target q[0]\nThy z shalt target q[0]\n",
+        "Thy rx shalt target q[1] with [PI[1, 3]]\nThy rx shalt target q[1] with [PI[5, 3]]\n",
+        "Thy h shalt target q[1]\nThy x shalt target q[1]\nThy h shalt target q[1]\nThy z shalt target q[1]\n",
+    ] {
+        assert!(resynthesize(gates).is_empty(), "{}", gates);
+    }
+}
+
+#[test]
+fn global_phases_are_dropped() {
+    // x y z is i times the identity, and x z x z is minus the identity
+    for gates in [
+        "Thy x shalt target q[0]\nThy y shalt target q[0]\nThy z shalt target q[0]\n",
+        "Thy x shalt target q[0]\nThy z shalt target q[0]\nThy x shalt target q[0]\nThy z shalt target q[0]\n",
+    ] {
+        assert!(resynthesize(gates).is_empty(), "{}", gates);
+    }
+    // x y is i z, which is left as a u3 without the i
+    assert_eq!(
+        resynthesize("Thy x shalt target q[1]\nThy y shalt target q[1]\n"),
+        ["u3"]
+    );
+}
+
+#[test]
+fn only_runs_on_one_qubit_are_fused() {
+    for (gates, left) in [
+        // A lone gate isn't a run
+        ("Thy h shalt target q[0]\n", vec!["h"]),
+        // The cnot splits the runs on q[0]
+        (
+            "Thy h shalt target q[0]
+Thy t shalt target q[0]
+Thy cnot shalt target q[1] and control on q[0]
+Thy t shalt target q[0]
+Thy h shalt target q[0]
+",
+            vec!["u3", "cnot", "u3"],
+        ),
+        // Gates over ranges are left alone, and end the runs they touch
+        (
+            "Thy h shalt target q[0]\nThy x shalt target q[0..1]\nThy h shalt target q[0]\n",
+            vec!["h", "x", "h"],
+        ),
+        // Runs on different qubits are fused separately
+        (
+            "Thy h shalt target q[0]\nThy s shalt target q[1]\nThy t shalt target q[0]\nThy h shalt target q[1]\n",
+            vec!["u3", "u3"],
+        ),
+    ] {
+        assert_eq!(resynthesize(gates), left, "{}", gates);
+    }
+}
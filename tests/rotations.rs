@@ -0,0 +1,83 @@
+//! Checks that merging rotations adds up the right angles and leaves
+//! everything else alone
+mod common;
+
+use common::run_pass;
+use quill::ast::{Param, Stmt};
+use quill::optimizations::rotations::MergeRotations;
+
+/// The gates left over from merging over two qubits q[0..1], with their angles
+fn merge(gates: &str) -> Vec<(String, Vec<Param>)> {
+    run_pass(&MergeRotations, 2, gates)
+        .into_iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Gate { gate, params, .. } => Some((gate, params)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn rotation(gate: &str, angle: Param) -> (String, Vec<Param>) {
+    (gate.to_string(), vec![angle])
+}
+
+#[test]
+fn angles_add_up() {
+    assert_eq!(
+        merge(
+            "Thy rz shalt target q[0] with [PI[1, 2]]\nThy rz shalt target q[0] with [PI[1, 4]]\n"
+        ),
+        [rotation("rz", Param::PI(3, 4))]
+    );
+    // Three in a row, through the control of a cnot
+    assert_eq!(
+        merge(
+            "Thy rz shalt target q[0] with [0.25]
+Thy cnot shalt target q[1] and control on q[0]
+Thy rz shalt target q[0] with [0.5]
+Thy rz shalt target q[0] with [1]
+"
+        ),
+        [
+            rotation("rz", Param::Float(1.75)),
+            ("cnot".to_string(), vec![]),
+        ]
+    );
+    assert_eq!(
+        merge("Thy rxx shalt target q[0] and q[1] with [0.5]\nThy rxx shalt target q[0] and q[1] with [0.5]\n"),
+        [rotation("rxx", Param::Float(1.0))]
+    );
+    // Past a full turn, the angle wraps around
+    assert_eq!(
+        merge(
+            "Thy ry shalt target q[1] with [PI[3, 2]]\nThy ry shalt target q[1] with [PI[3, 2]]\n"
+        ),
+        [rotation("ry", Param::PI(1, 1))]
+    );
+}
+
+#[test]
+fn full_turns_are_dropped() {
+    for gates in [
+        "Thy rz shalt target q[0] with [PI[1, 1]]\nThy rz shalt target q[0] with [PI[1, 1]]\n",
+        "Thy rx shalt target q[1] with [PI[3, 2]]\nThy rx shalt target q[1] with [PI[1, 2]]\n",
+        "Thy rzz shalt target q[0] and q[1] with [PI[2, 1]]\n",
+        "Thy ry shalt target q[0] with [0]\n",
+    ] {
+        assert!(merge(gates).is_empty(), "{}", gates);
+    }
+}
+
+#[test]
+fn different_axes_and_qubits_stay_apart() {
+    for gates in [
+        "Thy rz shalt target q[0] with [0.5]\nThy rx shalt target q[0] with [0.5]\n",
+        "Thy rx shalt target q[0] with [0.5]\nThy ry shalt target q[0] with [0.5]\n",
+        "Thy rz shalt target q[0] with [0.5]\nThy rz shalt target q[1] with [0.5]\n",
+        "Thy rxx shalt target q[0] and q[1] with [0.5]\nThy rzz shalt target q[0] and q[1] with [0.5]\n",
+        // Something in between that doesn't commute keeps them apart as well
+        "Thy rz shalt target q[0] with [0.5]\nThy h shalt target q[0]\nThy rz shalt target q[0] with [0.5]\n",
+    ] {
+        assert_eq!(merge(gates).len(), gates.lines().count(), "{}", gates);
+    }
+}
@@ -0,0 +1,211 @@
+//! Checks that routing only leaves two qubit gates on neighbouring physical
+//! qubits, and that the state it leaves the device in is the state of the
+//! original program with its qubits moved to where the layout says they end up
+use quill::ast::{Program, Stmt, Target};
+use quill::parser::parse;
+use quill::routing::{route, CouplingMap, Layout};
+use quill::simulator::StateVector;
+use quill::type_checker::type_check;
+
+fn physical(target: &Target) -> usize {
+    target
+        .name()
+        .strip_prefix("physical")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+fn check(source: &str, coupling: &CouplingMap) -> (Program, Layout) {
+    let mut program = parse(source).unwrap();
+    let symbols = type_check(&program).unwrap();
+    let mut expected = StateVector::new(&program);
+    expected.apply_gates(&program);
+
+    let layout = route(&mut program, &symbols, coupling).unwrap();
+    type_check(&program).unwrap();
+    let mut declared = vec![];
+    for stmt in &program.stmts {
+        match &stmt.kind {
+            Stmt::Gate {
+                targets, controls, ..
+            } if targets.len() + controls.len() == 2 => {
+                let qubits: Vec<usize> = targets.iter().chain(controls).map(physical).collect();
+                assert!(
+                    coupling.connected(qubits[0], qubits[1]),
+                    "{}: {:?} aren't neighbours",
+                    stmt.line_no,
+                    qubits
+                );
+            }
+            Stmt::Assign { name, .. } if name.starts_with("physical") => {
+                let qubit = physical(&Target::Name(name.clone()));
+                if !declared.contains(&qubit) {
+                    declared.push(qubit);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut actual = StateVector::new(&program);
+    actual.apply_gates(&program);
+    let (logical, width) = (layout.logical.len(), declared.len());
+    for (index, amplitude) in expected.amplitudes().iter().enumerate() {
+        // Move every bit of the logical basis state to its physical qubit
+        let mut moved = 0;
+        for (id, &qubit) in layout.end.iter().enumerate() {
+            if index & (1 << (logical - 1 - id)) != 0 {
+                let position = declared.iter().position(|&q| q == qubit).unwrap();
+                moved |= 1 << (width - 1 - position);
+            }
+        }
+        assert!(
+            (amplitude - actual.amplitudes()[moved]).norm() < 1e-9,
+            "the amplitude of {:b} moved wrong",
+            index
+        );
+    }
+    (program, layout)
+}
+
+const ALL_PAIRS: &str = "Maistow create oo qreg q with value 0[4]
+Canstow create oo qubit r with value 0
+Thy h shalt target q[0..3]
+Thy ry shalt target r with [0.3]
+Thy cnot shalt target q[3] and control on q[0]
+Thy rzz shalt target q[1] and r with [0.7]
+Thy cz shalt target r and control on q[2]
+Thy rx shalt target q[0] with [1.2]
+Thy cnot shalt target q[2] and control on q[0]
+Thy rxx shalt target q[3] and r with [PI[1, 3]]
+Thy cnot shalt target q[1] and control on q[3]
+Thy ry shalt target q[1..2] with [0.5]
+Thy swap shalt target q[0] and r
+Thy cnot shalt target q[0] and control on q[2]
+Thy ryy shalt target r and q[1] with [2.1]
+";
+
+#[test]
+fn routes_onto_a_line() {
+    let line = CouplingMap::new(5, &[(0, 1), (1, 2), (2, 3), (3, 4)]).unwrap();
+    let (_, layout) = check(ALL_PAIRS, &line);
+    assert!(layout.swaps > 0);
+}
+
+#[test]
+fn routes_onto_a_grid_with_qubits_to_spare() {
+    // 0 - 1 - 2
+    // |   |   |
+    // 3 - 4 - 5
+    let grid = CouplingMap::from_json(
+        r#"{"num_qubits": 6, "edges": [[0, 1], [1, 2], [3, 4], [4, 5], [0, 3], [1, 4], [2, 5]]}"#,
+    )
+    .unwrap();
+    check(ALL_PAIRS, &grid);
+}
+
+#[test]
+fn fully_connected_devices_need_no_swaps() {
+    let edges: Vec<(usize, usize)> = (0..5)
+        .flat_map(|a| (a + 1..5).map(move |b| (a, b)))
+        .collect();
+    let (_, layout) = check(ALL_PAIRS, &CouplingMap::new(5, &edges).unwrap());
+    assert_eq!(layout.swaps, 0);
+}
+
+#[test]
+fn measurements_still_land_in_the_same_cbits() {
+    let source = "Maistow create oo qreg q with value 0[3]
+Canstow create oo creg c with value b0[3]
+Thy h shalt target q[0]
+Thy cnot shalt target q[2] and control on q[0]
+Thy cnot shalt target q[1] and control on q[2]
+Thy cnot shalt target q[0] and control on q[1]
+Rede q and quyken c
+Return 10
+";
+    let line = CouplingMap::from_json("[[0, 1], [1, 2]]").unwrap();
+    let (program, layout) = check(source, &line);
+    let measurements: Vec<(usize, &Target)> = program
+        .stmts
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            Stmt::Measure {
+                measured,
+                recipient,
+            } => Some((physical(measured), recipient)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(measurements.len(), 3);
+    // Each qubit's measurement may move, but q[i] still goes into c[i]
+    for (qubit, recipient) in measurements {
+        match recipient {
+            Target::Slice { name, start, end } if name == "c" && start == end => {
+                assert_eq!(layout.physical("q", *start), Some(qubit))
+            }
+            _ => panic!("{} isn't a single cbit of c", recipient),
+        }
+    }
+    assert!(matches!(
+        program.stmts.last().unwrap().kind,
+        Stmt::Return { shots: 10 }
+    ));
+}
+
+#[test]
+fn bad_coupling_maps_are_rejected() {
+    assert!(CouplingMap::from_json("[[0, 1], [1, 2]").is_err());
+    assert!(CouplingMap::from_json("[[0, 0]]").is_err());
+    assert!(CouplingMap::from_json(r#"{"num_qubits": 2, "edges": [[0, 2]]}"#).is_err());
+    assert!(CouplingMap::from_json(r#"{"qubits": 2, "edges": [[0, 1]]}"#).is_err());
+    assert!(CouplingMap::from_json(r#"{"num_qubits": 2}"#).is_err());
+    // Valid JSON, but not a coupling map
+    for (json, message) in [
+        (
+            "\"edges\"",
+            "A coupling map is a list of edges or an object, not \"edges\"!",
+        ),
+        (
+            "[[0, 1, 2]]",
+            "Expected an edge like [0, 1], found [0,1,2]!",
+        ),
+        ("[[0, 1.5]]", "Expected a qubit, found 1.5!"),
+        ("[[0, -1]]", "Expected a qubit, found -1!"),
+        ("[[0, 1e12]]", "1000000000000 is too big to be a qubit!"),
+        (
+            r#"{"edges": {"0": 1}}"#,
+            "Expected a list of edges, found {\"0\":1}!",
+        ),
+    ] {
+        assert_eq!(CouplingMap::from_json(json).unwrap_err(), message);
+    }
+    let map = CouplingMap::from_json(" [ [0,1] ,\n [1, 3] ] ").unwrap();
+    assert_eq!(map.num_qubits(), 4);
+    assert_eq!(map.distance(0, 3), Some(2));
+    assert_eq!(map.distance(0, 2), None);
+}
+
+#[test]
+fn programs_that_cant_be_routed_are_rejected() {
+    let route_onto = |source: &str, json: &str| {
+        let mut program = parse(source).unwrap();
+        let symbols = type_check(&program).unwrap();
+        route(
+            &mut program,
+            &symbols,
+            &CouplingMap::from_json(json).unwrap(),
+        )
+    };
+    let pair = "Maistow create oo qreg q with value 0[2]
+Thy cnot shalt target q[1] and control on q[0]
+";
+    assert!(route_onto(pair, "[[0, 1]]").is_ok());
+    assert!(route_onto(pair, r#"{"num_qubits": 2, "edges": []}"#).is_err());
+    assert!(route_onto(ALL_PAIRS, "[[0, 1], [1, 2], [2, 3]]").is_err());
+    let toffoli = "Maistow create oo qreg q with value 0[3]
+Thy toffoli shalt target q[2] and control on q[0], q[1]
+";
+    assert!(route_onto(toffoli, "[[0, 1], [1, 2]]").is_err());
+}
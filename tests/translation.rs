@@ -0,0 +1,141 @@
+//! Checks that translating a program into a basis only leaves basis gates
+//! behind and preserves the unitary the program applies
+mod common;
+
+use common::equal_up_to_phase;
+use quill::ast::{Param, Stmt};
+use quill::backends::{qasm, qir};
+use quill::gates;
+use quill::optimizations::translation::BasisTranslation;
+use quill::optimizations::Pass;
+use quill::parser::parse;
+use quill::simulator::unitary;
+use quill::type_checker::type_check;
+
+const EVERY_GATE: &str = "Maistow create oo qreg q with value 0[3]
+Thy h shalt target q[0..2]
+Thy x shalt target q[0]
+Thy y shalt target q[1]
+Thy z shalt target q[2]
+Thy s shalt target q[0]
+Thy sadj shalt target q[1]
+Thy t shalt target q[2]
+Thy tadj shalt target q[0]
+Thy sx shalt target q[1]
+Thy sxadj shalt target q[2]
+Thy rx shalt target q[0] with [0.3]
+Thy ry shalt target q[1] with [PI[1, 3]]
+Thy rz shalt target q[2] with [1.1]
+Thy u3 shalt target q[0..1] with [0.4, 1.2, PI[3, 4]]
+Thy cnot shalt target q[1] and control on q[0]
+Thy cz shalt target q[2] and control on q[1]
+Thy swap shalt target q[0] and q[2]
+Thy rxx shalt target q[0] and q[1] with [0.7]
+Thy ryy shalt target q[1] and q[2] with [PI[1, 5]]
+Thy rzz shalt target q[2] and q[0] with [2.2]
+Thy toffoli shalt target q[2] and control on q[0], q[1]
+Thy mcz shalt target q[0] and control on q[1], q[2]
+Thy mcy shalt target q[1] and control on q[2], q[0]
+";
+
+#[test]
+fn every_gate_into_every_kind_of_basis() {
+    let bases: [&[&str]; 8] = [
+        &["rz", "sx", "cx"],
+        &["rx", "ry", "rzz"],
+        &["u3", "cnot"],
+        &["rz", "ry", "cz"],
+        &["rz", "rx", "rxx"],
+        &["rx", "ry", "ryy"],
+        &["u3", "cz"],
+        &["rz", "sx", "rzz"],
+    ];
+    for basis in bases {
+        let mut program = parse(EVERY_GATE).unwrap();
+        let symbols = type_check(&program).unwrap();
+        let expected = unitary(&program);
+
+        let translation = BasisTranslation::new(basis).unwrap();
+        translation.run(&mut program, &symbols).unwrap();
+        for stmt in &program.stmts {
+            if let Stmt::Gate { gate, .. } = &stmt.kind {
+                let name = gates::lookup(gate).unwrap().name;
+                assert!(
+                    translation.gates().contains(&name),
+                    "{} is not in {:?}",
+                    gate,
+                    basis
+                );
+            }
+        }
+        assert!(
+            equal_up_to_phase(&expected, &unitary(&program)),
+            "translating into {:?} changed the unitary",
+            basis
+        );
+    }
+}
+
+#[test]
+fn basis_gates_keep_their_angles() {
+    let mut program = parse(
+        "Maistow create oo qubit q with value 0\n\
+         Thy rz shalt target q with [PI[1, 3]]\n\
+         Thy rx shalt target q with [PI[1, 2]]\n",
+    )
+    .unwrap();
+    let symbols = type_check(&program).unwrap();
+    BasisTranslation::new(&["rz", "sx", "cx"])
+        .unwrap()
+        .run(&mut program, &symbols)
+        .unwrap();
+    let params: Vec<Vec<Param>> = program
+        .stmts
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            Stmt::Gate { params, .. } => Some(params.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(params[0], vec![Param::PI(1, 3)]);
+    // rx(PI / 2) = rz(-PI / 2) sx rz(PI / 2), up to a global phase
+    for param in params.iter().flatten() {
+        assert!(matches!(param, Param::PI(..)), "{:?} is not exact", param);
+    }
+}
+
+#[test]
+fn bases_that_cant_express_everything_are_rejected() {
+    assert!(BasisTranslation::new(&["foo", "cnot"]).is_err());
+    assert!(BasisTranslation::new(&["u3"]).is_err());
+    assert!(BasisTranslation::new(&["h", "t", "cnot"]).is_err());
+    assert!(BasisTranslation::new(&["u3", "toffoli"]).is_err());
+    assert!(BasisTranslation::new(&["u3", "mcx", "cnot"]).is_err());
+    assert!(BasisTranslation::new(&["rz", "sx", "cx"]).is_ok());
+}
+
+#[test]
+fn backends_take_translated_programs() {
+    let program = parse(EVERY_GATE).unwrap();
+    let symbols = type_check(&program).unwrap();
+    assert!(qasm::emit(&program, &symbols).is_err());
+    assert!(qir::emit(&program, &symbols).is_err());
+
+    let mut translated = program.clone();
+    BasisTranslation::new(qasm::GATES)
+        .unwrap()
+        .run(&mut translated, &symbols)
+        .unwrap();
+    let source = qasm::emit(&translated, &symbols).unwrap();
+    assert!(source.starts_with("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[3];\n"));
+    assert!(source.contains("ccx q[0],q[1],q[2];"));
+
+    let mut translated = program;
+    BasisTranslation::new(qir::GATES)
+        .unwrap()
+        .run(&mut translated, &symbols)
+        .unwrap();
+    let source = qir::emit(&translated, &symbols).unwrap();
+    assert!(source.contains("define void @main() #0 {"));
+    assert!(source.contains("\"required_num_qubits\"=\"3\""));
+}
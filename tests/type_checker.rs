@@ -0,0 +1,152 @@
+//! Checks the errors the type checker gives for programs that parse, but
+//! misuse their gates or variables
+use quill::parser::parse;
+use quill::type_checker::type_check;
+
+/// Type checks the statements after declaring three qubits q[0..2] and three
+/// bits c[0..2], returning the error if there is one
+fn check(stmts: &str) -> Result<(), String> {
+    let source = format!(
+        "Maistow create oo qreg q with value 0[3]\nCanstow create oo creg c with value b0[3]\n{}",
+        stmts
+    );
+    type_check(&parse(&source).unwrap()).map(|_| ())
+}
+
+#[test]
+fn gates_need_the_right_number_of_parameters() {
+    assert_eq!(
+        check("Thy rz shalt target q[0]\n"),
+        Err("3: rz takes 1 parameter(s), but 0 were given!".to_string())
+    );
+    assert_eq!(
+        check("Thy u3 shalt target q[0] with [0.1, 0.2]\n"),
+        Err("3: u3 takes 3 parameter(s), but 2 were given!".to_string())
+    );
+    assert!(check("Thy u3 shalt target q[0] with [0.1, 0.2, PI]\n").is_ok());
+}
+
+#[test]
+fn gates_need_the_right_number_of_qubits() {
+    assert_eq!(
+        check("Thy swap shalt target q[0]\n"),
+        Err("3: swap acts on 2 qubit(s), but 1 were given!".to_string())
+    );
+    assert_eq!(
+        check("Thy rzz shalt target q[0] with [0.5]\n"),
+        Err("3: rzz acts on 2 qubit(s), but 1 were given!".to_string())
+    );
+}
+
+#[test]
+fn only_two_qubit_gates_take_two_targets() {
+    assert!(check("Thy swap shalt target q[0] and q[2]\n").is_ok());
+    assert_eq!(
+        check("Thy h shalt target q[0] and q[1]\n"),
+        Err("3: h acts on 1 qubit(s), but 2 were given!".to_string())
+    );
+    assert_eq!(
+        check("Thy rz shalt target q[0] and q[1] with [0.5]\n"),
+        Err("3: rz acts on 1 qubit(s), but 2 were given!".to_string())
+    );
+    assert_eq!(
+        check("Thy swap shalt target q[0] and q[1] and q[2]\n"),
+        Err("3: swap acts on 2 qubit(s), but 3 were given!".to_string())
+    );
+    assert_eq!(
+        check("Thy swap shalt target q[0] and q[0]\n"),
+        Err(
+            "3: A gate cannot act on the same qubit twice, but there was a duplicate amongst: [(\"q\", 0), (\"q\", 0)]"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn gates_need_the_right_number_of_controls() {
+    assert_eq!(
+        check("Thy h shalt target q[0] and control on q[1]\n"),
+        Err("3: h does not take any control qubits!".to_string())
+    );
+    assert_eq!(
+        check("Thy cnot shalt target q[0]\n"),
+        Err("3: cnot needs exactly 1 control qubit(s), but 0 were given!".to_string())
+    );
+    assert_eq!(
+        check("Thy toffoli shalt target q[0] and control on q[1]\n"),
+        Err("3: toffoli needs exactly 2 control qubit(s), but 1 were given!".to_string())
+    );
+    assert_eq!(
+        check("Thy mcx shalt target q[0]\n"),
+        Err("3: mcx needs at least 1 control qubit(s), but 0 were given!".to_string())
+    );
+    // Aliases are checked against the gate they stand for
+    assert!(check("Thy cx shalt target q[0] and control on q[1]\n").is_ok());
+    assert_eq!(
+        check("Thy ccx shalt target q[0] and control on q[1]\n"),
+        Err("3: toffoli needs exactly 2 control qubit(s), but 1 were given!".to_string())
+    );
+}
+
+#[test]
+fn measurements_need_matching_widths() {
+    assert!(check("Rede q and quyken c\n").is_ok());
+    assert!(check("Rede q[1..2] and quyken c[0..1]\n").is_ok());
+    assert_eq!(
+        check("Rede q and quyken c[0..1]\n"),
+        Err(
+            "3: Cannot measure 3 qubit(s) of \"q\" into 2 cbit(s) of \"c\", the widths must match!"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        check("Rede q[0] and quyken c\n"),
+        Err(
+            "3: Cannot measure 1 qubit(s) of \"q\" into 3 cbit(s) of \"c\", the widths must match!"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn measurements_go_from_qubits_into_bits() {
+    assert_eq!(
+        check("Canstow create oo qreg r with value 0[1]\nRede q[0] and quyken r\n"),
+        Err("4: [CBit, CReg] expected for \"r\", QReg given!".to_string())
+    );
+    assert_eq!(
+        check("Rede c and quyken c\n"),
+        Err("3: [Qubit, QReg] expected for \"c\", CReg given!".to_string())
+    );
+}
+
+#[test]
+fn slices_stay_in_bounds() {
+    assert_eq!(
+        check("Rede q[2..1] and quyken c[0..1]\n"),
+        Err(
+            "3: The range q[2..1] is backwards, the start index must not exceed the end!"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        check("Rede q[1..3] and quyken c[0..2]\n"),
+        Err("3: Index 3 is out of bounds for \"q\", which only has 3 elements!".to_string())
+    );
+    assert_eq!(
+        check("Rede q[0] and quyken c[5]\n"),
+        Err("3: Index 5 is out of bounds for \"c\", which only has 3 elements!".to_string())
+    );
+    assert_eq!(
+        check("Thy x shalt target q[3]\n"),
+        Err("3: Index 3 is out of bounds for \"q\", which only has 3 elements!".to_string())
+    );
+}
+
+#[test]
+fn registers_cant_be_empty() {
+    assert_eq!(
+        check("Canstow create oo qreg r with value 0[0]\n"),
+        Err("3: Register lengths must be positive integers, found 0 instead!".to_string())
+    );
+}